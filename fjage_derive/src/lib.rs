@@ -0,0 +1,196 @@
+//! `#[derive(FjageMessage)]` for fjage_rs. Use it through `fjage_rs::core::typed::FjageMessage`.
+// Explicit returns, like the rest of fjage_rs
+#![allow(clippy::needless_return)]
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    parse_macro_input, Data, DeriveInput, Error, Fields, GenericArgument, LitStr, PathArguments,
+    Type,
+};
+
+/// Element types of the arrays fjåge sends base64-encoded. Must match the `JavaArray` impls in fjage_rs.
+const JAVA_ARRAY_TYPES: &[&str] = &["u8", "i16", "i32", "i64", "f32", "f64", "bool"];
+
+/// Generate `FjageMessage` for a struct with named fields.
+///
+/// The struct takes `#[fjage(clazz = "...")]`. A field marked `#[fjage(header)]` of type `MessageHeader`
+/// carries the standard fields; without one, each message gets a fresh header with the REQUEST performative.
+/// Every other field becomes a message field of the same name: `Option` fields are left out when `None`, and
+/// `Vec`s of primitive numbers are base64-encoded the way the Java gateway expects.
+#[proc_macro_derive(FjageMessage, attributes(fjage))]
+pub fn derive_fjage_message(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    return match expand(input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    };
+}
+
+/// How a field is written to and read from the message
+enum FieldKind {
+    Plain,
+    Optional,
+    Array,
+    OptionalArray,
+}
+
+fn expand(input: DeriveInput) -> Result<TokenStream2, Error> {
+    let name = &input.ident;
+    let mut clazz: Option<LitStr> = None;
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("fjage")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("clazz") {
+                clazz = Some(meta.value()?.parse()?);
+                return Ok(());
+            }
+            return Err(meta.error("expected `clazz`"));
+        })?;
+    }
+    let Some(clazz) = clazz else {
+        return Err(Error::new_spanned(
+            name,
+            "FjageMessage needs #[fjage(clazz = \"...\")]",
+        ));
+    };
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new_spanned(
+                    name,
+                    "FjageMessage needs a struct with named fields",
+                ))
+            }
+        },
+        _ => return Err(Error::new_spanned(name, "FjageMessage needs a struct")),
+    };
+
+    let mut header = None;
+    let mut encode = Vec::new();
+    let mut decode = Vec::new();
+    for field in fields.iter() {
+        let ident = field.ident.as_ref().unwrap();
+        let mut is_header = false;
+        for attr in field.attrs.iter().filter(|a| a.path().is_ident("fjage")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("header") {
+                    is_header = true;
+                    return Ok(());
+                }
+                return Err(meta.error("expected `header`"));
+            })?;
+        }
+        if is_header {
+            if header.is_some() {
+                return Err(Error::new_spanned(
+                    ident,
+                    "only one field can be the header",
+                ));
+            }
+            header = Some(ident.clone());
+            continue;
+        }
+        let key = ident.to_string();
+        let ty = &field.ty;
+        let (enc, dec) = match field_kind(ty) {
+            FieldKind::Plain => (
+                quote! { Some(__private::encode(&self.#ident)) },
+                quote! { __private::decode(#key, fields.remove(#key)) },
+            ),
+            FieldKind::Optional => (
+                quote! { self.#ident.as_ref().map(|v| __private::encode(v)) },
+                quote! { __private::decode(#key, fields.remove(#key)) },
+            ),
+            FieldKind::Array => (
+                quote! { Some(__private::encode_array(&self.#ident)) },
+                quote! { __private::decode_array(#key, fields.remove(#key)) },
+            ),
+            FieldKind::OptionalArray => (
+                quote! { self.#ident.as_ref().map(|v| __private::encode_array(v)) },
+                quote! { __private::decode_optional_array(#key, fields.remove(#key)) },
+            ),
+        };
+        encode.push(quote! {
+            if let Some(value) = #enc {
+                msg.data.fields.insert(#key.to_string(), value);
+            }
+        });
+        decode.push(quote! { #ident: #dec? });
+    }
+
+    let (set_header, get_header) = match &header {
+        Some(header) => (
+            quote! { msg.set_header(&self.#header); },
+            quote! { #header: header, },
+        ),
+        None => (quote! {}, quote! {}),
+    };
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    return Ok(quote! {
+        impl #impl_generics ::fjage_rs::core::typed::FjageMessage for #name #ty_generics #where_clause {
+            const CLAZZ: &'static str = #clazz;
+            fn to_msg(&self) -> ::fjage_rs::core::message::Message {
+                use ::fjage_rs::core::typed::__private;
+                let mut msg = ::fjage_rs::core::message::Message::new();
+                msg.clazz = #clazz.to_string();
+                #set_header
+                #(#encode)*
+                return msg;
+            }
+            fn from_msg(
+                msg: ::fjage_rs::core::message::Message,
+            ) -> ::std::result::Result<Self, ::fjage_rs::core::error::DecodeError> {
+                use ::fjage_rs::core::typed::__private;
+                #[allow(unused_variables)]
+                let header = msg.header();
+                #[allow(unused_mut, unused_variables)]
+                let mut fields = msg.data.fields;
+                return Ok(#name {
+                    #get_header
+                    #(#decode,)*
+                });
+            }
+        }
+    });
+}
+
+fn field_kind(ty: &Type) -> FieldKind {
+    if let Some(inner) = generic_arg(ty, "Option") {
+        if is_java_array(inner) {
+            return FieldKind::OptionalArray;
+        }
+        return FieldKind::Optional;
+    }
+    if is_java_array(ty) {
+        return FieldKind::Array;
+    }
+    return FieldKind::Plain;
+}
+
+/// Whether `ty` is a `Vec` of one of the [JAVA_ARRAY_TYPES]
+fn is_java_array(ty: &Type) -> bool {
+    let Some(Type::Path(elem)) = generic_arg(ty, "Vec") else {
+        return false;
+    };
+    return elem.qself.is_none() && JAVA_ARRAY_TYPES.iter().any(|prim| elem.path.is_ident(prim));
+}
+
+/// The type argument of `ty` if it is `wrapper<T>`
+fn generic_arg<'a>(ty: &'a Type, wrapper: &str) -> Option<&'a Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    if segment.ident != wrapper {
+        return None;
+    }
+    let PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+    return match args.args.first()? {
+        GenericArgument::Type(inner) => Some(inner),
+        _ => None,
+    };
+}
@@ -0,0 +1,82 @@
+//! Migration shim for code written against the infallible [Gateway] API.
+//!
+//! Before [GatewayError] was introduced, most [Gateway] methods panicked on failure, or returned `None` or an
+//! empty value. [LegacyGateway] restores those signatures on top of the fallible API, so existing callers can move
+//! to `gw.legacy().agents()` and then migrate one call at a time.
+use std::time::Duration;
+
+use crate::core::{aid::AgentID, error::GatewayError, message::Message};
+
+use super::gateway::Gateway;
+
+pub struct LegacyGateway<'a> {
+    gw: &'a mut Gateway,
+}
+impl<'a> LegacyGateway<'a> {
+    pub(crate) fn new(gw: &'a mut Gateway) -> LegacyGateway<'a> {
+        return LegacyGateway { gw: gw };
+    }
+    pub fn subscribe(&mut self, aid: &AgentID) {
+        self.gw.subscribe(aid).unwrap();
+    }
+    pub fn unsubscribe(&mut self, aid: &AgentID) {
+        self.gw.unsubscribe(aid).unwrap();
+    }
+    pub fn subscribe_agent(&mut self, aid: &AgentID) {
+        self.gw.subscribe_agent(aid).unwrap();
+    }
+    pub fn unsubscribe_agent(&mut self, aid: &AgentID) {
+        self.gw.unsubscribe_agent(aid).unwrap();
+    }
+    pub fn agents(&mut self) -> Vec<AgentID> {
+        return self.gw.agents().unwrap();
+    }
+    pub fn services(&mut self) -> Vec<String> {
+        return self.gw.services().unwrap();
+    }
+    pub fn contains_agent(&mut self, aid: &AgentID) -> bool {
+        return self.gw.contains_agent(aid).unwrap();
+    }
+    pub fn agent_for_service(&mut self, service: &str) -> Option<AgentID> {
+        return match self.gw.agent_for_service(service) {
+            Ok(aid) => Some(aid),
+            Err(GatewayError::AgentNotFound(_)) => None,
+            Err(e) => panic!("{}", e),
+        };
+    }
+    pub fn agents_for_service(&mut self, service: &str) -> Vec<AgentID> {
+        return self.gw.agents_for_service(service).unwrap();
+    }
+    pub fn send(&mut self, to: &AgentID, msg: Message) {
+        self.gw.send(to, msg).unwrap();
+    }
+    /// Replies with a REFUSE or FAILURE performative are returned as before, rather than as errors.
+    pub fn request(&mut self, to: &AgentID, msg: Message) -> Option<Message> {
+        return LegacyGateway::reply_or_none(self.gw.request(to, msg));
+    }
+    pub fn request_timeout(
+        &mut self,
+        to: &AgentID,
+        msg: Message,
+        timeout: Duration,
+    ) -> Option<Message> {
+        return LegacyGateway::reply_or_none(self.gw.request_timeout(to, msg, timeout));
+    }
+    pub fn recv(&mut self, clazzes: Option<Vec<String>>, id: Option<String>) -> Option<Message> {
+        return self.gw.recv(clazzes, id).ok();
+    }
+    pub fn recv_timeout(
+        &mut self,
+        clazzes: Option<Vec<String>>,
+        id: Option<String>,
+        timeout: Duration,
+    ) -> Option<Message> {
+        return self.gw.recv_timeout(clazzes, id, timeout).ok();
+    }
+    fn reply_or_none(rsp: Result<Message, GatewayError>) -> Option<Message> {
+        return match rsp {
+            Ok(msg) => Some(msg),
+            Err(e) => e.into_reply(),
+        };
+    }
+}
@@ -1,147 +1,455 @@
-use std::{sync::Arc, time::Duration};
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex, OnceLock},
+    thread,
+    time::Duration,
+};
 
+#[cfg(unix)]
+use crate::protocol::connector::UnixSocketConnector;
 use crate::{
     core::{
+        aid::AgentID,
+        error::GatewayError,
+        filter::MessageFilter,
         message::Message,
         param::{ParameterManipulation, ParameterReq, ParameterRsp},
+        registry::AnyMessage,
+        typed::FjageMessage,
+    },
+    protocol::connector::{Connector, SerialPortConnector, TcpConnector, WebSocketConnector},
+    remote::{
+        agent::Agent,
+        container::*,
+        queue::{QueueLimits, QueueStats},
     },
-    remote::container::*,
 };
-use tokio::runtime::Runtime;
+use futures_util::StreamExt;
+use tokio::{
+    runtime::{Handle, Runtime, RuntimeFlavor},
+    sync::oneshot,
+};
+
+use super::compat::LegacyGateway;
+
+/// Identifies a listener added with [Gateway::add_message_listener]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ListenerId(usize);
 
+/// Where a [Gateway] runs the tasks that drive its connection
+#[derive(Clone, Debug)]
+pub enum GatewayRuntime {
+    /// A runtime of the gateway's own, shut down with the last clone of the gateway. This is what `Gateway::new_tcp` and friends use.
+    Dedicated,
+    /// A background runtime shared by every gateway opened with this option
+    Shared,
+    /// An existing runtime, such as the one an async `main` runs on. A current-thread runtime must be driven by a
+    /// thread other than the ones calling the gateway, or the calls will never complete.
+    Handle(Handle),
+}
+
+/// A runtime owned by a gateway and its clones
+struct OwnedRuntime(Option<Runtime>);
+impl Drop for OwnedRuntime {
+    fn drop(&mut self) {
+        let Some(runtime) = self.0.take() else {
+            return;
+        };
+        // Waiting for the workers to stop is not allowed inside an async context
+        if Handle::try_current().is_ok() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+/// Stop signals for the worker threads of the message listeners
+#[derive(Default)]
+struct Listeners {
+    stops: HashMap<ListenerId, oneshot::Sender<()>>,
+    next: usize,
+}
+impl Drop for Listeners {
+    fn drop(&mut self) {
+        for (_, stop) in self.stops.drain() {
+            let _ = stop.send(());
+        }
+    }
+}
+
+/// A blocking interface to a [RemoteContainer]. Clones share the connection, and may be used from several
+/// threads at once, and from inside async code.
+#[derive(Clone)]
 pub struct Gateway {
     container: RemoteContainer,
-    runtime: Arc<Box<Runtime>>,
+    handle: Handle,
+    /// Keeps a [GatewayRuntime::Dedicated] runtime alive as long as the gateway
+    _runtime: Option<Arc<OwnedRuntime>>,
+    listeners: Arc<Mutex<Listeners>>,
 }
 impl Gateway {
-    pub fn new_tcp(hostname: &str, port: u16) -> Gateway {
-        let runtime = Box::new(Runtime::new().unwrap());
-        let gw = runtime.block_on(RemoteContainer::new_tcp(hostname, port));
-        return Gateway {
-            container: gw,
-            runtime: Arc::new(runtime),
+    pub fn new_tcp(hostname: &str, port: u16) -> Result<Gateway, GatewayError> {
+        return Gateway::new(TcpConnector::new(hostname, port));
+    }
+    pub fn new_serial(dev: &str, baud: u32) -> Result<Gateway, GatewayError> {
+        return Gateway::new(SerialPortConnector::new(dev, baud));
+    }
+    pub fn new_ws(hostname: &str, port: u16) -> Result<Gateway, GatewayError> {
+        return Gateway::new(WebSocketConnector::new(hostname, port));
+    }
+    #[cfg(unix)]
+    pub fn new_unix<P: AsRef<std::path::Path>>(path: P) -> Result<Gateway, GatewayError> {
+        return Gateway::new(UnixSocketConnector::new(path));
+    }
+    /// Open a gateway over any connector, reconnecting with the default [ReconnectPolicy] if the link drops.
+    pub fn new<T: Connector + Send + Sync + 'static>(
+        connector: T,
+    ) -> Result<Gateway, GatewayError> {
+        return Gateway::new_with_policy(connector, ReconnectPolicy::default());
+    }
+    /// Open a gateway over any connector, re-establishing the link according to `policy` if it drops.
+    pub fn new_with_policy<T: Connector + Send + Sync + 'static>(
+        connector: T,
+        policy: ReconnectPolicy,
+    ) -> Result<Gateway, GatewayError> {
+        return Gateway::new_in(connector, policy, GatewayRuntime::Dedicated);
+    }
+    /// Open a gateway whose connection is driven by `runtime`.
+    /// Gateways on a shared or existing runtime keep their connection open until [Gateway::close] is called.
+    pub fn new_in<T: Connector + Send + Sync + 'static>(
+        connector: T,
+        policy: ReconnectPolicy,
+        runtime: GatewayRuntime,
+    ) -> Result<Gateway, GatewayError> {
+        let (handle, owned) = match runtime {
+            GatewayRuntime::Dedicated => {
+                let runtime = Runtime::new().map_err(GatewayError::Connect)?;
+                let handle = runtime.handle().clone();
+                (handle, Some(Arc::new(OwnedRuntime(Some(runtime)))))
+            }
+            GatewayRuntime::Shared => (Gateway::shared_runtime()?, None),
+            GatewayRuntime::Handle(handle) => (handle, None),
         };
+        let container =
+            Gateway::block_on_handle(&handle, RemoteContainer::new_with_policy(connector, policy))?;
+        return Ok(Gateway::attach(container, handle, owned));
     }
-    pub fn new_serial(dev: &str, baud: u32) -> Gateway {
-        let runtime = Box::new(Runtime::new().unwrap());
-        let gw = runtime.block_on(RemoteContainer::new_serial(dev, baud));
+    /// Wrap a container already opened on the runtime behind `handle`
+    pub fn from_container(container: RemoteContainer, handle: Handle) -> Gateway {
+        return Gateway::attach(container, handle, None);
+    }
+    fn attach(
+        container: RemoteContainer,
+        handle: Handle,
+        runtime: Option<Arc<OwnedRuntime>>,
+    ) -> Gateway {
         return Gateway {
-            container: gw,
-            runtime: Arc::new(runtime),
+            container: container,
+            handle: handle,
+            _runtime: runtime,
+            listeners: Arc::new(Mutex::new(Listeners::default())),
         };
     }
-    pub fn is_subscribed(&self, aid: &str) -> bool {
-        return self.runtime.block_on(self.container.is_subscribed(aid));
+    /// The background runtime behind [GatewayRuntime::Shared], started on first use
+    pub fn shared_runtime() -> Result<Handle, GatewayError> {
+        static SHARED: OnceLock<Runtime> = OnceLock::new();
+        if let Some(shared) = SHARED.get() {
+            return Ok(shared.handle().clone());
+        }
+        let mut started = Some(Runtime::new().map_err(GatewayError::Connect)?);
+        let shared = SHARED.get_or_init(|| started.take().unwrap());
+        // Another thread got there first. Dropping a runtime blocks, which is not allowed in async code.
+        if let Some(spare) = started {
+            spare.shutdown_background();
+        }
+        return Ok(shared.handle().clone());
     }
-    pub fn subscribe(&mut self, aid: &str) {
-        return self.runtime.block_on(self.container.subscribe(aid));
+    /// Run `fut` on the runtime behind `handle`, blocking until it completes
+    fn block_on_handle<F: Future + Send>(handle: &Handle, fut: F) -> F::Output
+    where
+        F::Output: Send,
+    {
+        let Ok(current) = Handle::try_current() else {
+            return handle.block_on(fut);
+        };
+        // Handle::block_on panics inside an async context. A multi-threaded runtime lets us block in place,
+        // anywhere else we wait for a thread of our own to do it.
+        if current.runtime_flavor() == RuntimeFlavor::MultiThread {
+            return tokio::task::block_in_place(|| handle.block_on(fut));
+        }
+        return thread::scope(|scope| scope.spawn(|| handle.block_on(fut)).join().unwrap());
     }
-    pub fn unsubscribe(&mut self, aid: &str) {
-        return self.runtime.block_on(self.container.unsubscribe(aid));
+    fn block_on<F: Future + Send>(&self, fut: F) -> F::Output
+    where
+        F::Output: Send,
+    {
+        return Gateway::block_on_handle(&self.handle, fut);
+    }
+    /// A handle on the shared container, for the `&mut self` methods of [RemoteContainer]
+    fn container(&self) -> RemoteContainer {
+        return self.container.clone();
+    }
+    /// Access the gateway through the infallible signatures it had before [GatewayError] was introduced.
+    #[deprecated(note = "handle the GatewayError returned by the Gateway methods instead")]
+    pub fn legacy(&mut self) -> LegacyGateway<'_> {
+        return LegacyGateway::new(self);
+    }
+    pub fn link_state(&self) -> LinkState {
+        return self.container.link_state();
+    }
+    pub fn is_connected(&self) -> bool {
+        return self.container.is_connected();
+    }
+    pub fn is_subscribed(&self, aid: &AgentID) -> bool {
+        return self.block_on(self.container().is_subscribed(aid));
+    }
+    pub fn subscribe(&self, aid: &AgentID) -> Result<(), GatewayError> {
+        return self.block_on(self.container().subscribe(aid));
+    }
+    pub fn unsubscribe(&self, aid: &AgentID) -> Result<(), GatewayError> {
+        return self.block_on(self.container().unsubscribe(aid));
     }
     // Move to a separate trait related to local agent representation?
-    pub fn add_agent(&mut self, aid: &str) {
-        return self.runtime.block_on(self.container.add_agent(aid));
+    pub fn add_agent(&self, aid: &AgentID) {
+        return self.block_on(self.container().add_agent(aid));
+    }
+    /// Host an agent in the gateway's container. See [RemoteContainer::add].
+    pub fn add<A: Agent>(&self, name: &str, agent: A) -> Result<(), GatewayError> {
+        return self.block_on(self.container().add(name, agent));
+    }
+    pub fn remove_agent(&self, aid: &AgentID) -> Result<(), GatewayError> {
+        return self.block_on(self.container().remove_agent(aid));
     }
-    pub fn subscribe_agent(&mut self, aid: &str) {
-        return self.runtime.block_on(self.container.subscribe_agent(aid));
+    pub fn subscribe_agent(&self, aid: &AgentID) -> Result<(), GatewayError> {
+        return self.block_on(self.container().subscribe_agent(aid));
     }
-    pub fn unsubscribe_agent(&mut self, aid: &str) {
-        return self.runtime.block_on(self.container.unsubscribe_agent(aid));
+    pub fn unsubscribe_agent(&self, aid: &AgentID) -> Result<(), GatewayError> {
+        return self.block_on(self.container().unsubscribe_agent(aid));
     }
-    pub fn get_agent_id(&self) -> String {
+    pub fn get_agent_id(&self) -> AgentID {
         return self.container.get_agent_id();
     }
     /// Get list of agents running in fjåge
-    pub fn agents(&mut self) -> Vec<String> {
-        return self.runtime.block_on(self.container.agents());
+    pub fn agents(&self) -> Result<Vec<AgentID>, GatewayError> {
+        return self.block_on(self.container().agents());
     }
     /// Get list of services running in fjåge
-    pub fn services(&mut self) -> Vec<String> {
-        return self.runtime.block_on(self.container.services());
+    pub fn services(&self) -> Result<Vec<String>, GatewayError> {
+        return self.block_on(self.container().services());
     }
     /// Ask if upstream container contains a specific agent
-    pub fn contains_agent(&mut self, aid: &str) -> bool {
-        return self.runtime.block_on(self.container.contains_agent(aid));
+    pub fn contains_agent(&self, aid: &AgentID) -> Result<bool, GatewayError> {
+        return self.block_on(self.container().contains_agent(aid));
     }
     /// Find an agent which advertises the requested service
-    pub fn agent_for_service(&mut self, service: &str) -> Option<String> {
-        return self
-            .runtime
-            .block_on(self.container.agent_for_service(service));
+    pub fn agent_for_service(&self, service: &str) -> Result<AgentID, GatewayError> {
+        return self.block_on(self.container().agent_for_service(service));
     }
     /// Find all agents which advertise the requested service
-    pub fn agents_for_service(&mut self, service: &str) -> Vec<String> {
-        return self
-            .runtime
-            .block_on(self.container.agents_for_service(service));
+    pub fn agents_for_service(&self, service: &str) -> Result<Vec<AgentID>, GatewayError> {
+        return self.block_on(self.container().agents_for_service(service));
     }
 
-    /// Send a message to the specified agent or topic. If "sender" is empty, it will be filled with the AgentID of the Gateway
-    pub fn send(&mut self, to: &str, msg: Message) {
-        return self.container.send(to, msg);
+    /// Authenticate the connection with a master that requires credentials. Returns whether the master accepted them.
+    pub fn authenticate(&self, creds: &str) -> Result<bool, GatewayError> {
+        return self.block_on(self.container().authenticate(creds));
+    }
+    /// Like [Gateway::agents], but gives up with [GatewayError::Timeout] after `timeout`.
+    pub fn agents_timeout(&self, timeout: Duration) -> Result<Vec<AgentID>, GatewayError> {
+        return self.block_on(self.container().agents_timeout(timeout));
+    }
+    pub fn services_timeout(&self, timeout: Duration) -> Result<Vec<String>, GatewayError> {
+        return self.block_on(self.container().services_timeout(timeout));
+    }
+    pub fn contains_agent_timeout(
+        &self,
+        aid: &AgentID,
+        timeout: Duration,
+    ) -> Result<bool, GatewayError> {
+        return self.block_on(self.container().contains_agent_timeout(aid, timeout));
+    }
+    pub fn agent_for_service_timeout(
+        &self,
+        service: &str,
+        timeout: Duration,
+    ) -> Result<AgentID, GatewayError> {
+        return self.block_on(self.container().agent_for_service_timeout(service, timeout));
+    }
+    pub fn agents_for_service_timeout(
+        &self,
+        service: &str,
+        timeout: Duration,
+    ) -> Result<Vec<AgentID>, GatewayError> {
+        return self.block_on(
+            self.container()
+                .agents_for_service_timeout(service, timeout),
+        );
+    }
+    pub fn authenticate_timeout(
+        &self,
+        creds: &str,
+        timeout: Duration,
+    ) -> Result<bool, GatewayError> {
+        return self.block_on(self.container().authenticate_timeout(creds, timeout));
+    }
+
+    /// Send a message to the specified agent or topic. If "sender" is empty, it will be filled with the AgentID of the Gateway.
+    /// Blocks while the link to the master is too backed up to take it, see [RemoteContainer::send_wait].
+    pub fn send(&self, to: &AgentID, msg: Message) -> Result<(), GatewayError> {
+        return self.block_on(self.container().send_wait(to, msg));
+    }
+    /// Send a message, choosing whether the master should relay it to other containers connected to it.
+    pub fn send_relay(&self, to: &AgentID, msg: Message, relay: bool) -> Result<(), GatewayError> {
+        return self.block_on(self.container().send_relay_wait(to, msg, relay));
     }
     /// Send a message to the specified agent, then waits for a message with an inReplyTo marker matching the sent message's UUID.
-    pub fn request(&mut self, to: &str, msg: Message) -> Option<Message> {
-        return self.runtime.block_on(self.container.request(to, msg));
+    pub fn request(&self, to: &AgentID, msg: Message) -> Result<Message, GatewayError> {
+        return self.block_on(self.container().request(to, msg));
     }
     pub fn request_timeout(
-        &mut self,
-        to: &str,
+        &self,
+        to: &AgentID,
         msg: Message,
         timeout: Duration,
-    ) -> Option<Message> {
-        return self
-            .runtime
-            .block_on(async {
-                tokio::time::timeout(timeout, self.container.request(to, msg)).await
-            })
-            .unwrap_or(None);
+    ) -> Result<Message, GatewayError> {
+        return self.block_on(self.container().request_timeout(to, msg, timeout));
     }
     /// Receive a message. If clazzes, id, or both are specified, then only messages matching those parameters will be returned.
-    pub fn recv(&mut self, clazzes: Option<Vec<String>>, id: Option<String>) -> Option<Message> {
-        return self.runtime.block_on(self.container.recv(clazzes, id));
+    pub fn recv(
+        &self,
+        clazzes: Option<Vec<String>>,
+        id: Option<String>,
+    ) -> Result<Message, GatewayError> {
+        return self.block_on(self.container().recv(clazzes, id));
     }
     pub fn recv_timeout(
-        &mut self,
+        &self,
         clazzes: Option<Vec<String>>,
         id: Option<String>,
         timeout: Duration,
-    ) -> Option<Message> {
-        return self
-            .runtime
-            .block_on(async {
-                tokio::time::timeout(timeout, self.container.recv(clazzes, id)).await
-            })
-            .unwrap_or(None);
+    ) -> Result<Message, GatewayError> {
+        return self.block_on(self.container().recv_timeout(clazzes, id, timeout));
+    }
+    /// Receive the oldest queued message accepted by `filter`, waiting for one to arrive if there is none yet.
+    pub fn recv_filtered<F: MessageFilter>(&self, filter: F) -> Result<Message, GatewayError> {
+        return self.block_on(self.container().recv_filtered(filter));
+    }
+    pub fn recv_timeout_filtered<F: MessageFilter>(
+        &self,
+        filter: F,
+        timeout: Duration,
+    ) -> Result<Message, GatewayError> {
+        return self.block_on(self.container().recv_timeout_filtered(filter, timeout));
+    }
+    /// Change how many messages wait for [Gateway::recv], and for how long. See [RemoteContainer::set_queue_limits].
+    pub fn set_queue_limits(&self, limits: QueueLimits) {
+        self.container.set_queue_limits(limits);
+    }
+    pub fn queue_limits(&self) -> QueueLimits {
+        return self.container.queue_limits();
+    }
+    pub fn queue_stats(&self) -> QueueStats {
+        return self.container.queue_stats();
+    }
+    /// Change how many messages wait for each message listener and hosted agent. See [RemoteContainer::set_stream_limits].
+    pub fn set_stream_limits(&self, limits: QueueLimits) {
+        self.container.set_stream_limits(limits);
+    }
+    pub fn stream_limits(&self) -> QueueLimits {
+        return self.container.stream_limits();
+    }
+    pub fn stream_stats(&self) -> QueueStats {
+        return self.container.stream_stats();
+    }
+    /// Decode received messages of clazz `T::CLAZZ` as `T` in [Gateway::recv_typed]
+    pub fn register<T: FjageMessage + Send + std::fmt::Debug + 'static>(&self) {
+        self.container.register::<T>();
+    }
+    /// Like [Gateway::recv_filtered], but decodes the message into its registered type. See [RemoteContainer::recv_typed].
+    pub fn recv_typed<F: MessageFilter>(&self, filter: F) -> Result<AnyMessage, GatewayError> {
+        return self.block_on(self.container().recv_typed(filter));
+    }
+    pub fn recv_timeout_typed<F: MessageFilter>(
+        &self,
+        filter: F,
+        timeout: Duration,
+    ) -> Result<AnyMessage, GatewayError> {
+        return self.block_on(self.container().recv_timeout_typed(filter, timeout));
     }
     /// Interrupt an ongoing reception
-    pub fn interrupt(&mut self) {
-        self.container.interrupt();
+    pub fn interrupt(&self) {
+        self.container().interrupt();
+    }
+    /// Stop the message listeners and close the connection, for this gateway and all its clones. See [RemoteContainer::close].
+    pub fn close(&self) {
+        for (_, stop) in self.listeners.lock().unwrap().stops.drain() {
+            let _ = stop.send(());
+        }
+        self.block_on(self.container.close());
+    }
+    /// Call `callback` with every incoming message accepted by `filter`, like a MessageListener in the Java
+    /// gateway. Each listener runs on its own worker thread, so a slow callback only delays its own messages.
+    /// Listeners get a copy of each message; it is still returned by [Gateway::recv] as well.
+    /// Messages waiting for a busy callback are limited by [Gateway::set_stream_limits].
+    pub fn add_message_listener<F, C>(&self, filter: F, mut callback: C) -> ListenerId
+    where
+        F: MessageFilter + 'static,
+        C: FnMut(Message) + Send + 'static,
+    {
+        let (stop_tx, mut stop_rx) = oneshot::channel::<()>();
+        let messages = self.container.messages(filter);
+        let runtime = self.handle.clone();
+        thread::spawn(move || {
+            runtime.block_on(async move {
+                tokio::pin!(messages);
+                loop {
+                    // Check for a stop first, so that nothing is delivered once the listener is removed
+                    let msg = tokio::select! {
+                        biased;
+                        _ = &mut stop_rx => None,
+                        msg = messages.next() => msg,
+                    };
+                    let Some(msg) = msg else {
+                        return;
+                    };
+                    callback(msg);
+                }
+            });
+        });
+        let mut listeners = self.listeners.lock().unwrap();
+        let id = ListenerId(listeners.next);
+        listeners.next += 1;
+        listeners.stops.insert(id, stop_tx);
+        return id;
+    }
+    /// Stop a listener added with [Gateway::add_message_listener]. Returns whether it was still registered.
+    /// A callback that is already running finishes first.
+    pub fn remove_message_listener(&self, id: ListenerId) -> bool {
+        return match self.listeners.lock().unwrap().stops.remove(&id) {
+            Some(stop) => {
+                let _ = stop.send(());
+                true
+            }
+            None => false,
+        };
     }
 }
 impl ParameterManipulation for Gateway {
     /// Send a [ParameterReq](https://org-arl.github.io/fjage/javadoc/org/arl/fjage/param/ParameterReq.html) message to an agent in the upstream container and return the [ParameterRsp]((https://org-arl.github.io/fjage/javadoc/org/arl/fjage/param/ParameterRsp.html)).
-    fn param_req(&mut self, aid: &str, mut req: ParameterReq) -> Option<ParameterRsp> {
-        let rsp = self.request(aid, req.to_msg());
-        if rsp.is_none() {
-            return None;
-        }
-        let rsp = ParameterRsp::from_msg(rsp.unwrap());
+    fn param_req(&mut self, aid: &AgentID, req: ParameterReq) -> Option<ParameterRsp> {
+        let rsp = self.request(aid, req.to_msg()).ok()?;
+        let rsp = ParameterRsp::from_msg(rsp).ok()?;
         return Some(rsp);
     }
 
     fn param_req_timeout(
         &mut self,
-        aid: &str,
-        mut req: ParameterReq,
+        aid: &AgentID,
+        req: ParameterReq,
         timeout: Duration,
     ) -> Option<ParameterRsp> {
-        let rsp = self.request_timeout(aid, req.to_msg(), timeout);
-        if rsp.is_none() {
-            return None;
-        }
-        let rsp = ParameterRsp::from_msg(rsp.unwrap());
+        let rsp = self.request_timeout(aid, req.to_msg(), timeout).ok()?;
+        let rsp = ParameterRsp::from_msg(rsp).ok()?;
         return Some(rsp);
     }
 }
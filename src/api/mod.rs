@@ -1 +1,2 @@
+pub mod compat;
 pub mod gateway;
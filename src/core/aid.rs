@@ -0,0 +1,99 @@
+use std::{convert::Infallible, fmt, str::FromStr};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Identifies an agent or a topic. On the wire, topics are the topic name prefixed with `#`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct AgentID {
+    name: String,
+    topic: bool,
+}
+impl AgentID {
+    /// The AgentID of the named agent
+    pub fn agent(name: &str) -> AgentID {
+        return AgentID {
+            name: name.to_string(),
+            topic: false,
+        };
+    }
+    /// A named topic, not tied to any agent
+    pub fn topic(name: &str) -> AgentID {
+        return AgentID {
+            name: name.to_string(),
+            topic: true,
+        };
+    }
+    /// The topic an agent publishes its notifications on
+    pub fn agent_topic(agent: &AgentID) -> AgentID {
+        return AgentID::topic(&format!("{}__ntf", agent.name));
+    }
+    /// A named topic belonging to an agent
+    pub fn agent_named_topic(agent: &AgentID, topic: &str) -> AgentID {
+        return AgentID::topic(&format!("{}__{}__ntf", agent.name, topic));
+    }
+    /// Name of the agent or topic, without the leading `#` of a topic
+    pub fn name(&self) -> &str {
+        return &self.name;
+    }
+    pub fn is_topic(&self) -> bool {
+        return self.topic;
+    }
+    /// Whether this is the empty AgentID of a message that has no sender or recipient yet
+    pub fn is_empty(&self) -> bool {
+        return self.name.is_empty() && !self.topic;
+    }
+}
+impl fmt::Display for AgentID {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.topic {
+            write!(f, "#{}", self.name)
+        } else {
+            write!(f, "{}", self.name)
+        }
+    }
+}
+impl FromStr for AgentID {
+    type Err = Infallible;
+    fn from_str(s: &str) -> Result<AgentID, Infallible> {
+        return Ok(match s.strip_prefix('#') {
+            Some(topic) => AgentID::topic(topic),
+            None => AgentID::agent(s),
+        });
+    }
+}
+impl From<&str> for AgentID {
+    fn from(s: &str) -> AgentID {
+        let Ok(aid) = s.parse();
+        return aid;
+    }
+}
+impl From<String> for AgentID {
+    fn from(s: String) -> AgentID {
+        return AgentID::from(s.as_str());
+    }
+}
+impl PartialEq<str> for AgentID {
+    /// Compare with the wire form of an AgentID, where topics carry a leading `#`
+    fn eq(&self, other: &str) -> bool {
+        return match other.strip_prefix('#') {
+            Some(topic) => self.topic && self.name == topic,
+            None => !self.topic && self.name == other,
+        };
+    }
+}
+impl PartialEq<&str> for AgentID {
+    fn eq(&self, other: &&str) -> bool {
+        return *self == **other;
+    }
+}
+impl Serialize for AgentID {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        return serializer.collect_str(self);
+    }
+}
+impl<'de> Deserialize<'de> for AgentID {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<AgentID, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        return Ok(AgentID::from(s.as_str()));
+    }
+}
@@ -0,0 +1,134 @@
+use std::{error::Error, fmt, io};
+
+use crate::protocol::frame::FrameError;
+
+use super::message::{Message, Performative};
+
+/// Errors returned by [RemoteContainer](crate::remote::container::RemoteContainer) and [Gateway](crate::api::gateway::Gateway).
+#[derive(Debug)]
+pub enum GatewayError {
+    /// The connection to the master container could not be opened.
+    Connect(io::Error),
+    /// The link to the master container dropped before the operation completed, or has been given up on.
+    ConnectionLost,
+    /// No answer arrived within the allotted time.
+    Timeout,
+    /// The operation was aborted by a call to `interrupt()`, or its [CancelToken](crate::remote::cancel::CancelToken) was cancelled.
+    Interrupted,
+    /// The master container answered with a frame we could not make sense of.
+    MalformedFrame(String),
+    /// The recipient answered a request with a REFUSE performative. The reply is included.
+    Refused(Box<Message>),
+    /// The recipient answered a request with a FAILURE or NOT_UNDERSTOOD performative. The reply is included.
+    Failure(Box<Message>),
+    /// No agent matches the requested name or service.
+    AgentNotFound(String),
+    /// An agent with this name already exists in the container.
+    DuplicateAgent(String),
+    /// Too many frames are already waiting to go out to the master. Try again later, or use a call that waits for room.
+    WouldBlock,
+}
+impl GatewayError {
+    /// The reply carried by a [Refused](GatewayError::Refused) or [Failure](GatewayError::Failure) error.
+    pub fn reply(&self) -> Option<&Message> {
+        match self {
+            GatewayError::Refused(msg) | GatewayError::Failure(msg) => Some(msg),
+            _ => None,
+        }
+    }
+    /// Consume the error, returning the reply carried by a [Refused](GatewayError::Refused) or [Failure](GatewayError::Failure) error.
+    pub fn into_reply(self) -> Option<Message> {
+        match self {
+            GatewayError::Refused(msg) | GatewayError::Failure(msg) => Some(*msg),
+            _ => None,
+        }
+    }
+    /// Sort a reply into success or error according to its performative.
+    pub fn check_reply(msg: Message) -> Result<Message, GatewayError> {
+        match msg.data.perf {
+            Performative::REFUSE => Err(GatewayError::Refused(Box::new(msg))),
+            Performative::FAILURE | Performative::NOT_UNDERSTOOD => {
+                Err(GatewayError::Failure(Box::new(msg)))
+            }
+            _ => Ok(msg),
+        }
+    }
+}
+impl fmt::Display for GatewayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GatewayError::Connect(e) => write!(f, "could not connect to master container: {}", e),
+            GatewayError::ConnectionLost => write!(f, "connection to master container lost"),
+            GatewayError::Timeout => write!(f, "timed out waiting for a response"),
+            GatewayError::Interrupted => write!(f, "interrupted"),
+            GatewayError::MalformedFrame(frame) => write!(f, "malformed frame: {}", frame),
+            GatewayError::Refused(msg) => write!(f, "request refused by {}", msg.data.sender),
+            GatewayError::Failure(msg) => write!(
+                f,
+                "request failed at {} ({:?})",
+                msg.data.sender, msg.data.perf
+            ),
+            GatewayError::AgentNotFound(name) => write!(f, "no agent found for {}", name),
+            GatewayError::DuplicateAgent(name) => write!(f, "agent {} already exists", name),
+            GatewayError::WouldBlock => write!(f, "too many frames waiting to be sent"),
+        }
+    }
+}
+impl Error for GatewayError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            GatewayError::Connect(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+/// Errors converting a [Message] to a typed message with [FjageMessage::from_msg](crate::core::typed::FjageMessage::from_msg).
+#[derive(Debug)]
+pub enum DecodeError {
+    /// The message is of another clazz than the type it was decoded as.
+    WrongClazz { expected: String, found: String },
+    /// A required field is not in the message.
+    MissingField(String),
+    /// A field is in the message but does not have the expected type.
+    InvalidField(String, serde_json::Error),
+}
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::WrongClazz { expected, found } => {
+                write!(f, "expected a {} message, found {}", expected, found)
+            }
+            DecodeError::MissingField(name) => write!(f, "missing field {}", name),
+            DecodeError::InvalidField(name, e) => write!(f, "invalid field {}: {}", name, e),
+        }
+    }
+}
+impl Error for DecodeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DecodeError::InvalidField(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// A name or number that is not one of the [Performative]s.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownPerformative(pub String);
+impl fmt::Display for UnknownPerformative {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown performative {}", self.0)
+    }
+}
+impl Error for UnknownPerformative {}
+
+impl From<io::Error> for GatewayError {
+    fn from(e: io::Error) -> GatewayError {
+        return GatewayError::Connect(e);
+    }
+}
+impl From<FrameError> for GatewayError {
+    fn from(e: FrameError) -> GatewayError {
+        return GatewayError::MalformedFrame(e.to_string());
+    }
+}
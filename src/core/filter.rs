@@ -0,0 +1,91 @@
+//! Filters selecting which messages [recv_filtered](crate::remote::container::RemoteContainer::recv_filtered) returns.
+//!
+//! Any `Fn(&Message) -> bool` is a filter. The functions in this module build the common ones, and filters
+//! combine with [MessageFilter::and] and [MessageFilter::or]:
+//!
+//! ```ignore
+//! let filter = clazz("org.arl.unet.DatagramNtf").and(sender(&AgentID::agent("phy")));
+//! let ntf = gw.recv_filtered(filter)?;
+//! ```
+use serde_json::Value;
+
+use super::{
+    aid::AgentID,
+    message::{Message, Performative},
+};
+
+/// Decides whether a message is the one a caller is waiting for
+pub trait MessageFilter: Send + Sync {
+    fn matches(&self, msg: &Message) -> bool;
+
+    /// Match messages accepted by both filters
+    fn and<F: MessageFilter>(self, other: F) -> And<Self, F>
+    where
+        Self: Sized,
+    {
+        return And(self, other);
+    }
+    /// Match messages accepted by either filter
+    fn or<F: MessageFilter>(self, other: F) -> Or<Self, F>
+    where
+        Self: Sized,
+    {
+        return Or(self, other);
+    }
+}
+impl<F: Fn(&Message) -> bool + Send + Sync> MessageFilter for F {
+    fn matches(&self, msg: &Message) -> bool {
+        return self(msg);
+    }
+}
+impl MessageFilter for Box<dyn MessageFilter> {
+    fn matches(&self, msg: &Message) -> bool {
+        return self.as_ref().matches(msg);
+    }
+}
+
+/// See [MessageFilter::and]
+pub struct And<A, B>(A, B);
+impl<A: MessageFilter, B: MessageFilter> MessageFilter for And<A, B> {
+    fn matches(&self, msg: &Message) -> bool {
+        return self.0.matches(msg) && self.1.matches(msg);
+    }
+}
+
+/// See [MessageFilter::or]
+pub struct Or<A, B>(A, B);
+impl<A: MessageFilter, B: MessageFilter> MessageFilter for Or<A, B> {
+    fn matches(&self, msg: &Message) -> bool {
+        return self.0.matches(msg) || self.1.matches(msg);
+    }
+}
+
+/// Match every message
+pub fn any() -> impl MessageFilter {
+    return |_: &Message| true;
+}
+/// Match messages of the given clazz
+pub fn clazz(clazz: &str) -> impl MessageFilter {
+    let clazz = clazz.to_string();
+    return move |msg: &Message| msg.clazz == clazz;
+}
+/// Match messages from the given agent
+pub fn sender(aid: &AgentID) -> impl MessageFilter {
+    let aid = aid.clone();
+    return move |msg: &Message| msg.data.sender == aid;
+}
+/// Match messages with the given performative
+pub fn performative(perf: Performative) -> impl MessageFilter {
+    return move |msg: &Message| msg.data.perf == perf;
+}
+/// Match replies to the message with the given msgID
+pub fn in_reply_to(id: &str) -> impl MessageFilter {
+    let id = id.to_string();
+    return move |msg: &Message| msg.data.inReplyTo.as_ref() == Some(&id);
+}
+/// Match messages whose field `name` equals `value`
+pub fn field<V: Into<Value>>(name: &str, value: V) -> impl MessageFilter {
+    let name = name.to_string();
+    let value = value.into();
+    return move |msg: &Message| msg.data.fields.get(&name) == Some(&value);
+}
@@ -1,8 +1,14 @@
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
 use uuid::Uuid;
 
+use crate::core::aid::AgentID;
+use crate::core::error::{DecodeError, UnknownPerformative};
+use crate::core::signal::BasebandSignal;
+use crate::core::typed::FjageMessage;
 use crate::protocol::base64::*;
 
 #[macro_export]
@@ -10,9 +16,9 @@ macro_rules! msg_standard_fields {
     () => {
         pub msgID: String,
         pub perf: Performative,
-        pub recipient: String,
+        pub recipient: AgentID,
         pub inReplyTo: Option<String>,
-        pub sender: String,
+        pub sender: AgentID,
         pub sentAt: Option<i64>,
     };
 }
@@ -23,14 +29,53 @@ macro_rules! msg_standard_fields {
 pub struct Message {
     pub clazz: String,
     pub data: Payload,
+    /// Types of the fields to encode before sending, see [Message::set_java_type]
+    #[serde(skip)]
+    java_types: HashMap<String, JavaType>,
 }
 impl Message {
     pub fn new() -> Message {
         Message {
             clazz: "org.arl.fjage.GenericMessage".to_string(),
             data: Payload::new(),
+            java_types: HashMap::new(),
         }
     }
+    /// A reply to this message: addressed to its sender, with inReplyTo set to its msgID
+    pub fn reply(&self, perf: Performative) -> Message {
+        let mut rsp = Message::new();
+        rsp.clazz = "org.arl.fjage.Message".to_string();
+        rsp.data.perf = perf;
+        rsp.data.inReplyTo = Some(self.data.msgID.clone());
+        rsp.data.recipient = self.data.sender.clone();
+        // Left empty for a message to a topic, so that the container sending the reply fills in its own
+        if !self.data.recipient.is_topic() {
+            rsp.data.sender = self.data.recipient.clone();
+        }
+        return rsp;
+    }
+    pub fn agree(&self) -> Message {
+        return self.reply(Performative::AGREE);
+    }
+    /// A REFUSE reply, with the reason in its `reason` field
+    pub fn refuse(&self, reason: &str) -> Message {
+        let mut rsp = self.reply(Performative::REFUSE);
+        rsp.set_string("reason", reason);
+        return rsp;
+    }
+    /// A FAILURE reply, with the reason in its `reason` field
+    pub fn failure(&self, reason: &str) -> Message {
+        let mut rsp = self.reply(Performative::FAILURE);
+        rsp.set_string("reason", reason);
+        return rsp;
+    }
+    pub fn not_understood(&self) -> Message {
+        return self.reply(Performative::NOT_UNDERSTOOD);
+    }
+    /// Start building a message of the given clazz, see [MessageBuilder]
+    pub fn builder(clazz: &str) -> MessageBuilder {
+        return MessageBuilder::new(clazz);
+    }
     pub fn new_generic(clazz: &str, performative: Performative, fields: Value) -> Message {
         let mut msg = Message::new();
         msg.clazz = clazz.to_string();
@@ -38,6 +83,35 @@ impl Message {
         msg.data.fields = serde_json::from_value(fields).unwrap();
         return msg;
     }
+    /// The standard fields of the message
+    pub fn header(&self) -> MessageHeader {
+        return MessageHeader {
+            msgID: self.data.msgID.clone(),
+            perf: self.data.perf,
+            recipient: self.data.recipient.clone(),
+            inReplyTo: self.data.inReplyTo.clone(),
+            sender: self.data.sender.clone(),
+            sentAt: self.data.sentAt,
+        };
+    }
+    pub fn set_header(&mut self, header: &MessageHeader) {
+        self.data.msgID = header.msgID.clone();
+        self.data.perf = header.perf;
+        self.data.recipient = header.recipient.clone();
+        self.data.inReplyTo = header.inReplyTo.clone();
+        self.data.sender = header.sender.clone();
+        self.data.sentAt = header.sentAt;
+    }
+    /// Decode as the typed message `T`, which must have the same clazz as this message
+    pub fn decode<T: FjageMessage>(&self) -> Result<T, DecodeError> {
+        if self.clazz != T::CLAZZ {
+            return Err(DecodeError::WrongClazz {
+                expected: T::CLAZZ.to_string(),
+                found: self.clazz.clone(),
+            });
+        }
+        return T::from_msg(self.clone());
+    }
     pub fn check_clazz_is(&self, clazzes: &Option<Vec<String>>) -> bool {
         if clazzes.is_some() {
             return clazzes.as_ref().is_some_and(|x| x.contains(&self.clazz));
@@ -56,65 +130,277 @@ impl Message {
             return false;
         }
     }
+    /// Replace the base64-encoded Java arrays in the fields, at any depth, with plain JSON arrays.
+    /// Char arrays become strings.
     pub fn decode_java_classes(&mut self) {
-        for field in self.data.fields.clone().keys() {
-            let msg_data = self.data.fields.get(field).unwrap();
-            // Make sure it is an object
-            //println!("CHECK FOR OBJECT: ");
-            if !msg_data.is_object() {
-                //println!("FAIL");
-                continue;
+        for value in self.data.fields.values_mut() {
+            decode_java_value(value);
+        }
+    }
+    /// The signal in the `signal` field, with the sample rate and carrier from the `fs` and `fc` fields.
+    /// Complex signals are sent as interleaved I/Q floats.
+    pub fn get_signal(&self) -> Option<BasebandSignal> {
+        let fields = &self.data.fields;
+        let signal = fields.get("signal")?;
+        let floats: Vec<f32> = match signal.get("data").and_then(Value::as_str) {
+            Some(data) if signal["clazz"] == "[F" => base64_to_f32(data),
+            _ => signal
+                .as_array()?
+                .iter()
+                .map(|x| x.as_f64().map(|x| x as f32))
+                .collect::<Option<Vec<f32>>>()?,
+        };
+        let fc = fields.get("fc").and_then(Value::as_f64).unwrap_or(0.0) as f32;
+        let fs = fields.get("fs").and_then(Value::as_f64).map(|fs| fs as f32);
+        let complex = fields
+            .get("signal__isComplex")
+            .and_then(Value::as_bool)
+            .unwrap_or(fc != 0.0);
+        if complex {
+            return Some(BasebandSignal::new(
+                complex32_from_interleaved(&floats),
+                fs,
+                fc,
+            ));
+        }
+        return Some(BasebandSignal::passband(&floats, fs));
+    }
+    /// Set the `signal`, `fc` and, if the sample rate is known, `fs` fields
+    pub fn set_signal(&mut self, signal: &BasebandSignal) {
+        let fields = &mut self.data.fields;
+        let encoded = if signal.is_complex() {
+            b64_obj_from_complex32(&signal.samples)
+        } else {
+            b64_obj_from_f32(&signal.samples.iter().map(|x| x.re).collect())
+        };
+        fields.insert("signal".to_string(), encoded);
+        fields.insert(
+            "signal__isComplex".to_string(),
+            Value::from(signal.is_complex()),
+        );
+        fields.insert("fc".to_string(), Value::from(signal.fc));
+        match signal.fs {
+            Some(fs) => fields.insert("fs".to_string(), Value::from(fs)),
+            None => fields.remove("fs"),
+        };
+    }
+    /// Integer field `key`, or `default` if it is missing or not an integer
+    pub fn get_i32(&self, key: &str, default: i32) -> i32 {
+        return self
+            .field(key)
+            .and_then(Value::as_i64)
+            .and_then(|x| x.try_into().ok())
+            .unwrap_or(default);
+    }
+    pub fn get_i64(&self, key: &str, default: i64) -> i64 {
+        return self.field(key).and_then(Value::as_i64).unwrap_or(default);
+    }
+    pub fn get_f32(&self, key: &str, default: f32) -> f32 {
+        return self.get_f64(key, default as f64) as f32;
+    }
+    pub fn get_f64(&self, key: &str, default: f64) -> f64 {
+        return self.field(key).and_then(Value::as_f64).unwrap_or(default);
+    }
+    pub fn get_bool(&self, key: &str, default: bool) -> bool {
+        return self.field(key).and_then(Value::as_bool).unwrap_or(default);
+    }
+    pub fn get_string(&self, key: &str, default: &str) -> String {
+        return self
+            .field(key)
+            .and_then(Value::as_str)
+            .unwrap_or(default)
+            .to_string();
+    }
+    /// Byte array field `key`, whether still base64-encoded or not. `None` if it is missing or not a byte array.
+    pub fn get_bytes(&self, key: &str) -> Option<Vec<u8>> {
+        return self.get_array(key, |x| x.as_u64()?.try_into().ok());
+    }
+    pub fn get_i32_array(&self, key: &str) -> Option<Vec<i32>> {
+        return self.get_array(key, |x| x.as_i64()?.try_into().ok());
+    }
+    pub fn get_i64_array(&self, key: &str) -> Option<Vec<i64>> {
+        return self.get_array(key, Value::as_i64);
+    }
+    pub fn get_f32_array(&self, key: &str) -> Option<Vec<f32>> {
+        return self.get_array(key, |x| Some(x.as_f64()? as f32));
+    }
+    pub fn get_f64_array(&self, key: &str) -> Option<Vec<f64>> {
+        return self.get_array(key, Value::as_f64);
+    }
+    pub fn get_string_array(&self, key: &str) -> Option<Vec<String>> {
+        return self.get_array(key, |x| Some(x.as_str()?.to_string()));
+    }
+    fn field(&self, key: &str) -> Option<&Value> {
+        return self.data.fields.get(key);
+    }
+    fn get_array<T, F: Fn(&Value) -> Option<T>>(&self, key: &str, item: F) -> Option<Vec<T>> {
+        let value = self.field(key)?;
+        let decoded;
+        let value = match (value.get("clazz"), value.get("data")) {
+            (Some(clazz), Some(data)) => {
+                decoded = JavaType::from_clazz(clazz.as_str()?)?.decode(data.as_str()?);
+                &decoded
             }
-            let msg_data = msg_data.as_object().unwrap();
-
-            // Check for clazz
-            //println!("CHECK FOR CLAZZ: ");
-            if !msg_data.contains_key("clazz") {
-                //println!("FAIL");
+            _ => value,
+        };
+        return value.as_array()?.iter().map(item).collect();
+    }
+    /// Set field `key` to any JSON value. Arrays set this way are sent as JSON arrays.
+    pub fn set<V: Into<Value>>(&mut self, key: &str, value: V) {
+        self.data.fields.insert(key.to_string(), value.into());
+    }
+    pub fn set_i32(&mut self, key: &str, value: i32) {
+        self.set(key, value);
+    }
+    pub fn set_i64(&mut self, key: &str, value: i64) {
+        self.set(key, value);
+    }
+    pub fn set_f32(&mut self, key: &str, value: f32) {
+        self.set(key, value);
+    }
+    pub fn set_f64(&mut self, key: &str, value: f64) {
+        self.set(key, value);
+    }
+    pub fn set_bool(&mut self, key: &str, value: bool) {
+        self.set(key, value);
+    }
+    pub fn set_string(&mut self, key: &str, value: &str) {
+        self.set(key, value);
+    }
+    /// Set a byte array field, sent as a Java byte[]
+    pub fn set_bytes(&mut self, key: &str, value: &[u8]) {
+        self.set_java_array(key, value, JavaType::BYTE_ARRAY);
+    }
+    pub fn set_i32_array(&mut self, key: &str, value: &[i32]) {
+        self.set_java_array(key, value, JavaType::INT_ARRAY);
+    }
+    pub fn set_i64_array(&mut self, key: &str, value: &[i64]) {
+        self.set_java_array(key, value, JavaType::LONG_ARRAY);
+    }
+    pub fn set_f32_array(&mut self, key: &str, value: &[f32]) {
+        self.set_java_array(key, value, JavaType::FLOAT_ARRAY);
+    }
+    pub fn set_f64_array(&mut self, key: &str, value: &[f64]) {
+        self.set_java_array(key, value, JavaType::DOUBLE_ARRAY);
+    }
+    pub fn set_string_array(&mut self, key: &str, value: &[String]) {
+        self.set(key, value);
+    }
+    fn set_java_array<T: Clone + Into<Value>>(
+        &mut self,
+        key: &str,
+        value: &[T],
+        java_type: JavaType,
+    ) {
+        self.set(key, value);
+        self.set_java_type(key, java_type);
+    }
+    /// Send field `field` as a base64-encoded Java array of type `java_type`, see [Message::encode_java_classes]
+    pub fn set_java_type(&mut self, field: &str, java_type: JavaType) {
+        self.java_types.insert(field.to_string(), java_type);
+    }
+    /// Encode the fields given a type with [Message::set_java_type] as the Java side expects them.
+    /// Fields that are already encoded, or do not fit their type, are left as they are.
+    pub fn encode_java_classes(&mut self) {
+        for (field, java_type) in self.java_types.iter() {
+            let Some(value) = self.data.fields.get_mut(field) else {
                 continue;
+            };
+            match java_type.encode(value) {
+                Some(encoded) => *value = encoded,
+                None if value.is_object() => (),
+                None => println!(
+                    "Error: field {} does not fit {}, sending it unencoded.",
+                    field,
+                    java_type.clazz()
+                ),
             }
+        }
+    }
+}
 
-            // Check for the two possible keys
-            //println!("CHECK FOR DATA/SIG: ");
-            let key = if msg_data.contains_key("data") {
-                "data"
-            } else if msg_data.contains_key("signal") {
-                "signal"
-            } else {
-                //println!("FAIL");
-                continue;
-            };
-            let val: Value = match msg_data.get("clazz").unwrap().as_str().unwrap() {
-                "[F" => {
-                    //println!("Type: Float32");
-                    base64_to_f32(msg_data.get(key).unwrap().as_str().unwrap()).into()
-                }
-                "[I" => {
-                    //println!("Type: Int32");
-                    base64_to_i32(msg_data.get(key).unwrap().as_str().unwrap()).into()
-                }
-                "[D" => {
-                    //println!("Type: Double(F64)");
-                    base64_to_f64(msg_data.get(key).unwrap().as_str().unwrap()).into()
-                }
-                "[J" => {
-                    //println!("Type: Long int(I64)");
-                    base64_to_i64(msg_data.get(key).unwrap().as_str().unwrap()).into()
-                }
-                "[B" => {
-                    //println!("Type: Bytearray(Vec<u8>");
-                    base64_to_u8(msg_data.get(key).unwrap().as_str().unwrap()).into()
-                }
-                "[Ljava.lang.String;" => msg_data.get("data").unwrap().to_owned(),
-                _ => {
-                    //println!("Unsupported B64 type");
-                    Value::Null
-                }
-            };
-            // Replace with decoded
-            self.data.fields.insert(field.to_string(), val);
+/// Builds a [Message] field by field:
+///
+/// ```ignore
+/// let req = MessageBuilder::new("org.arl.unet.DatagramReq")
+///     .recipient(&dsp)
+///     .field("to", 2)
+///     .bytes("data", b"hello")
+///     .build();
+/// ```
+pub struct MessageBuilder {
+    msg: Message,
+}
+impl MessageBuilder {
+    /// A REQUEST of the given clazz
+    pub fn new(clazz: &str) -> MessageBuilder {
+        let mut msg = Message::new();
+        msg.clazz = clazz.to_string();
+        return MessageBuilder { msg: msg };
+    }
+    pub fn perf(mut self, perf: Performative) -> MessageBuilder {
+        self.msg.data.perf = perf;
+        return self;
+    }
+    pub fn recipient(mut self, recipient: &AgentID) -> MessageBuilder {
+        self.msg.data.recipient = recipient.clone();
+        return self;
+    }
+    pub fn in_reply_to(mut self, msg_id: &str) -> MessageBuilder {
+        self.msg.data.inReplyTo = Some(msg_id.to_string());
+        return self;
+    }
+    /// Set a field, see [Message::set]
+    pub fn field<V: Into<Value>>(mut self, key: &str, value: V) -> MessageBuilder {
+        self.msg.set(key, value);
+        return self;
+    }
+    /// Set a byte array field, see [Message::set_bytes]
+    pub fn bytes(mut self, key: &str, value: &[u8]) -> MessageBuilder {
+        self.msg.set_bytes(key, value);
+        return self;
+    }
+    /// Set a field and the Java array type it is sent as, see [Message::set_java_type]
+    pub fn java_array<V: Into<Value>>(
+        mut self,
+        key: &str,
+        value: V,
+        java_type: JavaType,
+    ) -> MessageBuilder {
+        self.msg.set(key, value);
+        self.msg.set_java_type(key, java_type);
+        return self;
+    }
+    pub fn build(self) -> Message {
+        return self.msg;
+    }
+}
+
+fn decode_java_value(value: &mut Value) {
+    match value {
+        Value::Object(obj) => {
+            let clazz = obj.get("clazz").and_then(Value::as_str).unwrap_or("");
+            // Java arrays come as {clazz, data}, or {clazz, signal} for signals
+            let data = obj.get("data").or(obj.get("signal"));
+            if let (true, Some(data)) = (clazz.starts_with('['), data) {
+                *value = match (JavaType::from_clazz(clazz), data.as_str()) {
+                    (Some(java_type), Some(data)) => java_type.decode(data),
+                    // Arrays of objects, such as String[], are sent as plain JSON
+                    (None, _) if data.is_array() => data.clone(),
+                    _ => Value::Null,
+                };
+                return;
+            }
+            for value in obj.values_mut() {
+                decode_java_value(value);
+            }
+        }
+        Value::Array(items) => {
+            for value in items.iter_mut() {
+                decode_java_value(value);
+            }
         }
-        //println!("POST_DECODE: {:?}", self);
+        _ => (),
     }
 }
 
@@ -123,9 +409,9 @@ impl Message {
 pub struct Payload {
     pub msgID: String,
     pub perf: Performative,
-    pub recipient: String,
+    pub recipient: AgentID,
     pub inReplyTo: Option<String>,
-    pub sender: String,
+    pub sender: AgentID,
     pub sentAt: Option<i64>,
     #[serde(flatten)]
     pub fields: HashMap<String, Value>,
@@ -135,17 +421,42 @@ impl Payload {
         Payload {
             msgID: Uuid::new_v4().to_string(),
             perf: Performative::REQUEST,
-            recipient: String::new(),
+            recipient: AgentID::default(),
             inReplyTo: None,
-            sender: String::new(),
+            sender: AgentID::default(),
             sentAt: None,
             fields: HashMap::new(),
         }
     }
 }
 
-#[allow(non_camel_case_types)]
+/// The standard fields every message carries, for typed messages (see [FjageMessage](crate::core::typed::FjageMessage))
+#[allow(non_camel_case_types, non_snake_case)]
 #[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MessageHeader {
+    pub msgID: String,
+    pub perf: Performative,
+    pub recipient: AgentID,
+    pub inReplyTo: Option<String>,
+    pub sender: AgentID,
+    pub sentAt: Option<i64>,
+}
+impl MessageHeader {
+    /// A header with a fresh msgID and no recipient yet
+    pub fn new(perf: Performative) -> MessageHeader {
+        return MessageHeader {
+            msgID: Uuid::new_v4().to_string(),
+            perf: perf,
+            recipient: AgentID::default(),
+            inReplyTo: None,
+            sender: AgentID::default(),
+            sentAt: None,
+        };
+    }
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Performative {
     NONE = 0, //The C API defines this, but the spec does not.
     REQUEST = 1,
@@ -161,3 +472,57 @@ pub enum Performative {
     PROPOSE = 11,
     CANCEL = 12,
 }
+impl Performative {
+    const ALL: [Performative; 13] = [
+        Performative::NONE,
+        Performative::REQUEST,
+        Performative::AGREE,
+        Performative::REFUSE,
+        Performative::FAILURE,
+        Performative::INFORM,
+        Performative::CONFIRM,
+        Performative::DISCONFIRM,
+        Performative::QUERY_IF,
+        Performative::NOT_UNDERSTOOD,
+        Performative::CPF,
+        Performative::PROPOSE,
+        Performative::CANCEL,
+    ];
+    /// Whether a reply with this performative means the request was carried out
+    pub fn is_success(&self) -> bool {
+        return matches!(
+            self,
+            Performative::AGREE | Performative::INFORM | Performative::CONFIRM
+        );
+    }
+}
+impl fmt::Display for Performative {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+impl FromStr for Performative {
+    type Err = UnknownPerformative;
+    /// Parse the name of a performative, in any case
+    fn from_str(s: &str) -> Result<Performative, UnknownPerformative> {
+        return Performative::ALL
+            .into_iter()
+            .find(|perf| perf.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| UnknownPerformative(s.to_string()));
+    }
+}
+impl From<Performative> for i32 {
+    /// The number of the performative in the C API
+    fn from(perf: Performative) -> i32 {
+        return perf as i32;
+    }
+}
+impl TryFrom<i32> for Performative {
+    type Error = UnknownPerformative;
+    fn try_from(value: i32) -> Result<Performative, UnknownPerformative> {
+        return Performative::ALL
+            .into_iter()
+            .find(|perf| *perf as i32 == value)
+            .ok_or_else(|| UnknownPerformative(value.to_string()));
+    }
+}
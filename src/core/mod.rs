@@ -1,5 +1,11 @@
 //pub mod agent;
 //pub mod behavior;
 //pub mod container;
+pub mod aid;
+pub mod error;
+pub mod filter;
 pub mod message;
 pub mod param;
+pub mod registry;
+pub mod signal;
+pub mod typed;
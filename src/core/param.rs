@@ -1,41 +1,33 @@
 use std::{collections::HashMap, time::Duration};
 
-use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use uuid::Uuid;
 
 use crate::protocol::base64::*;
 
-use super::message::{Message, Performative};
+use super::{
+    aid::AgentID,
+    message::{MessageHeader, Performative},
+    typed::FjageMessage,
+};
 
 /*fn skip_if_requests_null(val: Option<HashMap<String,Value>>) {
     if val.is_none()
 }*/
 
-#[allow(non_camel_case_types, non_snake_case)]
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(FjageMessage, Debug, Clone)]
+#[fjage(clazz = "org.arl.fjage.param.ParameterReq")]
 pub struct ParameterReq {
-    pub msgID: String,
-    pub perf: Performative,
-    pub recipient: String,
-    pub inReplyTo: Option<String>,
-    pub sender: String,
-    pub sentAt: i64,
+    #[fjage(header)]
+    pub header: MessageHeader,
     pub index: i64,
     pub param: Option<String>,
     pub value: Value,
-    #[serde(skip_serializing_if = "Option::is_none")]
     pub requests: Option<HashMap<String, Value>>,
 }
 impl ParameterReq {
     pub fn new() -> ParameterReq {
         return ParameterReq {
-            msgID: Uuid::new_v4().to_string(),
-            perf: Performative::REQUEST,
-            recipient: String::new(),
-            inReplyTo: None,
-            sender: String::new(),
-            sentAt: 0,
+            header: MessageHeader::new(Performative::REQUEST),
             index: -1,
             param: None,
             value: Value::Null,
@@ -69,26 +61,13 @@ impl ParameterReq {
         req.requests = Some(map);
         return req;
     }
-    pub fn from_msg(msg: Message) -> ParameterReq {
-        return serde_json::from_value(serde_json::to_value(msg.data).unwrap()).unwrap();
-    }
-    pub fn to_msg(&mut self) -> Message {
-        return Message {
-            clazz: "org.arl.fjage.param.ParameterReq".to_string(),
-            data: serde_json::from_value(serde_json::to_value(self).unwrap()).unwrap(),
-        };
-    }
 }
 
-#[allow(non_camel_case_types, non_snake_case)]
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(FjageMessage, Debug, Clone)]
+#[fjage(clazz = "org.arl.fjage.param.ParameterRsp")]
 pub struct ParameterRsp {
-    pub msgID: String,
-    pub perf: Performative,
-    pub recipient: String,
-    pub inReplyTo: Option<String>,
-    pub sender: String,
-    pub sentAt: i64,
+    #[fjage(header)]
+    pub header: MessageHeader,
     pub index: i64,
     pub param: Option<String>,
     pub value: Value,
@@ -98,12 +77,7 @@ pub struct ParameterRsp {
 impl ParameterRsp {
     pub fn new() -> ParameterRsp {
         return ParameterRsp {
-            msgID: Uuid::new_v4().to_string(),
-            perf: Performative::REQUEST,
-            recipient: String::new(),
-            inReplyTo: None,
-            sender: String::new(),
-            sentAt: 0,
+            header: MessageHeader::new(Performative::INFORM),
             index: -1,
             param: None,
             value: Value::Null,
@@ -111,26 +85,17 @@ impl ParameterRsp {
             readonly: None,
         };
     }
-    pub fn from_msg(msg: Message) -> ParameterRsp {
-        return serde_json::from_value(serde_json::to_value(msg.data).unwrap()).unwrap();
-    }
-    pub fn to_msg(&mut self) -> Message {
-        return Message {
-            clazz: "org.arl.fjage.param.ParameterRsp".to_string(),
-            data: serde_json::from_value(serde_json::to_value(self).unwrap()).unwrap(),
-        };
-    }
 }
 
 pub trait ParameterManipulation {
-    fn param_req(&mut self, aid: &str, req: ParameterReq) -> Option<ParameterRsp>;
+    fn param_req(&mut self, aid: &AgentID, req: ParameterReq) -> Option<ParameterRsp>;
     fn param_req_timeout(
         &mut self,
-        aid: &str,
+        aid: &AgentID,
         req: ParameterReq,
         timeout: Duration,
     ) -> Option<ParameterRsp>;
-    fn get_param(&mut self, aid: &str, param: &str, index: i64) -> Option<Value> {
+    fn get_param(&mut self, aid: &AgentID, param: &str, index: i64) -> Option<Value> {
         let value = self.param_req_timeout(
             aid,
             ParameterReq::get(param, index),
@@ -144,7 +109,7 @@ pub trait ParameterManipulation {
     }
     fn set_param(
         &mut self,
-        aid: &str,
+        aid: &AgentID,
         param: &str,
         value: Value,
         index: i64,
@@ -169,7 +134,7 @@ pub trait ParameterManipulation {
         }
     }
     //
-    fn get_bool(&mut self, aid: &str, param: &str, index: i64) -> Option<bool> {
+    fn get_bool(&mut self, aid: &AgentID, param: &str, index: i64) -> Option<bool> {
         let req = ParameterReq::get(param, index);
         let rsp = self.param_req(aid, req);
         if rsp.is_none() {
@@ -180,14 +145,14 @@ pub trait ParameterManipulation {
         return rsp.as_bool();
     }
     // NOTE: serde_json interprets all numerical values as either long or double. The get_int and get_float methods are only here for convenience.
-    fn get_int(&mut self, aid: &str, param: &str, index: i64) -> Option<i32> {
+    fn get_int(&mut self, aid: &AgentID, param: &str, index: i64) -> Option<i32> {
         let rsp = self.get_long(aid, param, index);
         if rsp.is_none() {
             return None;
         }
         return Some(rsp.unwrap() as i32);
     }
-    fn get_long(&mut self, aid: &str, param: &str, index: i64) -> Option<i64> {
+    fn get_long(&mut self, aid: &AgentID, param: &str, index: i64) -> Option<i64> {
         let req = ParameterReq::get(param, index);
         let rsp = self.param_req(aid, req);
         if rsp.is_none() {
@@ -196,14 +161,14 @@ pub trait ParameterManipulation {
         let rsp = rsp.unwrap().value;
         return rsp.as_i64();
     }
-    fn get_float(&mut self, aid: &str, param: &str, index: i64) -> Option<f32> {
+    fn get_float(&mut self, aid: &AgentID, param: &str, index: i64) -> Option<f32> {
         let rsp = self.get_double(aid, param, index);
         if rsp.is_none() {
             return None;
         }
         return Some(rsp.unwrap() as f32);
     }
-    fn get_double(&mut self, aid: &str, param: &str, index: i64) -> Option<f64> {
+    fn get_double(&mut self, aid: &AgentID, param: &str, index: i64) -> Option<f64> {
         let req = ParameterReq::get(param, index);
         let rsp = self.param_req(aid, req);
         if rsp.is_none() {
@@ -212,7 +177,7 @@ pub trait ParameterManipulation {
         let rsp = rsp.unwrap().value;
         return rsp.as_f64();
     }
-    fn get_string(&mut self, aid: &str, param: &str, index: i64) -> Option<String> {
+    fn get_string(&mut self, aid: &AgentID, param: &str, index: i64) -> Option<String> {
         let req = ParameterReq::get(param, index);
         let rsp = self.param_req(aid, req);
         if rsp.is_none() {
@@ -226,7 +191,13 @@ pub trait ParameterManipulation {
     }
 
     //
-    fn set_bool(&mut self, aid: &str, param: &str, value: bool, index: i64) -> Result<bool, i32> {
+    fn set_bool(
+        &mut self,
+        aid: &AgentID,
+        param: &str,
+        value: bool,
+        index: i64,
+    ) -> Result<bool, i32> {
         let req = ParameterReq::set(param, Value::from(value), index);
         let rsp = self.param_req(aid, req);
         if rsp.is_none() {
@@ -243,7 +214,7 @@ pub trait ParameterManipulation {
             return Err(-1);
         }
     }
-    fn set_int(&mut self, aid: &str, param: &str, value: i32, index: i64) -> Result<i32, i32> {
+    fn set_int(&mut self, aid: &AgentID, param: &str, value: i32, index: i64) -> Result<i32, i32> {
         let rsp = self.set_long(aid, param, value as i64, index);
         if rsp.is_ok() {
             return Ok(rsp.unwrap() as i32);
@@ -251,7 +222,7 @@ pub trait ParameterManipulation {
             return Err(rsp.unwrap_err());
         }
     }
-    fn set_long(&mut self, aid: &str, param: &str, value: i64, index: i64) -> Result<i64, i32> {
+    fn set_long(&mut self, aid: &AgentID, param: &str, value: i64, index: i64) -> Result<i64, i32> {
         let req = ParameterReq::set(param, Value::from(value), index);
         let rsp = self.param_req(aid, req);
         if rsp.is_none() {
@@ -268,7 +239,13 @@ pub trait ParameterManipulation {
             return Err(-1);
         }
     }
-    fn set_float(&mut self, aid: &str, param: &str, value: f32, index: i64) -> Result<f32, i32> {
+    fn set_float(
+        &mut self,
+        aid: &AgentID,
+        param: &str,
+        value: f32,
+        index: i64,
+    ) -> Result<f32, i32> {
         let rsp = self.set_double(aid, param, value as f64, index);
         if rsp.is_ok() {
             return Ok(rsp.unwrap() as f32);
@@ -276,7 +253,13 @@ pub trait ParameterManipulation {
             return Err(rsp.unwrap_err());
         }
     }
-    fn set_double(&mut self, aid: &str, param: &str, value: f64, index: i64) -> Result<f64, i32> {
+    fn set_double(
+        &mut self,
+        aid: &AgentID,
+        param: &str,
+        value: f64,
+        index: i64,
+    ) -> Result<f64, i32> {
         let req = ParameterReq::set(param, Value::from(value), index);
         let rsp = self.param_req(aid, req);
         if rsp.is_none() {
@@ -295,7 +278,7 @@ pub trait ParameterManipulation {
     }
     fn set_string(
         &mut self,
-        aid: &str,
+        aid: &AgentID,
         param: &str,
         value: &str,
         index: i64,
@@ -318,7 +301,7 @@ pub trait ParameterManipulation {
     }
 
     // array getters
-    fn get_int_array(&mut self, aid: &str, param: &str, index: i64) -> Option<Vec<i32>> {
+    fn get_int_array(&mut self, aid: &AgentID, param: &str, index: i64) -> Option<Vec<i32>> {
         let rsp = self.get_long_array(aid, param, index);
         if rsp.is_none() {
             return None;
@@ -326,7 +309,7 @@ pub trait ParameterManipulation {
         let rsp: Vec<i32> = rsp.unwrap().iter().map(|v| *v as i32).collect();
         return Some(rsp);
     }
-    fn get_long_array(&mut self, aid: &str, param: &str, index: i64) -> Option<Vec<i64>> {
+    fn get_long_array(&mut self, aid: &AgentID, param: &str, index: i64) -> Option<Vec<i64>> {
         let req = ParameterReq::get(param, index);
         let rsp = self.param_req(aid, req);
         if rsp.is_none() {
@@ -346,7 +329,7 @@ pub trait ParameterManipulation {
         let rsp: Vec<i64> = rsp.iter().map(|v| v.as_i64().unwrap_or(0)).collect();
         return Some(rsp);
     }
-    fn get_float_array(&mut self, aid: &str, param: &str, index: i64) -> Option<Vec<f32>> {
+    fn get_float_array(&mut self, aid: &AgentID, param: &str, index: i64) -> Option<Vec<f32>> {
         {
             let rsp = self.get_double_array(aid, param, index);
             if rsp.is_none() {
@@ -356,7 +339,7 @@ pub trait ParameterManipulation {
             return Some(rsp);
         }
     }
-    fn get_double_array(&mut self, aid: &str, param: &str, index: i64) -> Option<Vec<f64>> {
+    fn get_double_array(&mut self, aid: &AgentID, param: &str, index: i64) -> Option<Vec<f64>> {
         let req = ParameterReq::get(param, index);
         let rsp = self.param_req(aid, req);
         if rsp.is_none() {
@@ -376,7 +359,7 @@ pub trait ParameterManipulation {
         let rsp: Vec<f64> = rsp.iter().map(|v| v.as_f64().unwrap_or(0.0)).collect();
         return Some(rsp);
     }
-    fn get_string_array(&mut self, aid: &str, param: &str, index: i64) -> Option<Vec<String>> {
+    fn get_string_array(&mut self, aid: &AgentID, param: &str, index: i64) -> Option<Vec<String>> {
         {
             let req = ParameterReq::get(param, index);
             let rsp = self.param_req(aid, req);
@@ -404,7 +387,7 @@ pub trait ParameterManipulation {
     // array setters
     fn set_int_array(
         &mut self,
-        aid: &str,
+        aid: &AgentID,
         param: &str,
         value: Vec<i32>,
         index: i64,
@@ -438,7 +421,7 @@ pub trait ParameterManipulation {
     }
     fn set_long_array(
         &mut self,
-        aid: &str,
+        aid: &AgentID,
         param: &str,
         value: Vec<i64>,
         index: i64,
@@ -472,7 +455,7 @@ pub trait ParameterManipulation {
     }
     fn set_float_array(
         &mut self,
-        aid: &str,
+        aid: &AgentID,
         param: &str,
         value: Vec<f32>,
         index: i64,
@@ -509,7 +492,7 @@ pub trait ParameterManipulation {
     }
     fn set_double_array(
         &mut self,
-        aid: &str,
+        aid: &AgentID,
         param: &str,
         value: Vec<f64>,
         index: i64,
@@ -543,7 +526,7 @@ pub trait ParameterManipulation {
     }
     fn set_string_array(
         &mut self,
-        aid: &str,
+        aid: &AgentID,
         param: &str,
         value: Vec<String>,
         index: i64,
@@ -0,0 +1,140 @@
+//! Maps message clazzes to Rust types, so received messages come out typed instead of as a generic [Message].
+//!
+//! ```ignore
+//! gw.register::<TxFrameReq>();
+//! match gw.recv_typed(any())? {
+//!     msg if msg.is::<TxFrameReq>() => handle(msg.downcast::<TxFrameReq>().unwrap()),
+//!     msg => println!("{}", msg.clazz()),
+//! }
+//! ```
+use std::{any::Any, collections::HashMap, fmt::Debug};
+
+use crate::remote::{
+    file::{GetFileReq, GetFileRsp, PutFileReq},
+    shell::ShellExecReq,
+};
+
+use super::{
+    error::DecodeError,
+    message::Message,
+    param::{ParameterReq, ParameterRsp},
+    typed::FjageMessage,
+};
+
+/// A typed message held by [AnyMessage]. Implemented for every [FjageMessage] that is `Debug + Send`.
+pub trait TypedMessage: Any + Send + Debug {
+    fn clazz(&self) -> &'static str;
+    /// Convert back to a generic message, see [FjageMessage::to_msg]
+    fn to_message(&self) -> Message;
+    fn as_any(&self) -> &dyn Any;
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+impl<T: FjageMessage + Send + Debug + 'static> TypedMessage for T {
+    fn clazz(&self) -> &'static str {
+        return T::CLAZZ;
+    }
+    fn to_message(&self) -> Message {
+        return self.to_msg();
+    }
+    fn as_any(&self) -> &dyn Any {
+        return self;
+    }
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        return self;
+    }
+}
+
+/// A received message: typed if its clazz is registered, generic otherwise
+#[derive(Debug)]
+pub enum AnyMessage {
+    Typed(Box<dyn TypedMessage>),
+    Generic(Box<Message>),
+}
+impl AnyMessage {
+    pub fn clazz(&self) -> &str {
+        return match self {
+            AnyMessage::Typed(msg) => msg.clazz(),
+            AnyMessage::Generic(msg) => &msg.clazz,
+        };
+    }
+    /// Whether this is a typed message of type `T`
+    pub fn is<T: TypedMessage>(&self) -> bool {
+        return self.downcast_ref::<T>().is_some();
+    }
+    pub fn downcast_ref<T: TypedMessage>(&self) -> Option<&T> {
+        return match self {
+            AnyMessage::Typed(msg) => msg.as_any().downcast_ref(),
+            AnyMessage::Generic(_) => None,
+        };
+    }
+    /// Take out the typed message, or get `self` back if it is not a `T`
+    pub fn downcast<T: TypedMessage>(self) -> Result<T, AnyMessage> {
+        if !self.is::<T>() {
+            return Err(self);
+        }
+        let AnyMessage::Typed(msg) = self else {
+            unreachable!();
+        };
+        return Ok(*msg.into_any().downcast().unwrap());
+    }
+    /// The message as a generic [Message], whichever kind it is
+    pub fn into_msg(self) -> Message {
+        return match self {
+            AnyMessage::Typed(msg) => msg.to_message(),
+            AnyMessage::Generic(msg) => *msg,
+        };
+    }
+}
+
+type Decoder = fn(Message) -> Result<Box<dyn TypedMessage>, DecodeError>;
+
+/// Message types to decode received messages into, by clazz. The default registry knows the message types
+/// of this crate; [RemoteContainer::register](crate::remote::container::RemoteContainer::register) adds more.
+#[derive(Clone)]
+pub struct MessageRegistry {
+    decoders: HashMap<String, Decoder>,
+}
+impl MessageRegistry {
+    /// A registry with no types registered
+    pub fn new() -> MessageRegistry {
+        return MessageRegistry {
+            decoders: HashMap::new(),
+        };
+    }
+    /// Decode messages of clazz `T::CLAZZ` as `T`, replacing any type registered for it before
+    pub fn register<T: FjageMessage + Send + Debug + 'static>(&mut self) {
+        let decoder: Decoder = |msg| {
+            return T::from_msg(msg).map(|msg| Box::new(msg) as Box<dyn TypedMessage>);
+        };
+        self.decoders.insert(T::CLAZZ.to_string(), decoder);
+    }
+    pub fn is_registered(&self, clazz: &str) -> bool {
+        return self.decoders.contains_key(clazz);
+    }
+    /// Decode a message into its registered type. Messages of unknown clazz, and messages that do not
+    /// decode as their registered type, come back generic.
+    pub fn decode(&self, msg: Message) -> AnyMessage {
+        let Some(decoder) = self.decoders.get(&msg.clazz) else {
+            return AnyMessage::Generic(Box::new(msg));
+        };
+        return match decoder(msg.clone()) {
+            Ok(typed) => AnyMessage::Typed(typed),
+            Err(e) => {
+                println!("Error: could not decode {}: {}", msg.clazz, e);
+                AnyMessage::Generic(Box::new(msg))
+            }
+        };
+    }
+}
+impl Default for MessageRegistry {
+    fn default() -> MessageRegistry {
+        let mut registry = MessageRegistry::new();
+        registry.register::<ParameterReq>();
+        registry.register::<ParameterRsp>();
+        registry.register::<ShellExecReq>();
+        registry.register::<GetFileReq>();
+        registry.register::<GetFileRsp>();
+        registry.register::<PutFileReq>();
+        return registry;
+    }
+}
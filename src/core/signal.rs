@@ -0,0 +1,53 @@
+use std::time::Duration;
+
+pub use num_complex::Complex32;
+
+/// A signal as carried by UnetStack's RxBasebandSignalNtf and TxBasebandSignalReq. Read and write it with
+/// [Message::get_signal](crate::core::message::Message::get_signal) and [Message::set_signal](crate::core::message::Message::set_signal).
+#[derive(Clone, Debug, PartialEq)]
+pub struct BasebandSignal {
+    pub samples: Vec<Complex32>,
+    /// Sample rate in Hz, if known
+    pub fs: Option<f32>,
+    /// Carrier frequency in Hz. Passband signals have none, so it is zero for them.
+    pub fc: f32,
+    /// Whether the samples are complex baseband, rather than real passband with no imaginary part
+    complex: bool,
+}
+impl BasebandSignal {
+    /// A complex baseband signal around the carrier `fc`. A carrier of zero is allowed and keeps the signal complex.
+    pub fn new(samples: Vec<Complex32>, fs: Option<f32>, fc: f32) -> BasebandSignal {
+        return BasebandSignal {
+            samples: samples,
+            fs: fs,
+            fc: fc,
+            complex: true,
+        };
+    }
+    /// A real passband signal
+    pub fn passband(samples: &[f32], fs: Option<f32>) -> BasebandSignal {
+        return BasebandSignal {
+            samples: samples.iter().map(|x| Complex32::new(*x, 0.0)).collect(),
+            fs: fs,
+            fc: 0.0,
+            complex: false,
+        };
+    }
+    /// Whether the samples are complex, i.e. the signal is at baseband rather than passband
+    pub fn is_complex(&self) -> bool {
+        return self.complex;
+    }
+    pub fn len(&self) -> usize {
+        return self.samples.len();
+    }
+    pub fn is_empty(&self) -> bool {
+        return self.samples.is_empty();
+    }
+    /// How long the signal lasts, if its sample rate is known
+    pub fn duration(&self) -> Option<Duration> {
+        let fs = self.fs.filter(|fs| *fs > 0.0)?;
+        return Some(Duration::from_secs_f64(
+            self.samples.len() as f64 / fs as f64,
+        ));
+    }
+}
@@ -0,0 +1,108 @@
+//! Typed messages, converted to and from [Message] with `#[derive(FjageMessage)]`:
+//!
+//! ```ignore
+//! #[derive(FjageMessage, Debug, Clone)]
+//! #[fjage(clazz = "org.arl.unet.phy.TxFrameReq")]
+//! pub struct TxFrameReq {
+//!     #[fjage(header)]
+//!     pub header: MessageHeader,
+//!     pub to: i32,
+//!     pub data: Vec<u8>,
+//! }
+//! ```
+use serde_json::Value;
+
+pub use fjage_derive::FjageMessage;
+
+use crate::protocol::base64::*;
+
+use super::{error::DecodeError, message::Message};
+
+/// A message type with a fixed clazz, usually implemented with `#[derive(FjageMessage)]`
+pub trait FjageMessage: Sized {
+    /// Java class of the message
+    const CLAZZ: &'static str;
+    fn to_msg(&self) -> Message;
+    fn from_msg(msg: Message) -> Result<Self, DecodeError>;
+}
+
+/// Primitive types that fjåge sends as base64-encoded Java arrays
+pub trait JavaArray: Sized {
+    /// Java class of an array of this type
+    const JAVA_CLAZZ: &'static str;
+    fn to_b64_obj(v: &Vec<Self>) -> Value;
+    fn from_base64(s: &str) -> Vec<Self>;
+}
+macro_rules! java_array {
+    ($t:ty, $clazz:literal, $to:ident, $from:ident) => {
+        impl JavaArray for $t {
+            const JAVA_CLAZZ: &'static str = $clazz;
+            fn to_b64_obj(v: &Vec<$t>) -> Value {
+                return $to(v);
+            }
+            fn from_base64(s: &str) -> Vec<$t> {
+                return $from(s);
+            }
+        }
+    };
+}
+java_array!(u8, "[B", b64_obj_from_u8, base64_to_u8);
+java_array!(i16, "[S", b64_obj_from_i16, base64_to_i16);
+java_array!(i32, "[I", b64_obj_from_i32, base64_to_i32);
+java_array!(i64, "[J", b64_obj_from_i64, base64_to_i64);
+java_array!(f32, "[F", b64_obj_from_f32, base64_to_f32);
+java_array!(f64, "[D", b64_obj_from_f64, base64_to_f64);
+java_array!(bool, "[Z", b64_obj_from_bool, base64_to_bool);
+
+/// Used by the code `#[derive(FjageMessage)]` generates
+#[doc(hidden)]
+pub mod __private {
+    use serde::{de::DeserializeOwned, Serialize};
+    use serde_json::Value;
+
+    use super::JavaArray;
+    use crate::core::error::DecodeError;
+
+    pub fn encode<T: Serialize>(v: &T) -> Value {
+        return serde_json::to_value(v).unwrap_or(Value::Null);
+    }
+    pub fn encode_array<T: JavaArray>(v: &Vec<T>) -> Value {
+        return T::to_b64_obj(v);
+    }
+    /// A missing field decodes like null, so that `Option` and `Value` fields may be left out
+    pub fn decode<T: DeserializeOwned>(name: &str, value: Option<Value>) -> Result<T, DecodeError> {
+        return match value {
+            Some(value) => serde_json::from_value(value)
+                .map_err(|e| DecodeError::InvalidField(name.to_string(), e)),
+            None => serde_json::from_value(Value::Null)
+                .map_err(|_| DecodeError::MissingField(name.to_string())),
+        };
+    }
+    /// Accepts both a base64 object and a plain JSON array, as left by [decode_java_classes](crate::core::message::Message::decode_java_classes)
+    pub fn decode_array<T: JavaArray + DeserializeOwned>(
+        name: &str,
+        value: Option<Value>,
+    ) -> Result<Vec<T>, DecodeError> {
+        let Some(value) = value else {
+            return Err(DecodeError::MissingField(name.to_string()));
+        };
+        if let Some(data) = value
+            .as_object()
+            .filter(|obj| obj.get("clazz").and_then(Value::as_str) == Some(T::JAVA_CLAZZ))
+            .and_then(|obj| obj.get("data"))
+            .and_then(Value::as_str)
+        {
+            return Ok(T::from_base64(data));
+        }
+        return decode(name, Some(value));
+    }
+    pub fn decode_optional_array<T: JavaArray + DeserializeOwned>(
+        name: &str,
+        value: Option<Value>,
+    ) -> Result<Option<Vec<T>>, DecodeError> {
+        return match value {
+            None | Some(Value::Null) => Ok(None),
+            value => decode_array(name, value).map(Some),
+        };
+    }
+}
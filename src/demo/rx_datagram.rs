@@ -24,26 +24,22 @@ fn main() {
     let port = port.unwrap();
 
     // Connect to gateway
-    let mut gw = Gateway::new_tcp(hostname, port);
+    let gw = Gateway::new_tcp(hostname, port).unwrap();
 
     // Find and subscribe to all agents advertising the DATAGRAM service
-    let dsp = gw.agents_for_service("org.arl.unet.Services.DATAGRAM");
+    let dsp = gw
+        .agents_for_service("org.arl.unet.Services.DATAGRAM")
+        .unwrap();
     for agent in dsp.iter() {
-        gw.subscribe_agent(&agent);
+        gw.subscribe_agent(&agent).unwrap();
     }
 
     // Receive, filtering for DatagramNtf
     let rsp = gw.recv(Some(vec!["org.arl.unet.DatagramNtf".to_string()]), None);
     let rsp = rsp.unwrap();
 
-    if rsp.data.fields.contains_key("data") {
-        // TODO: Make this API more ergonomic. Message::get_byte_array etc.
-        let data = rsp.data.fields.get("data").unwrap().as_array().unwrap();
-        // String from Vec<Value> where Value is of type u8.
-        let data =
-            String::from_utf8(data.iter().map(|x| x.as_u64().unwrap() as u8).collect()).unwrap();
-        println!("Received: {}", data);
-    } else {
-        println!("Received blank message.");
+    match rsp.get_bytes("data") {
+        Some(data) => println!("Received: {}", String::from_utf8_lossy(&data)),
+        None => println!("Received blank message."),
     }
 }
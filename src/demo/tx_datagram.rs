@@ -4,7 +4,6 @@ use fjage_rs::{
     api::gateway::Gateway,
     core::message::{Message, Performative},
 };
-use serde_json::json;
 
 static HELP_STRING: &str = r##"
 Usage: tx_datagram <hostname> <port> <remote node> <message>
@@ -31,7 +30,7 @@ async fn main() {
     let data = args.get(4).unwrap();
 
     // Connect to gateway
-    let mut gw = Gateway::new_tcp(hostname, port);
+    let gw = Gateway::new_tcp(hostname, port).unwrap();
 
     // Find an agent advertising the DATAGRAM service
     let dsp = gw
@@ -39,19 +38,15 @@ async fn main() {
         .unwrap();
 
     // Demonstration of the power of generic messages
-    let datagram = Message::new_generic(
-        "org.arl.unet.DatagramReq",
-        Performative::REQUEST,
-        json!({"data": data.as_bytes().to_vec(), "to": dst, "protocol": 0}),
-    );
+    let datagram = Message::builder("org.arl.unet.DatagramReq")
+        .field("to", dst)
+        .field("protocol", 0)
+        .bytes("data", data.as_bytes())
+        .build();
 
     // Send message to the datagram service provider
     let rsp = gw.request(&dsp, datagram);
-    let rsp = rsp.unwrap();
-    if match rsp.data.perf {
-        Performative::AGREE => true,
-        _ => false,
-    } {
+    if rsp.is_ok_and(|rsp| matches!(rsp.data.perf, Performative::AGREE)) {
         println!("Transmitted successfully.");
     } else {
         println!("Transmission failed!");
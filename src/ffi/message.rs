@@ -16,9 +16,9 @@ use std::{
 
 use serde_json::Value;
 
-use crate::msg_get_array;
+use crate::{core::message::Performative, msg_get_array, protocol::base64::JavaType};
 
-use super::util::{c_api_cstr_to_string, c_api_int_to_perf, c_api_perf_to_int, fjage_msg_t};
+use super::util::{c_api_cstr_to_string, fjage_msg_t};
 
 //fjage_msg_t fjage_msg_create(const char *clazz, fjage_perf_t perf);
 #[no_mangle]
@@ -26,7 +26,7 @@ pub unsafe extern "C" fn fjage_msg_create(clazz: *mut c_char, perf: c_int) -> *m
     let msg = fjage_msg_t::alloc();
     let msg_ref = &mut msg.as_mut().unwrap().msg;
     msg_ref.clazz = c_api_cstr_to_string(clazz);
-    msg_ref.data.perf = c_api_int_to_perf(perf);
+    msg_ref.data.perf = Performative::try_from(perf).unwrap_or(Performative::NONE);
     return msg;
 }
 
@@ -170,7 +170,7 @@ pub unsafe extern "C" fn fjage_msg_add_byte_array(
 ) {
     let arr = slice::from_raw_parts(value, len as usize);
     //let arr: Vec<u8> = Vec::from_raw_parts(value, len as usize, len as usize);
-    fjage_msg_t::set(msg, key, Value::from(arr));
+    fjage_msg_t::set_array(msg, key, Value::from(arr), JavaType::BYTE_ARRAY);
 }
 
 /// Add an integer array value to a message.
@@ -189,7 +189,7 @@ pub unsafe extern "C" fn fjage_msg_add_int_array(
     len: c_int,
 ) {
     let arr = slice::from_raw_parts(value, len as usize);
-    fjage_msg_t::set(msg, key, Value::from(arr));
+    fjage_msg_t::set_array(msg, key, Value::from(arr), JavaType::INT_ARRAY);
 }
 
 /* NOT IN BASE C API */
@@ -201,7 +201,7 @@ pub unsafe extern "C" fn fjage_msg_add_long_array(
     len: c_int,
 ) {
     let arr = slice::from_raw_parts(value, len as usize);
-    fjage_msg_t::set(msg, key, Value::from(arr));
+    fjage_msg_t::set_array(msg, key, Value::from(arr), JavaType::LONG_ARRAY);
 }
 
 /// Add a floating point array value to a message.
@@ -220,7 +220,7 @@ pub unsafe extern "C" fn fjage_msg_add_float_array(
     len: c_int,
 ) {
     let arr = slice::from_raw_parts(value, len as usize);
-    fjage_msg_t::set(msg, key, Value::from(arr));
+    fjage_msg_t::set_array(msg, key, Value::from(arr), JavaType::FLOAT_ARRAY);
 }
 
 /* NOT IN BASE C API */
@@ -233,7 +233,7 @@ pub unsafe extern "C" fn fjage_msg_add_double_array(
     len: c_int,
 ) {
     let arr = slice::from_raw_parts(value, len as usize);
-    fjage_msg_t::set(msg, key, Value::from(arr));
+    fjage_msg_t::set_array(msg, key, Value::from(arr), JavaType::DOUBLE_ARRAY);
 }
 
 /// Get the message ID. The string returned by this function should
@@ -278,7 +278,7 @@ pub unsafe extern "C" fn fjage_msg_get_clazz(msg: *mut fjage_msg_t) -> *const c_
 //fjage_perf_t fjage_msg_get_performative(fjage_msg_t msg);
 #[no_mangle]
 pub unsafe extern "C" fn fjage_msg_get_performative(msg: *mut fjage_msg_t) -> c_int {
-    return c_api_perf_to_int(&msg.as_ref().unwrap().msg.data.perf);
+    return i32::from(msg.as_ref().unwrap().msg.data.perf);
 }
 
 /// Get the message recipient. The AgentID returned by this function should
@@ -295,7 +295,7 @@ pub unsafe extern "C" fn fjage_msg_get_recipient(msg: *mut fjage_msg_t) -> *cons
     if recipient.is_empty() {
         return std::ptr::null();
     }
-    let recipient = msg.as_mut().unwrap().alloc_str(recipient.clone());
+    let recipient = msg.as_mut().unwrap().alloc_str(recipient.to_string());
     return recipient;
 }
 
@@ -310,7 +310,7 @@ pub unsafe extern "C" fn fjage_msg_get_recipient(msg: *mut fjage_msg_t) -> *cons
 #[no_mangle]
 pub unsafe extern "C" fn fjage_msg_get_sender(msg: *mut fjage_msg_t) -> *const c_char {
     let sender = &msg.as_ref().unwrap().msg.data.sender;
-    let sender = msg.as_mut().unwrap().alloc_str(sender.clone());
+    let sender = msg.as_mut().unwrap().alloc_str(sender.to_string());
     return sender;
 }
 
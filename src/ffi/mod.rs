@@ -3,9 +3,12 @@ use std::{
     time::Duration,
 };
 
-use util::{c_api_alloc_cstr, c_api_cstr_to_string, c_api_free_cstr, fjage_msg_t};
+use util::{
+    c_api_alloc_cstr, c_api_cstr_to_aid, c_api_cstr_to_string, c_api_free_cstr,
+    c_api_result_to_int, c_api_serial_connector, fjage_msg_t,
+};
 
-use crate::api::gateway::Gateway;
+use crate::{api::gateway::Gateway, core::aid::AgentID};
 
 pub mod message;
 pub mod param;
@@ -16,8 +19,36 @@ pub mod util;
 pub unsafe extern "C" fn fjage_tcp_open(hostname: *const c_char, port: c_int) -> *mut Gateway {
     let hostname = CStr::from_ptr(hostname);
     let hostname = String::from_utf8_lossy(hostname.to_bytes()).to_string();
-    let port: i32 = i32::from(port);
-    let gw = Box::new(Gateway::new_tcp(&hostname, port.try_into().unwrap()));
+    let Ok(port) = u16::try_from(port) else {
+        return std::ptr::null_mut();
+    };
+    let gw = Gateway::new_tcp(&hostname, port);
+    if gw.is_err() {
+        return std::ptr::null_mut();
+    }
+    let gw = Box::new(gw.unwrap());
+    let gw = Box::into_raw(gw);
+    return gw;
+}
+
+/* NOT IN BASE C API */
+/// Open a gateway to a fjåge master container via its WebSocket connector, at ws://hostname:port/ws.
+///
+/// @param hostname       Host name or IP address
+/// @param port           Port number of the web server
+/// @return               Gateway
+
+//fjage_gw_t fjage_ws_open(const char *hostname, int port);
+#[no_mangle]
+pub unsafe extern "C" fn fjage_ws_open(hostname: *const c_char, port: c_int) -> *mut Gateway {
+    let Ok(port) = u16::try_from(port) else {
+        return std::ptr::null_mut();
+    };
+    let gw = Gateway::new_ws(&c_api_cstr_to_string(hostname), port);
+    if gw.is_err() {
+        return std::ptr::null_mut();
+    }
+    let gw = Box::new(gw.unwrap());
     let gw = Box::into_raw(gw);
     return gw;
 }
@@ -36,10 +67,15 @@ pub unsafe extern "C" fn fjage_rs232_open(
     baud: c_int,
     settings: *const c_char,
 ) -> *mut Gateway {
-    let gw = Box::new(Gateway::new_serial(
-        &c_api_cstr_to_string(devname),
-        baud as u32,
-    ));
+    let conn = c_api_serial_connector(devname, baud, settings);
+    if conn.is_err() {
+        return std::ptr::null_mut();
+    }
+    let gw = Gateway::new(conn.unwrap());
+    if gw.is_err() {
+        return std::ptr::null_mut();
+    }
+    let gw = Box::new(gw.unwrap());
     let gw = Box::into_raw(gw);
     return gw;
 }
@@ -58,8 +94,13 @@ pub unsafe extern "C" fn fjage_rs232_wakeup(
     baud: c_int,
     settings: *const c_char,
 ) -> c_int {
-    // unimplemented!();
-    // Maybe we make this assert "alive"?
+    let conn = c_api_serial_connector(devname, baud, settings);
+    if conn.is_err() {
+        return -1;
+    }
+    if conn.unwrap().wakeup().is_err() {
+        return -1;
+    }
     return 0;
 }
 
@@ -72,7 +113,8 @@ pub unsafe extern "C" fn fjage_rs232_wakeup(
 //int fjage_close(fjage_gw_t gw);
 #[no_mangle]
 pub unsafe extern "C" fn fjage_close(gw: *mut Gateway) -> c_int {
-    let mut _gw = Box::from_raw(gw);
+    let gw = Box::from_raw(gw);
+    gw.close();
     return 0;
 }
 
@@ -88,7 +130,7 @@ pub unsafe extern "C" fn fjage_close(gw: *mut Gateway) -> c_int {
 pub unsafe extern "C" fn fjage_get_agent_id(gw: *mut Gateway) -> *const c_char {
     let aid = gw.as_mut().unwrap();
     let aid = aid.get_agent_id();
-    return c_api_alloc_cstr(aid);
+    return c_api_alloc_cstr(aid.to_string());
 }
 
 /// Subscribe to a topic.
@@ -100,8 +142,7 @@ pub unsafe extern "C" fn fjage_get_agent_id(gw: *mut Gateway) -> *const c_char {
 //int fjage_subscribe(fjage_gw_t gw, const fjage_aid_t topic);
 #[no_mangle]
 pub unsafe extern "C" fn fjage_subscribe(gw: *mut Gateway, topic: *const c_char) -> c_int {
-    gw.as_mut().unwrap().subscribe(&c_api_cstr_to_string(topic));
-    return 0;
+    return c_api_result_to_int(gw.as_mut().unwrap().subscribe(&c_api_cstr_to_aid(topic)));
 }
 
 /// Subscribe to an agent's default topic.
@@ -113,10 +154,11 @@ pub unsafe extern "C" fn fjage_subscribe(gw: *mut Gateway, topic: *const c_char)
 //int fjage_subscribe_agent(fjage_gw_t gw, const fjage_aid_t aid);
 #[no_mangle]
 pub unsafe extern "C" fn fjage_subscribe_agent(gw: *mut Gateway, aid: *const c_char) -> c_int {
-    gw.as_mut()
-        .unwrap()
-        .subscribe_agent(&c_api_cstr_to_string(aid));
-    return 0;
+    return c_api_result_to_int(
+        gw.as_mut()
+            .unwrap()
+            .subscribe_agent(&c_api_cstr_to_aid(aid)),
+    );
 }
 
 /// Unsubscribe from a topic.
@@ -128,10 +170,7 @@ pub unsafe extern "C" fn fjage_subscribe_agent(gw: *mut Gateway, aid: *const c_c
 //int fjage_unsubscribe(fjage_gw_t gw, const fjage_aid_t topic);
 #[no_mangle]
 pub unsafe extern "C" fn fjage_unsubscribe(gw: *mut Gateway, topic: *const c_char) -> c_int {
-    gw.as_mut()
-        .unwrap()
-        .unsubscribe(&c_api_cstr_to_string(topic));
-    return 0;
+    return c_api_result_to_int(gw.as_mut().unwrap().unsubscribe(&c_api_cstr_to_aid(topic)));
 }
 
 /// Check if a topic is subscribed to.
@@ -146,7 +185,7 @@ pub unsafe extern "C" fn fjage_is_subscribed(gw: *mut Gateway, topic: *const c_c
     return gw
         .as_mut()
         .unwrap()
-        .is_subscribed(&c_api_cstr_to_string(topic));
+        .is_subscribed(&c_api_cstr_to_aid(topic));
 }
 
 /// Find an agent providing a specified service. The AgentID returned by this function
@@ -166,10 +205,10 @@ pub unsafe extern "C" fn fjage_agent_for_service(
         .as_mut()
         .unwrap()
         .agent_for_service(&c_api_cstr_to_string(service));
-    if result.is_none() {
+    if result.is_err() {
         return std::ptr::null(); // C will recognize this as returning NULL (I hope)
     }
-    return c_api_alloc_cstr(result.unwrap());
+    return c_api_alloc_cstr(result.unwrap().to_string());
 }
 
 /// Find all agents providing a specified service. The list of agents is populated in an
@@ -194,13 +233,14 @@ pub unsafe extern "C" fn fjage_agents_for_service(
     let result = gw
         .as_mut()
         .unwrap()
-        .agents_for_service(&c_api_cstr_to_string(service));
+        .agents_for_service(&c_api_cstr_to_string(service))
+        .unwrap_or_default();
     if result.is_empty() {
         return 0;
     }
 
     for n in 0..std::cmp::min(max, result.len().try_into().unwrap()) {
-        let str = result.get(n as usize).unwrap();
+        let aid = result.get(n as usize).unwrap();
         if agents.is_null() {
             return result.len() as c_int;
         }
@@ -209,7 +249,7 @@ pub unsafe extern "C" fn fjage_agents_for_service(
 
         let curr_aid_ptr = curr_aid_ptr.add((n) as usize);
 
-        (*curr_aid_ptr) = c_api_alloc_cstr(String::from(str)).cast_mut();
+        (*curr_aid_ptr) = c_api_alloc_cstr(aid.to_string()).cast_mut();
     }
 
     return result.len() as c_int;
@@ -225,9 +265,7 @@ pub unsafe extern "C" fn fjage_agents_for_service(
 //int fjage_send(fjage_gw_t gw, const fjage_msg_t msg);
 #[no_mangle]
 pub unsafe extern "C" fn fjage_send(gw: *mut Gateway, msg: *mut fjage_msg_t) -> c_int {
-    fjage_msg_t::send(gw, msg); //auto-frees
-
-    return 0 as c_int;
+    return c_api_result_to_int(fjage_msg_t::send(gw, msg)); //auto-frees
 }
 
 /// Receive a message. The received message should be freed by the caller using fjage_msg_destroy().
@@ -266,7 +304,7 @@ pub unsafe extern "C" fn fjage_receive(
         Duration::from_millis(timeout as u64),
     );
 
-    if msg.is_none() {
+    if msg.is_err() {
         return std::ptr::null();
     }
     let boxed_msg = fjage_msg_t::alloc();
@@ -309,7 +347,7 @@ pub unsafe extern "C" fn fjage_receive_any(
         Duration::from_millis(timeout as u64),
     );
 
-    if msg.is_none() {
+    if msg.is_err() {
         return std::ptr::null();
     }
     let boxed_msg = fjage_msg_t::alloc();
@@ -362,7 +400,8 @@ pub unsafe extern "C" fn fjage_interrupt(gw: *mut Gateway) -> c_int {
 //fjage_aid_t fjage_aid_create(const char *name);
 #[no_mangle]
 pub unsafe extern "C" fn fjage_aid_create(name: *const c_char) -> *const c_char {
-    return c_api_alloc_cstr(c_api_cstr_to_string(name));
+    let aid = AgentID::agent(&c_api_cstr_to_string(name));
+    return c_api_alloc_cstr(aid.to_string());
 }
 
 /// Create an topic. The topic AgentID created using this function should be freed using
@@ -374,9 +413,8 @@ pub unsafe extern "C" fn fjage_aid_create(name: *const c_char) -> *const c_char
 //fjage_aid_t fjage_aid_topic(const char *topic);
 #[no_mangle]
 pub unsafe extern "C" fn fjage_aid_topic(topic: *const c_char) -> *const c_char {
-    let mut aid_topic = String::from("#");
-    aid_topic.push_str(&c_api_cstr_to_string(topic));
-    return c_api_alloc_cstr(aid_topic);
+    let aid = AgentID::topic(&c_api_cstr_to_string(topic));
+    return c_api_alloc_cstr(aid.to_string());
 }
 
 /// Destroy an AgentID. Once destroyed, the AgentID is considered invalid and should no
@@ -16,7 +16,7 @@ use serde_json::Value;
 
 use crate::{api::gateway::Gateway, core::param::ParameterManipulation};
 
-use super::util::{c_api_alloc_cstr, c_api_cstr_to_string, c_api_set_param};
+use super::util::{c_api_alloc_cstr, c_api_cstr_to_aid, c_api_cstr_to_string, c_api_set_param};
 
 //int fjage_param_get_int(fjage_gw_t gw, fjage_aid_t aid, const char *param, int ndx, int defval);
 #[no_mangle]
@@ -38,7 +38,7 @@ pub unsafe extern "C" fn fjage_param_get_int(
         return val.as_i64().unwrap() as c_int;
     }*/
     let val = gw.as_mut().unwrap().get_int(
-        &c_api_cstr_to_string(aid),
+        &c_api_cstr_to_aid(aid),
         &c_api_cstr_to_string(param),
         ndx as i64,
     );
@@ -65,7 +65,7 @@ pub unsafe extern "C" fn fjage_param_get_long(
     defval: i64,
 ) -> i64 {
     let val = gw.as_mut().unwrap().get_long(
-        &c_api_cstr_to_string(aid),
+        &c_api_cstr_to_aid(aid),
         &c_api_cstr_to_string(param),
         ndx as i64,
     );
@@ -92,7 +92,7 @@ pub unsafe extern "C" fn fjage_param_get_float(
     defval: c_float,
 ) -> c_float {
     let val = gw.as_mut().unwrap().get_float(
-        &c_api_cstr_to_string(aid),
+        &c_api_cstr_to_aid(aid),
         &c_api_cstr_to_string(param),
         ndx as i64,
     );
@@ -109,7 +109,7 @@ pub unsafe extern "C" fn fjage_param_get_double(
     defval: c_double,
 ) -> c_double {
     let val = gw.as_mut().unwrap().get_double(
-        &c_api_cstr_to_string(aid),
+        &c_api_cstr_to_aid(aid),
         &c_api_cstr_to_string(param),
         ndx as i64,
     );
@@ -138,7 +138,7 @@ pub unsafe extern "C" fn fjage_param_get_bool(
     // Rust does not let us treat integers as booleans
     let defval = defval == 1;
     let val = gw.as_mut().unwrap().get_bool(
-        &c_api_cstr_to_string(aid),
+        &c_api_cstr_to_aid(aid),
         &c_api_cstr_to_string(param),
         ndx as i64,
     );
@@ -168,7 +168,7 @@ pub unsafe extern "C" fn fjage_param_get_string(
     len: c_int,
 ) -> c_int {
     let val = gw.as_mut().unwrap().get_string(
-        &c_api_cstr_to_string(aid),
+        &c_api_cstr_to_aid(aid),
         &c_api_cstr_to_string(param),
         ndx as i64,
     );
@@ -200,7 +200,7 @@ pub unsafe extern "C" fn fjage_param_get_int_array(
     ndx: c_int,
 ) -> c_int {
     let val = gw.as_mut().unwrap().get_int_array(
-        &c_api_cstr_to_string(aid),
+        &c_api_cstr_to_aid(aid),
         &c_api_cstr_to_string(param),
         ndx as i64,
     );
@@ -226,7 +226,7 @@ pub unsafe extern "C" fn fjage_param_get_long_array(
     ndx: c_int,
 ) -> c_int {
     let val = gw.as_mut().unwrap().get_long_array(
-        &c_api_cstr_to_string(aid),
+        &c_api_cstr_to_aid(aid),
         &c_api_cstr_to_string(param),
         ndx as i64,
     );
@@ -252,7 +252,7 @@ pub unsafe extern "C" fn fjage_param_get_float_array(
     ndx: c_int,
 ) -> c_int {
     let val = gw.as_mut().unwrap().get_float_array(
-        &c_api_cstr_to_string(aid),
+        &c_api_cstr_to_aid(aid),
         &c_api_cstr_to_string(param),
         ndx as i64,
     );
@@ -278,7 +278,7 @@ pub unsafe extern "C" fn fjage_param_get_double_array(
     ndx: c_int,
 ) -> c_int {
     let val = gw.as_mut().unwrap().get_double_array(
-        &c_api_cstr_to_string(aid),
+        &c_api_cstr_to_aid(aid),
         &c_api_cstr_to_string(param),
         ndx as i64,
     );
@@ -305,7 +305,7 @@ pub unsafe extern "C" fn fjage_param_get_string_array(
     ndx: c_int,
 ) -> c_int {
     let val = gw.as_mut().unwrap().get_string_array(
-        &c_api_cstr_to_string(aid),
+        &c_api_cstr_to_aid(aid),
         &c_api_cstr_to_string(param),
         ndx as i64,
     );
@@ -506,7 +506,7 @@ pub unsafe extern "C" fn fjage_param_set_int_array(
     ndx: c_int,
 ) -> c_int {
     let result = gw.as_mut().unwrap().set_int_array(
-        &c_api_cstr_to_string(aid),
+        &c_api_cstr_to_aid(aid),
         &c_api_cstr_to_string(param),
         Vec::from(slice::from_raw_parts(value, len as usize)),
         ndx as i64,
@@ -531,7 +531,7 @@ pub unsafe extern "C" fn fjage_param_set_long_array(
     ndx: c_int,
 ) -> c_int {
     let result = gw.as_mut().unwrap().set_long_array(
-        &c_api_cstr_to_string(aid),
+        &c_api_cstr_to_aid(aid),
         &c_api_cstr_to_string(param),
         Vec::from(slice::from_raw_parts(value, len as usize)),
         ndx as i64,
@@ -555,7 +555,7 @@ pub unsafe extern "C" fn fjage_param_set_float_array(
     ndx: c_int,
 ) -> c_int {
     let result = gw.as_mut().unwrap().set_float_array(
-        &c_api_cstr_to_string(aid),
+        &c_api_cstr_to_aid(aid),
         &c_api_cstr_to_string(param),
         Vec::from(slice::from_raw_parts(value, len as usize)),
         ndx as i64,
@@ -581,7 +581,7 @@ pub unsafe extern "C" fn fjage_param_set_double_array(
 ) -> c_int {
     //unimplemented!();
     let result = gw.as_mut().unwrap().set_double_array(
-        &c_api_cstr_to_string(aid),
+        &c_api_cstr_to_aid(aid),
         &c_api_cstr_to_string(param),
         Vec::from(slice::from_raw_parts(value, len as usize)),
         ndx as i64,
@@ -1,5 +1,6 @@
 use std::{
     ffi::{c_char, c_int, c_long, CStr, CString},
+    io,
     time::Duration,
 };
 
@@ -8,9 +9,12 @@ use serde_json::Value;
 use crate::{
     api::gateway::Gateway,
     core::{
+        aid::AgentID,
+        error::GatewayError,
         message::{Message, Performative},
         param::ParameterManipulation,
     },
+    protocol::{base64::JavaType, connector::SerialPortConnector},
 };
 
 #[macro_export]
@@ -39,6 +43,31 @@ pub unsafe fn c_api_cstr_to_string(str: *const c_char) -> String {
     return String::from_utf8_lossy(str.to_bytes()).to_string();
 }
 
+/// Read an AgentID passed through the C API, where topics carry a leading `#`.
+pub unsafe fn c_api_cstr_to_aid(str: *const c_char) -> AgentID {
+    return AgentID::from(c_api_cstr_to_string(str));
+}
+
+/// Build a serial port connector from the arguments of the rs232 functions. A NULL settings string means "N81".
+pub unsafe fn c_api_serial_connector(
+    devname: *const c_char,
+    baud: c_int,
+    settings: *const c_char,
+) -> io::Result<SerialPortConnector> {
+    let Ok(baud) = u32::try_from(baud) else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid baud rate {}", baud),
+        ));
+    };
+    let settings = if settings.is_null() {
+        String::new()
+    } else {
+        c_api_cstr_to_string(settings)
+    };
+    return SerialPortConnector::with_settings(&c_api_cstr_to_string(devname), baud, &settings);
+}
+
 pub unsafe fn c_api_alloc_cstr(aid: String) -> *const c_char {
     let aid_ptr = CString::new(aid).unwrap();
     let aid_ptr = aid_ptr.into_boxed_c_str();
@@ -59,40 +88,13 @@ pub unsafe fn c_api_free_msg(msg: *mut Message) {
     println!("Deallocated a message");
 }
 
-pub unsafe fn c_api_perf_to_int(perf: &Performative) -> c_int {
-    return match perf {
-        Performative::NONE => 0,
-        Performative::REQUEST => 1,
-        Performative::AGREE => 2,
-        Performative::REFUSE => 3,
-        Performative::FAILURE => 4,
-        Performative::INFORM => 5,
-        Performative::CONFIRM => 6,
-        Performative::DISCONFIRM => 7,
-        Performative::QUERY_IF => 8,
-        Performative::NOT_UNDERSTOOD => 9,
-        Performative::CPF => 10,
-        Performative::PROPOSE => 11,
-        Performative::CANCEL => 12,
+/// Map a result onto the C API convention of 0 on success and an error code otherwise.
+pub fn c_api_result_to_int<T>(result: Result<T, GatewayError>) -> c_int {
+    return match result {
+        Ok(_) => 0,
+        Err(_) => -1,
     };
 }
-pub unsafe fn c_api_int_to_perf(perf: c_int) -> Performative {
-    match perf {
-        1 => Performative::REQUEST,
-        2 => Performative::AGREE,
-        3 => Performative::REFUSE,
-        4 => Performative::FAILURE,
-        5 => Performative::INFORM,
-        6 => Performative::CONFIRM,
-        7 => Performative::DISCONFIRM,
-        8 => Performative::QUERY_IF,
-        9 => Performative::NOT_UNDERSTOOD,
-        10 => Performative::CPF,
-        11 => Performative::PROPOSE,
-        12 => Performative::CANCEL,
-        _ => Performative::NONE,
-    }
-}
 
 #[allow(non_camel_case_types)]
 pub struct fjage_msg_t {
@@ -126,14 +128,14 @@ impl fjage_msg_t {
         }
         let _msg: Box<fjage_msg_t> = Box::from_raw(msg);
     }
-    pub unsafe fn send(gw: *mut Gateway, msg: *mut fjage_msg_t) {
-        gw.as_mut().unwrap().send(
-            fjage_msg_t::strkey_get(msg, "recipient")
-                .as_str()
-                .unwrap_or(""),
-            msg.as_mut().unwrap().msg.clone(),
-        );
+    pub unsafe fn send(gw: *mut Gateway, msg: *mut fjage_msg_t) -> Result<(), GatewayError> {
+        let recipient = msg.as_ref().unwrap().msg.data.recipient.clone();
+        let result = gw
+            .as_mut()
+            .unwrap()
+            .send(&recipient, msg.as_mut().unwrap().msg.clone());
         fjage_msg_t::free(msg);
+        return result;
     }
     pub unsafe fn request(
         gw: *mut Gateway,
@@ -148,11 +150,16 @@ impl fjage_msg_t {
         );
         fjage_msg_t::free(msg);
 
-        if rsp.is_none() {
-            return std::ptr::null();
-        }
+        // The C API hands REFUSE and FAILURE replies back to the caller like any other response
+        let rsp = match rsp {
+            Ok(rsp) => rsp,
+            Err(e) => match e.into_reply() {
+                Some(rsp) => rsp,
+                None => return std::ptr::null(),
+            },
+        };
         let boxed_msg = fjage_msg_t::alloc();
-        boxed_msg.as_mut().unwrap().msg = rsp.unwrap();
+        boxed_msg.as_mut().unwrap().msg = rsp;
         return boxed_msg;
     }
     pub unsafe fn set(msg: *mut fjage_msg_t, key: *const c_char, value: Value) {
@@ -166,16 +173,17 @@ impl fjage_msg_t {
                 data.msgID = value.as_str().unwrap().to_string();
             }
             "perf" => {
-                data.perf = c_api_int_to_perf(value.as_i64().unwrap() as c_int);
+                data.perf = Performative::try_from(value.as_i64().unwrap() as i32)
+                    .unwrap_or(Performative::NONE);
             }
             "recipient" => {
-                data.recipient = value.as_str().unwrap().to_string();
+                data.recipient = AgentID::from(value.as_str().unwrap());
             }
             "inReplyTo" => {
                 data.inReplyTo = Some(value.as_str().unwrap().to_string());
             }
             "sender" => {
-                data.sender = value.as_str().unwrap().to_string();
+                data.sender = AgentID::from(value.as_str().unwrap());
             }
             "data" => {
                 data.fields.insert("data".to_string(), value);
@@ -188,6 +196,17 @@ impl fjage_msg_t {
             }
         }
     }
+    /// Set an array field, to be sent as a base64-encoded Java array of type `java_type`
+    pub unsafe fn set_array(
+        msg: *mut fjage_msg_t,
+        key: *const c_char,
+        value: Value,
+        java_type: JavaType,
+    ) {
+        fjage_msg_t::set(msg, key, value);
+        let field = c_api_cstr_to_string(key);
+        msg.as_mut().unwrap().msg.set_java_type(&field, java_type);
+    }
     pub unsafe fn strkey_get(msg: *mut fjage_msg_t, key: &str) -> Value {
         let key = CString::new(key).unwrap();
         return fjage_msg_t::get(msg, key.as_ptr());
@@ -206,12 +225,12 @@ impl fjage_msg_t {
 
         match key.as_str() {
             "msgID" => Value::String(data.msgID.clone()),
-            "perf" => Value::Number(c_api_perf_to_int(&data.perf).into()),
+            "perf" => Value::from(i32::from(data.perf)),
             "recipient" => {
                 if data.recipient.is_empty() {
                     Value::Null
                 } else {
-                    Value::String(data.recipient.clone())
+                    Value::String(data.recipient.to_string())
                 }
             }
             "inReplyTo" => {
@@ -221,7 +240,7 @@ impl fjage_msg_t {
                     return Value::Null;
                 }
             }
-            "sender" => Value::String(data.sender.clone()),
+            "sender" => Value::String(data.sender.to_string()),
             "data" => data.fields.get("data").unwrap().clone(),
             "signal" => data.fields.get("signal").unwrap().clone(),
             _ => {
@@ -244,7 +263,7 @@ pub unsafe fn c_api_set_param(
     ndx: c_int,
 ) -> c_int {
     let val = gw.as_mut().unwrap().set_param(
-        &c_api_cstr_to_string(aid),
+        &c_api_cstr_to_aid(aid),
         &c_api_cstr_to_string(param),
         value.clone(),
         ndx as i64,
@@ -267,7 +286,7 @@ pub unsafe fn c_api_get_param(
     //    tokio::time::timeout(
     //        Duration::from_millis(1000),
     let val = gw.as_mut().unwrap().get_param(
-        &c_api_cstr_to_string(aid),
+        &c_api_cstr_to_aid(aid),
         &c_api_cstr_to_string(param),
         ndx as i64,
     );
@@ -1,3 +1,6 @@
+// Lets the code generated by `#[derive(FjageMessage)]` name this crate the same way inside and outside it
+extern crate self as fjage_rs;
+
 pub mod api;
 pub mod core;
 pub mod ffi;
@@ -1,4 +1,5 @@
 use base64::prelude::*;
+use num_complex::Complex32;
 use serde_json::{Map, Value};
 
 fn u8_to_f32_vec(v: &[u8]) -> Vec<f32> {
@@ -15,6 +16,13 @@ fn u8_to_i32_vec(v: &[u8]) -> Vec<i32> {
         .map(i32::from_le_bytes)
         .collect()
 }
+fn u8_to_i16_vec(v: &[u8]) -> Vec<i16> {
+    v.chunks_exact(2)
+        .map(TryInto::try_into)
+        .map(Result::unwrap)
+        .map(i16::from_le_bytes)
+        .collect()
+}
 fn u8_to_f64_vec(v: &[u8]) -> Vec<f64> {
     v.chunks_exact(8)
         .map(TryInto::try_into)
@@ -49,6 +57,23 @@ pub fn base64_to_i64(s: &str) -> Vec<i64> {
 pub fn base64_to_u8(s: &str) -> Vec<u8> {
     BASE64_STANDARD.decode(s).expect("Decode Failure")
 }
+pub fn base64_to_i16(s: &str) -> Vec<i16> {
+    let decoded = BASE64_STANDARD.decode(s).expect("Decode Failure");
+    u8_to_i16_vec(&decoded)
+}
+pub fn base64_to_bool(s: &str) -> Vec<bool> {
+    let decoded = BASE64_STANDARD.decode(s).expect("Decode Failure");
+    decoded.iter().map(|b| *b != 0).collect()
+}
+/// Java chars are UTF-16 code units
+pub fn base64_to_char(s: &str) -> String {
+    let decoded = BASE64_STANDARD.decode(s).expect("Decode Failure");
+    let units: Vec<u16> = decoded
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect();
+    String::from_utf16_lossy(&units)
+}
 
 fn f32_to_u8_vec(v: &[f32]) -> Vec<u8> {
     let x = v.into_iter();
@@ -60,6 +85,11 @@ fn i32_to_u8_vec(v: &[i32]) -> Vec<u8> {
     let x = x.map(|j| j.to_le_bytes()).flatten();
     x.collect()
 }
+fn i16_to_u8_vec(v: &[i16]) -> Vec<u8> {
+    let x = v.into_iter();
+    let x = x.map(|j| j.to_le_bytes()).flatten();
+    x.collect()
+}
 fn f64_to_u8_vec(v: &[f64]) -> Vec<u8> {
     let x = v.into_iter();
     let x = x.map(|j| j.to_le_bytes()).flatten();
@@ -83,6 +113,16 @@ pub fn base64_from_f64(v: &Vec<f64>) -> String {
 pub fn base64_from_i64(v: &Vec<i64>) -> String {
     BASE64_STANDARD.encode(i64_to_u8_vec(v))
 }
+pub fn base64_from_i16(v: &Vec<i16>) -> String {
+    BASE64_STANDARD.encode(i16_to_u8_vec(v))
+}
+pub fn base64_from_bool(v: &Vec<bool>) -> String {
+    BASE64_STANDARD.encode(v.iter().map(|b| *b as u8).collect::<Vec<u8>>())
+}
+pub fn base64_from_char(s: &str) -> String {
+    let x = s.encode_utf16().map(|j| j.to_le_bytes()).flatten();
+    BASE64_STANDARD.encode(x.collect::<Vec<u8>>())
+}
 
 pub fn b64_obj_from_u8(v: &Vec<u8>) -> Value {
     let mut obj: Map<String, Value> = Map::<String, Value>::new();
@@ -114,3 +154,130 @@ pub fn b64_obj_from_f64(v: &Vec<f64>) -> Value {
     obj.insert("data".to_string(), Value::from(base64_from_f64(v)));
     return Value::from(obj);
 }
+/// Pair up interleaved I/Q floats. A trailing unpaired float is dropped.
+pub fn complex32_from_interleaved(v: &[f32]) -> Vec<Complex32> {
+    v.chunks_exact(2)
+        .map(|c| Complex32::new(c[0], c[1]))
+        .collect()
+}
+pub fn interleaved_from_complex32(v: &[Complex32]) -> Vec<f32> {
+    v.iter().flat_map(|c| [c.re, c.im]).collect()
+}
+/// Decode a complex signal, sent by fjåge as a float array of interleaved I/Q samples
+pub fn base64_to_complex32(s: &str) -> Vec<Complex32> {
+    complex32_from_interleaved(&base64_to_f32(s))
+}
+pub fn base64_from_complex32(v: &[Complex32]) -> String {
+    base64_from_f32(&interleaved_from_complex32(v))
+}
+pub fn b64_obj_from_complex32(v: &[Complex32]) -> Value {
+    b64_obj_from_f32(&interleaved_from_complex32(v))
+}
+pub fn b64_obj_from_i16(v: &Vec<i16>) -> Value {
+    let mut obj: Map<String, Value> = Map::<String, Value>::new();
+    obj.insert("clazz".to_string(), Value::from("[S"));
+    obj.insert("data".to_string(), Value::from(base64_from_i16(v)));
+    return Value::from(obj);
+}
+pub fn b64_obj_from_bool(v: &Vec<bool>) -> Value {
+    let mut obj: Map<String, Value> = Map::<String, Value>::new();
+    obj.insert("clazz".to_string(), Value::from("[Z"));
+    obj.insert("data".to_string(), Value::from(base64_from_bool(v)));
+    return Value::from(obj);
+}
+pub fn b64_obj_from_char(s: &str) -> Value {
+    let mut obj: Map<String, Value> = Map::<String, Value>::new();
+    obj.insert("clazz".to_string(), Value::from("[C"));
+    obj.insert("data".to_string(), Value::from(base64_from_char(s)));
+    return Value::from(obj);
+}
+
+/// Java array classes that fjåge sends base64-encoded
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum JavaType {
+    BYTE_ARRAY,
+    SHORT_ARRAY,
+    INT_ARRAY,
+    LONG_ARRAY,
+    FLOAT_ARRAY,
+    DOUBLE_ARRAY,
+    BOOLEAN_ARRAY,
+    /// Decoded to, and encoded from, a string
+    CHAR_ARRAY,
+}
+impl JavaType {
+    pub fn clazz(&self) -> &'static str {
+        return match self {
+            JavaType::BYTE_ARRAY => "[B",
+            JavaType::SHORT_ARRAY => "[S",
+            JavaType::INT_ARRAY => "[I",
+            JavaType::LONG_ARRAY => "[J",
+            JavaType::FLOAT_ARRAY => "[F",
+            JavaType::DOUBLE_ARRAY => "[D",
+            JavaType::BOOLEAN_ARRAY => "[Z",
+            JavaType::CHAR_ARRAY => "[C",
+        };
+    }
+    pub fn from_clazz(clazz: &str) -> Option<JavaType> {
+        return match clazz {
+            "[B" => Some(JavaType::BYTE_ARRAY),
+            "[S" => Some(JavaType::SHORT_ARRAY),
+            "[I" => Some(JavaType::INT_ARRAY),
+            "[J" => Some(JavaType::LONG_ARRAY),
+            "[F" => Some(JavaType::FLOAT_ARRAY),
+            "[D" => Some(JavaType::DOUBLE_ARRAY),
+            "[Z" => Some(JavaType::BOOLEAN_ARRAY),
+            "[C" => Some(JavaType::CHAR_ARRAY),
+            _ => None,
+        };
+    }
+    /// Decode the base64 data of an array of this type into plain JSON
+    pub fn decode(&self, data: &str) -> Value {
+        // The decoders panic on malformed input, which a remote peer should not be able to cause
+        if BASE64_STANDARD.decode(data).is_err() {
+            return Value::Null;
+        }
+        return match self {
+            JavaType::BYTE_ARRAY => base64_to_u8(data).into(),
+            JavaType::SHORT_ARRAY => base64_to_i16(data).into(),
+            JavaType::INT_ARRAY => base64_to_i32(data).into(),
+            JavaType::LONG_ARRAY => base64_to_i64(data).into(),
+            JavaType::FLOAT_ARRAY => base64_to_f32(data).into(),
+            JavaType::DOUBLE_ARRAY => base64_to_f64(data).into(),
+            JavaType::BOOLEAN_ARRAY => base64_to_bool(data).into(),
+            JavaType::CHAR_ARRAY => base64_to_char(data).into(),
+        };
+    }
+    /// Encode plain JSON as a base64 object of this type. Returns `None` if the value does not fit the type.
+    pub fn encode(&self, value: &Value) -> Option<Value> {
+        if let (JavaType::CHAR_ARRAY, Some(s)) = (self, value.as_str()) {
+            return Some(b64_obj_from_char(s));
+        }
+        let items = value.as_array()?;
+        return Some(match self {
+            JavaType::BYTE_ARRAY => {
+                b64_obj_from_u8(&collect(items, |x| x.as_u64()?.try_into().ok())?)
+            }
+            JavaType::SHORT_ARRAY => {
+                b64_obj_from_i16(&collect(items, |x| x.as_i64()?.try_into().ok())?)
+            }
+            JavaType::INT_ARRAY => {
+                b64_obj_from_i32(&collect(items, |x| x.as_i64()?.try_into().ok())?)
+            }
+            JavaType::LONG_ARRAY => b64_obj_from_i64(&collect(items, Value::as_i64)?),
+            JavaType::FLOAT_ARRAY => {
+                b64_obj_from_f32(&collect(items, |x| Some(x.as_f64()? as f32))?)
+            }
+            JavaType::DOUBLE_ARRAY => b64_obj_from_f64(&collect(items, Value::as_f64)?),
+            JavaType::BOOLEAN_ARRAY => b64_obj_from_bool(&collect(items, Value::as_bool)?),
+            JavaType::CHAR_ARRAY => {
+                let chars: Option<String> = items.iter().map(|x| x.as_str()).collect();
+                b64_obj_from_char(&chars?)
+            }
+        });
+    }
+}
+fn collect<T, F: Fn(&Value) -> Option<T>>(items: &[Value], f: F) -> Option<Vec<T>> {
+    return items.iter().map(f).collect();
+}
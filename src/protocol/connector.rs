@@ -1,166 +1,424 @@
 //use std::sync::mpsc;
 
+use std::future::Future;
+use std::io::Write;
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use futures_util::{Sink, SinkExt, Stream, StreamExt};
 use tokio::{
+    io::DuplexStream,
     io::{self, AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, BufWriter},
     net::TcpStream,
-    sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    sync::mpsc::{self, Receiver, Sender},
 };
 
+#[cfg(unix)]
+use tokio::net::UnixStream;
+use tokio_serial::SerialPortBuilder;
+pub use tokio_serial::{DataBits, FlowControl, Parity, StopBits};
+use tokio_tungstenite::tungstenite::{self, Message as WsMessage};
+
 use super::frame::Frame;
 
+/// A transport to a fjåge master container. `connect` may be called again after the link drops,
+/// so implementations should open a fresh stream on every call. When the link is lost, the
+/// returned receiver yields `None` and the sender starts failing.
 pub trait Connector {
-    async fn connect(&self) -> (UnboundedSender<Frame>, UnboundedReceiver<Frame>);
+    fn connect(&self) -> impl Future<Output = io::Result<Link>> + Send;
 }
 
-pub struct TcpConnector {
-    hostname: String,
-    port: u16,
+/// The two ends of a link to the master container, as returned by [Connector::connect]. Both directions hold at most
+/// [LINK_CAPACITY] frames, so a slow transport makes the sending side wait instead of buffering without limit.
+pub type Link = (Sender<Frame>, Receiver<Frame>);
+
+/// Frames buffered in each direction of a [Link]
+pub const LINK_CAPACITY: usize = 64;
+
+/// Speaks the line-based JSON protocol over any byte stream. The TCP, serial port and Unix socket
+/// connectors open a stream and hand it to [StreamConnector::spawn]; used directly, a
+/// `StreamConnector` wraps an already open stream and can only be connected once.
+pub struct StreamConnector<T> {
+    name: &'static str,
+    stream: std::sync::Mutex<Option<T>>,
 }
 
-impl TcpConnector {
-    pub fn new(hostname: &str, port: u16) -> TcpConnector {
-        TcpConnector {
-            hostname: String::from(hostname),
-            port: port,
+impl<T: AsyncRead + AsyncWrite + Send + 'static> StreamConnector<T> {
+    pub fn new(stream: T) -> StreamConnector<T> {
+        return StreamConnector::with_name("StreamConnector", stream);
+    }
+    /// Like [StreamConnector::new], naming the connector in log output.
+    pub fn with_name(name: &'static str, stream: T) -> StreamConnector<T> {
+        StreamConnector {
+            name: name,
+            stream: std::sync::Mutex::new(Some(stream)),
         }
     }
-    async fn read_task<T: AsyncRead + Unpin>(stream: T, mut sender: UnboundedSender<Frame>) {
-        let mut reader = BufReader::new(stream);
-        loop {
-            let mut line_in = String::new();
-            let bytes_read = reader.read_line(&mut line_in).await;
-            if (bytes_read.is_err()) {
-                panic!("Error: TcpConnector could not read: {:?}", bytes_read.err());
-            }
-            let bytes_read = bytes_read.unwrap();
-            if (bytes_read == 0) {
-                panic!("Error: TcpConnector reached EOF!");
+    /// Start exchanging frames over `stream`. `name` identifies the connector in log output.
+    pub fn spawn(name: &'static str, stream: T) -> Link {
+        let (rstream, wstream) = io::split(stream);
+
+        let (client_to_conn, conn_from_client): (Sender<Frame>, Receiver<Frame>) =
+            mpsc::channel(LINK_CAPACITY);
+        let (conn_to_client, client_from_conn): (Sender<Frame>, Receiver<Frame>) =
+            mpsc::channel(LINK_CAPACITY);
+
+        // Run both halves in one task, so that losing either side tears down the whole link
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = read_task(name, rstream, conn_to_client) => (),
+                _ = write_task(name, wstream, conn_from_client) => (),
             }
-            let frame = Frame::from_json(&line_in);
-            if frame.is_some() {
-                let frame = frame.unwrap();
-                //println!("\nTcpConnector << Remote: {:?}", frame);
-                sender.send(frame).unwrap();
-            } else {
-                println!("Error: TcpConnector could not parse frame: {:?}", line_in);
+        });
+
+        return (client_to_conn, client_from_conn);
+    }
+}
+impl<T: AsyncRead + AsyncWrite + Send + 'static> Connector for StreamConnector<T> {
+    async fn connect(&self) -> io::Result<Link> {
+        let stream = self.stream.lock().unwrap().take();
+        match stream {
+            Some(stream) => return Ok(StreamConnector::spawn(self.name, stream)),
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    format!("{} cannot reopen its stream", self.name),
+                ))
             }
         }
     }
-    async fn write_task<T: AsyncWrite + Unpin>(stream: T, mut receiver: UnboundedReceiver<Frame>) {
-        let mut writer = BufWriter::new(stream);
-        loop {
-            let frame = receiver.recv().await;
-            if frame.is_none() {
-                println!("Error: Receive channel closed for TcpConnector!");
-                continue;
+}
+
+async fn read_task<T: AsyncRead + Unpin>(name: &'static str, stream: T, sender: Sender<Frame>) {
+    let mut reader = BufReader::new(stream);
+    loop {
+        let mut line_in = String::new();
+        match reader.read_line(&mut line_in).await {
+            Ok(0) => {
+                println!("Error: {} reached EOF!", name);
+                return;
+            }
+            Ok(_) => (),
+            Err(e) => {
+                println!("Error: {} could not read: {:?}", name, e);
+                return;
+            }
+        }
+        if line_in.trim().is_empty() {
+            continue;
+        }
+        match Frame::from_json(&line_in) {
+            Ok(frame) => {
+                //println!("\n{} << Remote: {:?}", name, frame);
+                if sender.send(frame).await.is_err() {
+                    // Nobody is listening anymore, so there is no point in keeping the link up
+                    return;
+                }
+            }
+            Err(e) => {
+                println!("Error: {} skipping bad frame: {}", name, e);
             }
-            let mut frame = frame.unwrap();
-            //println!("\nTcpConnector >> Remote: {:?}", frame);
-            writer.write_all(frame.to_json().as_bytes()).await.unwrap();
-            writer.write_all(b"\n").await.unwrap();
-            writer.flush().await.unwrap();
         }
     }
 }
-impl Connector for TcpConnector {
-    async fn connect(&self) -> (UnboundedSender<Frame>, UnboundedReceiver<Frame>) {
-        let input_stream = TcpStream::connect(format!("{}:{}", self.hostname, self.port))
-            .await
-            .unwrap();
-
-        let (rstream, wstream) = io::split(input_stream);
-
-        let (client_to_conn, conn_from_client): (UnboundedSender<Frame>, UnboundedReceiver<Frame>) =
-            mpsc::unbounded_channel();
-        let (conn_to_client, client_from_conn): (UnboundedSender<Frame>, UnboundedReceiver<Frame>) =
-            mpsc::unbounded_channel();
+async fn write_task<T: AsyncWrite + Unpin>(
+    name: &'static str,
+    stream: T,
+    mut receiver: Receiver<Frame>,
+) {
+    let mut writer = BufWriter::new(stream);
+    while let Some(mut frame) = receiver.recv().await {
+        //println!("\n{} >> Remote: {:?}", name, frame);
+        let written = async {
+            writer.write_all(frame.to_json().as_bytes()).await?;
+            writer.write_all(b"\n").await?;
+            writer.flush().await
+        }
+        .await;
+        if let Err(e) = written {
+            println!("Error: {} could not write: {:?}", name, e);
+            return;
+        }
+    }
+}
 
-        tokio::spawn(async move {
-            TcpConnector::read_task(rstream, conn_to_client).await;
-        });
-        tokio::spawn(async move {
-            TcpConnector::write_task(wstream, conn_from_client).await;
-        });
+pub struct TcpConnector {
+    hostname: String,
+    port: u16,
+}
 
-        return (client_to_conn, client_from_conn);
+impl TcpConnector {
+    pub fn new(hostname: &str, port: u16) -> TcpConnector {
+        TcpConnector {
+            hostname: String::from(hostname),
+            port: port,
+        }
     }
 }
+impl Connector for TcpConnector {
+    async fn connect(&self) -> io::Result<Link> {
+        let stream = TcpStream::connect(format!("{}:{}", self.hostname, self.port)).await?;
+        return Ok(StreamConnector::spawn("TcpConnector", stream));
+    }
+}
+
+/// Bytes sent by [SerialPortConnector::wakeup]. Blank lines are skipped by the fjåge line protocol,
+/// so the sequence is harmless if the device was already awake.
+pub const RS232_WAKEUP_SEQUENCE: &[u8] = b"\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n";
+/// Time given to the device to come up after the wakeup sequence has been sent.
+pub const RS232_WAKEUP_DELAY: Duration = Duration::from_secs(1);
 
 pub struct SerialPortConnector {
     dev: String,
     baud: u32,
+    data_bits: DataBits,
+    parity: Parity,
+    stop_bits: StopBits,
+    flow_control: FlowControl,
 }
 
 impl SerialPortConnector {
+    /// Open `dev` at `baud` with 8 data bits, no parity, 1 stop bit and no flow control.
     pub fn new(dev: &str, baud: u32) -> SerialPortConnector {
         SerialPortConnector {
             dev: String::from(dev),
             baud: baud,
+            data_bits: DataBits::Eight,
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+            flow_control: FlowControl::None,
         }
     }
-    async fn read_task<T: AsyncRead + Unpin>(stream: T, mut sender: UnboundedSender<Frame>) {
-        let mut reader = BufReader::new(stream);
-        loop {
-            let mut line_in = String::new();
-            let bytes_read = reader.read_line(&mut line_in).await;
-            if (bytes_read.is_err()) {
-                panic!(
-                    "Error: SerialPortConnector could not read: {:?}",
-                    bytes_read.err()
-                );
-            }
-            let bytes_read = bytes_read.unwrap();
-            if (bytes_read == 0) {
-                panic!("Error: SerialPortConnector reached EOF!");
-            }
-            let frame = Frame::from_json(&line_in);
-            if frame.is_some() {
-                let frame = frame.unwrap();
-                //println!("\nTcpConnector << Remote: {:?}", frame);
-                sender.send(frame).unwrap();
-            } else {
-                println!(
-                    "Error: SerialPortConnector could not parse frame: {:?}",
-                    line_in
-                );
+    /// Open `dev` at `baud` with framing given as a settings string such as "N81", "E72" or "O82".
+    pub fn with_settings(dev: &str, baud: u32, settings: &str) -> io::Result<SerialPortConnector> {
+        let (parity, data_bits, stop_bits) = SerialPortConnector::parse_settings(settings)?;
+        let mut conn = SerialPortConnector::new(dev, baud);
+        conn.parity = parity;
+        conn.data_bits = data_bits;
+        conn.stop_bits = stop_bits;
+        return Ok(conn);
+    }
+    /// Use hardware (RTS/CTS) or software (XON/XOFF) flow control.
+    pub fn flow_control(mut self, flow_control: FlowControl) -> SerialPortConnector {
+        self.flow_control = flow_control;
+        return self;
+    }
+    /// Parse an RS-232 settings string of the form used by the fjåge C API: a parity letter
+    /// (N, E or O), the number of data bits (5-8) and the number of stop bits (1 or 2).
+    /// An empty string means "N81".
+    pub fn parse_settings(settings: &str) -> io::Result<(Parity, DataBits, StopBits)> {
+        let settings = settings.trim();
+        if settings.is_empty() {
+            return Ok((Parity::None, DataBits::Eight, StopBits::One));
+        }
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid RS-232 settings \"{}\"", settings),
+            )
+        };
+        let chars: Vec<char> = settings.chars().collect();
+        if chars.len() != 3 {
+            return Err(invalid());
+        }
+        let parity = match chars[0].to_ascii_uppercase() {
+            'N' => Parity::None,
+            'E' => Parity::Even,
+            'O' => Parity::Odd,
+            _ => return Err(invalid()),
+        };
+        let data_bits = match chars[1] {
+            '5' => DataBits::Five,
+            '6' => DataBits::Six,
+            '7' => DataBits::Seven,
+            '8' => DataBits::Eight,
+            _ => return Err(invalid()),
+        };
+        let stop_bits = match chars[2] {
+            '1' => StopBits::One,
+            '2' => StopBits::Two,
+            _ => return Err(invalid()),
+        };
+        return Ok((parity, data_bits, stop_bits));
+    }
+    fn builder(&self) -> SerialPortBuilder {
+        return tokio_serial::new(self.dev.clone(), self.baud)
+            .data_bits(self.data_bits)
+            .parity(self.parity)
+            .stop_bits(self.stop_bits)
+            .flow_control(self.flow_control);
+    }
+    /// Wake up a sleeping device by sending [RS232_WAKEUP_SEQUENCE], then give it
+    /// [RS232_WAKEUP_DELAY] to come up. This is the counterpart of `fjage_rs232_wakeup` in the
+    /// fjåge C gateway (`gateways/c/fjage.c`), which it backs in the C API.
+    ///
+    /// Blocks the calling thread for the whole delay, so async code should call [SerialPortConnector::wakeup_async] instead.
+    pub fn wakeup(&self) -> io::Result<()> {
+        let mut port = self.builder().open()?;
+        port.write_all(RS232_WAKEUP_SEQUENCE)?;
+        port.flush()?;
+        drop(port);
+        std::thread::sleep(RS232_WAKEUP_DELAY);
+        return Ok(());
+    }
+    /// Like [SerialPortConnector::wakeup], but waits without blocking the runtime.
+    pub async fn wakeup_async(&self) -> io::Result<()> {
+        let mut port = tokio_serial::SerialStream::open(&self.builder())?;
+        AsyncWriteExt::write_all(&mut port, RS232_WAKEUP_SEQUENCE).await?;
+        AsyncWriteExt::flush(&mut port).await?;
+        drop(port);
+        tokio::time::sleep(RS232_WAKEUP_DELAY).await;
+        return Ok(());
+    }
+}
+impl Connector for SerialPortConnector {
+    async fn connect(&self) -> io::Result<Link> {
+        let sp = tokio_serial::SerialStream::open(&self.builder())?;
+        return Ok(StreamConnector::spawn("SerialPortConnector", sp));
+    }
+}
+
+/// Connects to a fjåge master listening on a Unix domain socket.
+#[cfg(unix)]
+pub struct UnixSocketConnector {
+    path: PathBuf,
+}
+
+#[cfg(unix)]
+impl UnixSocketConnector {
+    pub fn new<P: AsRef<Path>>(path: P) -> UnixSocketConnector {
+        UnixSocketConnector {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+}
+#[cfg(unix)]
+impl Connector for UnixSocketConnector {
+    async fn connect(&self) -> io::Result<Link> {
+        let stream = UnixStream::connect(&self.path).await?;
+        return Ok(StreamConnector::spawn("UnixSocketConnector", stream));
+    }
+}
+
+/// An in-memory link, with the master at the other end of a [tokio::io::duplex] pipe.
+/// Like any [StreamConnector], it cannot reconnect once the pipe is closed, so pair it with
+/// [ReconnectPolicy::never](crate::remote::container::ReconnectPolicy::never).
+pub struct DuplexConnector {
+    inner: StreamConnector<DuplexStream>,
+}
+
+impl DuplexConnector {
+    pub fn new(stream: DuplexStream) -> DuplexConnector {
+        DuplexConnector {
+            inner: StreamConnector::with_name("DuplexConnector", stream),
+        }
+    }
+    /// Create a connector together with the stream for the master's end of the pipe.
+    pub fn pair(max_buf_size: usize) -> (DuplexConnector, DuplexStream) {
+        let (ours, theirs) = io::duplex(max_buf_size);
+        return (DuplexConnector::new(ours), theirs);
+    }
+}
+impl Connector for DuplexConnector {
+    async fn connect(&self) -> io::Result<Link> {
+        return self.inner.connect().await;
+    }
+}
+
+/// Connects to the WebSocket connector of a fjåge master, as used by the fjåge web shell.
+/// Frames are the same JSON lines used over TCP, carried in WebSocket text messages.
+pub struct WebSocketConnector {
+    url: String,
+}
+
+impl WebSocketConnector {
+    /// Connect to `ws://hostname:port/ws`, where fjåge serves its WebSocket connector by default.
+    pub fn new(hostname: &str, port: u16) -> WebSocketConnector {
+        return WebSocketConnector::from_url(&format!("ws://{}:{}/ws", hostname, port));
+    }
+    pub fn from_url(url: &str) -> WebSocketConnector {
+        WebSocketConnector {
+            url: String::from(url),
+        }
+    }
+    async fn read_task<T: Stream<Item = Result<WsMessage, tungstenite::Error>> + Unpin>(
+        mut stream: T,
+        sender: Sender<Frame>,
+    ) {
+        while let Some(msg) = stream.next().await {
+            let text = match msg {
+                Ok(WsMessage::Text(text)) => text,
+                Ok(WsMessage::Binary(data)) => String::from_utf8_lossy(&data).to_string(),
+                Ok(WsMessage::Close(_)) => {
+                    println!("Error: WebSocketConnector connection closed by remote!");
+                    return;
+                }
+                // Pings are answered by tungstenite itself
+                Ok(_) => continue,
+                Err(e) => {
+                    println!("Error: WebSocketConnector could not read: {:?}", e);
+                    return;
+                }
+            };
+            // A single WebSocket message may carry several newline-terminated frames
+            for line_in in text.lines() {
+                if line_in.trim().is_empty() {
+                    continue;
+                }
+                match Frame::from_json(line_in) {
+                    Ok(frame) => {
+                        //println!("\nWebSocketConnector << Remote: {:?}", frame);
+                        if sender.send(frame).await.is_err() {
+                            // Nobody is listening anymore, so there is no point in keeping the link up
+                            return;
+                        }
+                    }
+                    Err(e) => {
+                        println!("Error: WebSocketConnector skipping bad frame: {}", e);
+                    }
+                }
             }
         }
+        println!("Error: WebSocketConnector reached EOF!");
     }
-    async fn write_task<T: AsyncWrite + Unpin>(stream: T, mut receiver: UnboundedReceiver<Frame>) {
-        let mut writer = BufWriter::new(stream);
-        loop {
-            let frame = receiver.recv().await;
-            if frame.is_none() {
-                println!("Error: Receive channel closed for TcpConnector!");
-                continue;
+    async fn write_task<T: Sink<WsMessage, Error = tungstenite::Error> + Unpin>(
+        mut sink: T,
+        mut receiver: Receiver<Frame>,
+    ) {
+        while let Some(mut frame) = receiver.recv().await {
+            //println!("\nWebSocketConnector >> Remote: {:?}", frame);
+            let mut json = frame.to_json();
+            json.push('\n');
+            if let Err(e) = sink.send(WsMessage::Text(json)).await {
+                println!("Error: WebSocketConnector could not write: {:?}", e);
+                return;
             }
-            let mut frame = frame.unwrap();
-            //println!("\nTcpConnector >> Remote: {:?}", frame);
-            writer.write_all(frame.to_json().as_bytes()).await.unwrap();
-            writer.write_all(b"\n").await.unwrap();
-            writer.flush().await.unwrap();
         }
+        let _ = sink.close().await;
     }
 }
-impl Connector for SerialPortConnector {
-    async fn connect(&self) -> (UnboundedSender<Frame>, UnboundedReceiver<Frame>) {
-        let sp = tokio_serial::new(self.dev.clone(), self.baud);
-        let sp = tokio_serial::SerialStream::open(&sp).unwrap();
+impl Connector for WebSocketConnector {
+    async fn connect(&self) -> io::Result<Link> {
+        let (ws, _) = tokio_tungstenite::connect_async(self.url.as_str())
+            .await
+            .map_err(io::Error::other)?;
 
-        let (rstream, wstream) = io::split(sp);
+        let (wstream, rstream) = ws.split();
 
-        let (client_to_conn, conn_from_client): (UnboundedSender<Frame>, UnboundedReceiver<Frame>) =
-            mpsc::unbounded_channel();
-        let (conn_to_client, client_from_conn): (UnboundedSender<Frame>, UnboundedReceiver<Frame>) =
-            mpsc::unbounded_channel();
+        let (client_to_conn, conn_from_client): (Sender<Frame>, Receiver<Frame>) =
+            mpsc::channel(LINK_CAPACITY);
+        let (conn_to_client, client_from_conn): (Sender<Frame>, Receiver<Frame>) =
+            mpsc::channel(LINK_CAPACITY);
 
+        // Run both halves in one task, so that losing either side tears down the whole link
         tokio::spawn(async move {
-            SerialPortConnector::read_task(rstream, conn_to_client).await;
-        });
-        tokio::spawn(async move {
-            SerialPortConnector::write_task(wstream, conn_from_client).await;
+            tokio::select! {
+                _ = WebSocketConnector::read_task(rstream, conn_to_client) => (),
+                _ = WebSocketConnector::write_task(wstream, conn_from_client) => (),
+            }
         });
 
-        return (client_to_conn, client_from_conn);
+        return Ok((client_to_conn, client_from_conn));
     }
 }
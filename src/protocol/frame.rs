@@ -1,10 +1,42 @@
+use std::{error::Error, fmt};
+
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-use crate::core::message::Message;
+use crate::core::{aid::AgentID, message::Message};
 
 //use crate::protocol::message::*;
 
+/// Reasons a line received from the master could not be turned into a [Frame]
+#[derive(Debug)]
+pub enum FrameError {
+    /// The line is not valid JSON
+    Json(serde_json::Error),
+    /// The line is valid JSON, but not a JSON object
+    NotAnObject(Value),
+    /// A recognised action or response whose fields do not match the protocol
+    Malformed { frame: Value, reason: String },
+}
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameError::Json(e) => write!(f, "invalid JSON: {}", e),
+            FrameError::NotAnObject(v) => write!(f, "frame is not a JSON object: {}", v),
+            FrameError::Malformed { frame, reason } => {
+                write!(f, "malformed frame ({}): {}", reason, frame)
+            }
+        }
+    }
+}
+impl Error for FrameError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            FrameError::Json(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
 #[allow(non_camel_case_types, non_snake_case)]
 #[derive(Serialize, Deserialize, Debug, Clone)]
 
@@ -15,22 +47,49 @@ pub enum Frame {
     Request(RequestFrame),
     #[serde(untagged)]
     Response(ResponseFrame),
+    /// A well-formed JSON object that is not a frame we understand, such as an action added in a newer fjåge.
+    /// The raw JSON is kept so that it can be logged or forwarded.
+    #[serde(untagged)]
+    Unknown(Value),
 }
 impl Frame {
-    pub fn from_json(frame: &str) -> Option<Frame> {
-        let frame: Result<Value, _> = serde_json::from_str(frame);
-        if frame.is_err() {
-            return None;
+    pub fn from_json(frame: &str) -> Result<Frame, FrameError> {
+        let frame: Value = serde_json::from_str(frame).map_err(FrameError::Json)?;
+        if !frame.is_object() {
+            return Err(FrameError::NotAnObject(frame));
         }
-        let frame = frame.unwrap();
-        if frame.get("action").is_some() {
-            return Some(Frame::Request(serde_json::from_value(frame).unwrap()));
-        } else if frame.get("inResponseTo").is_some() {
-            return Some(Frame::Response(serde_json::from_value(frame).unwrap()));
-        } else if frame.get("alive").is_some() {
-            return Some(Frame::Alive(frame.get("alive").unwrap().as_bool().unwrap()));
+        if let Some(action) = frame.get("action") {
+            if !action.as_str().is_some_and(RequestFrame::is_known_action) {
+                return Ok(Frame::Unknown(frame));
+            }
+            return match serde_json::from_value(frame.clone()) {
+                Ok(req) => Ok(Frame::Request(req)),
+                Err(e) => Err(FrameError::Malformed {
+                    frame: frame,
+                    reason: e.to_string(),
+                }),
+            };
+        } else if let Some(action) = frame.get("inResponseTo") {
+            if !action.as_str().is_some_and(ResponseFrame::is_known_action) {
+                return Ok(Frame::Unknown(frame));
+            }
+            return match serde_json::from_value(frame.clone()) {
+                Ok(rsp) => Ok(Frame::Response(rsp)),
+                Err(e) => Err(FrameError::Malformed {
+                    frame: frame,
+                    reason: e.to_string(),
+                }),
+            };
+        } else if let Some(alive) = frame.get("alive") {
+            return match alive.as_bool() {
+                Some(alive) => Ok(Frame::Alive(alive)),
+                None => Err(FrameError::Malformed {
+                    frame: frame.clone(),
+                    reason: "alive is not a boolean".to_string(),
+                }),
+            };
         } else {
-            return None;
+            return Ok(Frame::Unknown(frame));
         }
     }
     pub fn to_json(&mut self) -> String {
@@ -53,6 +112,10 @@ impl Frame {
             Frame::Alive(_) => None,
             Frame::Request(req) => req.id(),
             Frame::Response(rsp) => Some(rsp.id()),
+            Frame::Unknown(frame) => match frame.get("id") {
+                Some(Value::String(id)) => Some(id),
+                _ => None,
+            },
         }
     }
 }
@@ -66,7 +129,7 @@ pub enum RequestFrame {
     },
     containsAgent {
         id: String,
-        agentID: String,
+        agentID: AgentID,
     },
     services {
         id: String,
@@ -79,17 +142,41 @@ pub enum RequestFrame {
         id: String,
         service: String,
     },
+    /// Deliver a message. With `relay` set, the receiving container also forwards the message to any other
+    /// containers that want messages for its recipient.
     send {
         message: Message,
         #[serde(default)]
         relay: bool,
     },
-    // shutdown,
+    /// The peer is shutting down and the connection should be torn down.
+    shutdown,
+    /// Declare the agents and topics the sender wants messages for. Replaces any previous declaration.
     wantsMessagesFor {
-        agentIDs: Vec<String>,
+        agentIDs: Vec<AgentID>,
+    },
+    /// Authenticate the connection with the supplied credentials.
+    auth {
+        id: String,
+        creds: String,
     },
 }
 impl RequestFrame {
+    /// Actions this version of the protocol implementation can decode
+    pub const ACTIONS: &'static [&'static str] = &[
+        "agents",
+        "containsAgent",
+        "services",
+        "agentForService",
+        "agentsForService",
+        "send",
+        "shutdown",
+        "wantsMessagesFor",
+        "auth",
+    ];
+    pub fn is_known_action(action: &str) -> bool {
+        return RequestFrame::ACTIONS.contains(&action);
+    }
     pub fn id(&self) -> Option<&String> {
         match self {
             RequestFrame::agents { id } => Some(id),
@@ -97,6 +184,7 @@ impl RequestFrame {
             RequestFrame::services { id } => Some(id),
             RequestFrame::agentForService { id, service: _ } => Some(id),
             RequestFrame::agentsForService { id, service: _ } => Some(id),
+            RequestFrame::auth { id, creds: _ } => Some(id),
 
             _ => None,
         }
@@ -107,26 +195,61 @@ impl RequestFrame {
 #[serde(tag = "inResponseTo")]
 /* If a message contains the "inResponseTo" field, we define it as a response and match against outstanding requests. */
 pub enum ResponseFrame {
-    agents { id: String, agentIDs: Vec<String> }, // Need to ask Chinmay about this and services
-    containsAgent { id: String, answer: bool },
-    services { id: String, services: Vec<String> },
-    agentForService { id: String, agentID: Option<String> }, // Error case?
-    agentsForService { id: String, agentIDs: Vec<String> },
-    // shutdown ?
+    agents {
+        id: String,
+        agentIDs: Vec<AgentID>,
+        /// Class names of the agents in `agentIDs`, if the peer reports them
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        agentTypes: Option<Vec<String>>,
+    },
+    containsAgent {
+        id: String,
+        answer: bool,
+    },
+    services {
+        id: String,
+        services: Vec<String>,
+    },
+    agentForService {
+        id: String,
+        agentID: Option<AgentID>,
+    },
+    agentsForService {
+        id: String,
+        agentIDs: Vec<AgentID>,
+    },
+    auth {
+        id: String,
+        auth: bool,
+    },
+    // shutdown, send and wantsMessagesFor are never answered
 }
 impl ResponseFrame {
+    /// Responses this version of the protocol implementation can decode
+    pub const ACTIONS: &'static [&'static str] = &[
+        "agents",
+        "containsAgent",
+        "services",
+        "agentForService",
+        "agentsForService",
+        "auth",
+    ];
+    pub fn is_known_action(action: &str) -> bool {
+        return ResponseFrame::ACTIONS.contains(&action);
+    }
     pub fn id(&self) -> &String {
         match self {
-            ResponseFrame::agents { id, agentIDs: _ } => id,
+            ResponseFrame::agents { id, .. } => id,
             ResponseFrame::containsAgent { id, answer: _ } => id,
             ResponseFrame::services { id, services: _ } => id,
             ResponseFrame::agentForService { id, agentID: _ } => id,
             ResponseFrame::agentsForService { id, agentIDs: _ } => id,
+            ResponseFrame::auth { id, auth: _ } => id,
         }
     }
-    pub fn get_agent_id(&self) -> Option<String> {
+    pub fn get_agent_id(&self) -> Option<AgentID> {
         match self {
-            ResponseFrame::agents { id: _, agentIDs } => Some(agentIDs.first().unwrap().clone()),
+            ResponseFrame::agents { agentIDs, .. } => agentIDs.first().cloned(),
             ResponseFrame::agentForService { id: _, agentID } => {
                 if agentID.is_some() {
                     Some(agentID.clone().unwrap())
@@ -134,15 +257,13 @@ impl ResponseFrame {
                     None
                 }
             }
-            ResponseFrame::agentsForService { id: _, agentIDs } => {
-                Some(agentIDs.first().unwrap().clone())
-            }
+            ResponseFrame::agentsForService { id: _, agentIDs } => agentIDs.first().cloned(),
             _ => None,
         }
     }
-    pub fn get_agent_ids(&self) -> Option<Vec<String>> {
+    pub fn get_agent_ids(&self) -> Option<Vec<AgentID>> {
         match self {
-            ResponseFrame::agents { id: _, agentIDs } => Some(agentIDs.clone()),
+            ResponseFrame::agents { agentIDs, .. } => Some(agentIDs.clone()),
             ResponseFrame::agentForService { id: _, agentID } => {
                 if agentID.is_some() {
                     Some(vec![agentID.clone().unwrap()])
@@ -166,4 +287,16 @@ impl ResponseFrame {
             _ => None,
         }
     }
+    pub fn get_agent_types(&self) -> Option<Vec<String>> {
+        match self {
+            ResponseFrame::agents { agentTypes, .. } => agentTypes.clone(),
+            _ => None,
+        }
+    }
+    pub fn get_auth(&self) -> Option<bool> {
+        match self {
+            ResponseFrame::auth { id: _, auth } => Some(*auth),
+            _ => None,
+        }
+    }
 }
@@ -0,0 +1,74 @@
+use std::sync::{Arc, Mutex};
+
+use crate::core::{aid::AgentID, error::GatewayError, message::Message};
+
+use super::{
+    behaviour::{Behaviour, BehaviourHandle, Behaviours},
+    container::RemoteContainer,
+};
+
+/// An agent hosted in a [RemoteContainer], which the master sees like any agent in a slave container.
+/// Each agent has its own mailbox, and the hooks below are called from the agent's own task.
+pub trait Agent: Send + 'static {
+    /// Called once when the agent is added to a container, before any message is delivered.
+    /// This is the place to register services and subscribe to topics.
+    fn init(&mut self, _ctx: &AgentContext) {}
+    /// Called for every message addressed to the agent, or to a topic it subscribes to, that no
+    /// [MessageBehaviour](super::behaviour::MessageBehaviour) has taken.
+    fn process_message(&mut self, _ctx: &AgentContext, _msg: Message) {}
+    /// Called once when the agent is removed from its container, or the link to the master is closed.
+    fn shutdown(&mut self, _ctx: &AgentContext) {}
+}
+
+/// The view an [Agent] has of itself and its container.
+#[derive(Clone)]
+pub struct AgentContext {
+    aid: AgentID,
+    container: RemoteContainer,
+    behaviours: Arc<Mutex<Behaviours>>,
+}
+impl AgentContext {
+    pub(crate) fn new(aid: AgentID, container: RemoteContainer) -> AgentContext {
+        AgentContext {
+            aid: aid,
+            container: container,
+            behaviours: Arc::new(Mutex::new(Behaviours::default())),
+        }
+    }
+    pub fn agent_id(&self) -> &AgentID {
+        return &self.aid;
+    }
+    pub fn container(&self) -> &RemoteContainer {
+        return &self.container;
+    }
+    /// Start a behaviour on behalf of this agent. It runs until the agent shuts down or the handle is stopped.
+    pub fn add<B: Behaviour>(&self, behaviour: B) -> BehaviourHandle {
+        let id = self.behaviours.lock().unwrap().next_id();
+        behaviour.attach(self, id);
+        return BehaviourHandle::new(id, self.behaviours.clone());
+    }
+    pub(crate) fn behaviours(&self) -> &Arc<Mutex<Behaviours>> {
+        return &self.behaviours;
+    }
+    /// Advertise a service, so that agentForService and agentsForService requests find this agent.
+    pub fn register(&self, service: &str) {
+        self.container.register_service(&self.aid, service);
+    }
+    pub fn deregister(&self, service: &str) {
+        self.container.deregister_service(&self.aid, service);
+    }
+    /// Have messages sent to `topic` delivered to this agent.
+    pub fn subscribe(&self, topic: &AgentID) -> Result<(), GatewayError> {
+        return self.container.subscribe_local(&self.aid, topic);
+    }
+    pub fn unsubscribe(&self, topic: &AgentID) -> Result<(), GatewayError> {
+        return self.container.unsubscribe_local(&self.aid, topic);
+    }
+    /// Send a message from this agent. Agents in the same container are reached without going through the master.
+    pub fn send(&self, to: &AgentID, mut msg: Message) -> Result<(), GatewayError> {
+        if msg.data.sender.is_empty() {
+            msg.data.sender = self.aid.clone();
+        }
+        return self.container.clone().send(to, msg);
+    }
+}
@@ -0,0 +1,298 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::{
+    task::AbortHandle,
+    time::{self, Instant, MissedTickBehavior},
+};
+
+use crate::core::message::Message;
+
+use super::agent::AgentContext;
+
+type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+type Action = Box<dyn FnMut(AgentContext) -> BoxFuture<()> + Send>;
+type MessageAction = Box<dyn FnMut(AgentContext, Message) -> BoxFuture<()> + Send>;
+type StateAction = Box<dyn FnMut(AgentContext) -> BoxFuture<FsmStep> + Send>;
+
+/// Something an agent does, scheduled on the runtime of its container. Add behaviours with
+/// [AgentContext::add]; they stop when the agent shuts down or when their [BehaviourHandle] is stopped.
+/// The behaviours in this module are the only ones; the trait cannot be implemented elsewhere.
+pub trait Behaviour: sealed::Attach {}
+
+pub(crate) mod sealed {
+    use super::AgentContext;
+
+    /// Starts a behaviour under the id [AgentContext::add] picked for it. Kept out of the public API,
+    /// so that nobody can attach a behaviour under an id of their own.
+    pub trait Attach {
+        fn attach(self, ctx: &AgentContext, id: usize);
+    }
+}
+
+/// Behaviours running on behalf of one agent
+#[derive(Default)]
+pub(crate) struct Behaviours {
+    next_id: usize,
+    tasks: HashMap<usize, AbortHandle>,
+    message: Vec<(usize, MessageBehaviour)>,
+}
+impl Behaviours {
+    pub(crate) fn next_id(&mut self) -> usize {
+        self.next_id += 1;
+        return self.next_id;
+    }
+    pub(crate) fn stop(&mut self, id: usize) {
+        if let Some(task) = self.tasks.remove(&id) {
+            task.abort();
+        }
+        self.message.retain(|(bid, _)| *bid != id);
+    }
+    pub(crate) fn stop_all(&mut self) {
+        for (_, task) in self.tasks.drain() {
+            task.abort();
+        }
+        self.message.clear();
+    }
+}
+
+/// Stops a behaviour added with [AgentContext::add]
+#[derive(Clone)]
+pub struct BehaviourHandle {
+    id: usize,
+    behaviours: Arc<Mutex<Behaviours>>,
+}
+impl BehaviourHandle {
+    pub(crate) fn new(id: usize, behaviours: Arc<Mutex<Behaviours>>) -> BehaviourHandle {
+        BehaviourHandle {
+            id: id,
+            behaviours: behaviours,
+        }
+    }
+    pub fn stop(&self) {
+        self.behaviours.lock().unwrap().stop(self.id);
+    }
+    /// Whether the behaviour is still scheduled: it has neither finished nor been stopped
+    pub fn is_running(&self) -> bool {
+        let behaviours = self.behaviours.lock().unwrap();
+        return behaviours.tasks.contains_key(&self.id)
+            || behaviours.message.iter().any(|(bid, _)| *bid == self.id);
+    }
+}
+
+fn spawn_task<F: Future<Output = ()> + Send + 'static>(ctx: &AgentContext, id: usize, task: F) {
+    let behaviours = ctx.behaviours().clone();
+    let mut guard = behaviours.lock().unwrap();
+    let finished = behaviours.clone();
+    let handle = tokio::spawn(async move {
+        task.await;
+        // Ids are never reused, so this cannot remove a later behaviour. The handle was inserted before we could get the lock.
+        finished.lock().unwrap().tasks.remove(&id);
+    });
+    guard.tasks.insert(id, handle.abort_handle());
+}
+
+/// Runs once, as soon as it is added.
+pub struct OneShotBehaviour {
+    action: Action,
+}
+impl OneShotBehaviour {
+    pub fn new<F, Fut>(mut action: F) -> OneShotBehaviour
+    where
+        F: FnMut(AgentContext) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        OneShotBehaviour {
+            action: Box::new(move |ctx| Box::pin(action(ctx))),
+        }
+    }
+}
+impl Behaviour for OneShotBehaviour {}
+impl sealed::Attach for OneShotBehaviour {
+    fn attach(mut self, ctx: &AgentContext, id: usize) {
+        let task_ctx = ctx.clone();
+        spawn_task(ctx, id, async move {
+            (self.action)(task_ctx).await;
+        });
+    }
+}
+
+/// Runs once, after a delay.
+pub struct WakerBehaviour {
+    delay: Duration,
+    action: Action,
+}
+impl WakerBehaviour {
+    pub fn new<F, Fut>(delay: Duration, mut action: F) -> WakerBehaviour
+    where
+        F: FnMut(AgentContext) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        WakerBehaviour {
+            delay: delay,
+            action: Box::new(move |ctx| Box::pin(action(ctx))),
+        }
+    }
+}
+impl Behaviour for WakerBehaviour {}
+impl sealed::Attach for WakerBehaviour {
+    fn attach(mut self, ctx: &AgentContext, id: usize) {
+        let task_ctx = ctx.clone();
+        spawn_task(ctx, id, async move {
+            time::sleep(self.delay).await;
+            (self.action)(task_ctx).await;
+        });
+    }
+}
+
+/// Runs every `period`, starting one period after it is added. A tick that overruns the period delays the
+/// following ticks rather than bunching them up.
+pub struct TickerBehaviour {
+    period: Duration,
+    action: Action,
+}
+impl TickerBehaviour {
+    pub fn new<F, Fut>(period: Duration, mut action: F) -> TickerBehaviour
+    where
+        F: FnMut(AgentContext) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        TickerBehaviour {
+            period: period,
+            action: Box::new(move |ctx| Box::pin(action(ctx))),
+        }
+    }
+}
+impl Behaviour for TickerBehaviour {}
+impl sealed::Attach for TickerBehaviour {
+    fn attach(mut self, ctx: &AgentContext, id: usize) {
+        let task_ctx = ctx.clone();
+        spawn_task(ctx, id, async move {
+            let mut ticker = time::interval_at(Instant::now() + self.period, self.period);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                (self.action)(task_ctx.clone()).await;
+            }
+        });
+    }
+}
+
+/// Handles the messages delivered to the agent, before its [process_message](super::agent::Agent::process_message).
+/// The first message behaviour that matches a message consumes it. Messages are handled one at a time, in order.
+pub struct MessageBehaviour {
+    clazz: Option<String>,
+    // Shared, so that the action can run without holding the agent's behaviours locked
+    action: Arc<Mutex<MessageAction>>,
+}
+impl MessageBehaviour {
+    /// Handle every message
+    pub fn new<F, Fut>(mut action: F) -> MessageBehaviour
+    where
+        F: FnMut(AgentContext, Message) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        MessageBehaviour {
+            clazz: None,
+            action: Arc::new(Mutex::new(Box::new(move |ctx, msg| {
+                Box::pin(action(ctx, msg))
+            }))),
+        }
+    }
+    /// Handle messages of the given clazz only
+    pub fn for_clazz<F, Fut>(clazz: &str, action: F) -> MessageBehaviour
+    where
+        F: FnMut(AgentContext, Message) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let mut behaviour = MessageBehaviour::new(action);
+        behaviour.clazz = Some(clazz.to_string());
+        return behaviour;
+    }
+    fn matches(&self, msg: &Message) -> bool {
+        return self.clazz.as_ref().is_none_or(|clazz| *clazz == msg.clazz);
+    }
+}
+impl Behaviour for MessageBehaviour {}
+impl sealed::Attach for MessageBehaviour {
+    fn attach(self, ctx: &AgentContext, id: usize) {
+        ctx.behaviours().lock().unwrap().message.push((id, self));
+    }
+}
+
+/// Offer a message to the agent's message behaviours. Gives the message back if none of them wants it.
+pub(crate) async fn dispatch(ctx: &AgentContext, msg: Message) -> Option<Message> {
+    let action = {
+        let behaviours = ctx.behaviours().lock().unwrap();
+        behaviours
+            .message
+            .iter()
+            .find(|(_, b)| b.matches(&msg))
+            .map(|(_, b)| b.action.clone())
+    };
+    let Some(action) = action else {
+        return Some(msg);
+    };
+    let handled = (action.lock().unwrap())(ctx.clone(), msg);
+    handled.await;
+    return None;
+}
+
+/// What an [FsmBehaviour] does after running the action of its current state
+#[allow(non_camel_case_types)]
+pub enum FsmStep {
+    /// Run the current state's action again after the given delay
+    WAIT(Duration),
+    /// Move to the named state and run its action
+    GOTO(String),
+    /// Stop the state machine
+    DONE,
+}
+
+/// A finite state machine. Each state has an action that decides the next step.
+pub struct FsmBehaviour {
+    initial: String,
+    states: HashMap<String, StateAction>,
+}
+impl FsmBehaviour {
+    pub fn new(initial: &str) -> FsmBehaviour {
+        FsmBehaviour {
+            initial: initial.to_string(),
+            states: HashMap::new(),
+        }
+    }
+    pub fn state<F, Fut>(mut self, name: &str, mut action: F) -> FsmBehaviour
+    where
+        F: FnMut(AgentContext) -> Fut + Send + 'static,
+        Fut: Future<Output = FsmStep> + Send + 'static,
+    {
+        self.states
+            .insert(name.to_string(), Box::new(move |ctx| Box::pin(action(ctx))));
+        return self;
+    }
+}
+impl Behaviour for FsmBehaviour {}
+impl sealed::Attach for FsmBehaviour {
+    fn attach(mut self, ctx: &AgentContext, id: usize) {
+        let task_ctx = ctx.clone();
+        spawn_task(ctx, id, async move {
+            let mut current = self.initial.clone();
+            loop {
+                let Some(action) = self.states.get_mut(&current) else {
+                    println!("Error: FsmBehaviour has no state {}, stopping.", current);
+                    return;
+                };
+                match action(task_ctx.clone()).await {
+                    FsmStep::WAIT(delay) => time::sleep(delay).await,
+                    FsmStep::GOTO(next) => current = next,
+                    FsmStep::DONE => return,
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,84 @@
+//! Per-call cancellation for the async [RemoteContainer](crate::remote::container::RemoteContainer).
+//! Unlike `interrupt()`, which aborts whichever reception is in progress, a token only aborts the calls it was given:
+//!
+//! ```ignore
+//! let token = CancelToken::new();
+//! let handle = token.clone();
+//! tokio::spawn(async move { wait_for_user_abort().await; handle.cancel(); });
+//! let rsp = token.run(container.request(&shell, req)).await?;
+//! ```
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use tokio::sync::Notify;
+
+use crate::core::error::GatewayError;
+
+struct CancelState {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+/// A handle to abort one or more calls. Clones share the same state, so one can be handed to another task to cancel from there.
+/// Once cancelled, a token stays cancelled.
+#[derive(Clone)]
+pub struct CancelToken {
+    state: Arc<CancelState>,
+}
+impl CancelToken {
+    pub fn new() -> CancelToken {
+        return CancelToken {
+            state: Arc::new(CancelState {
+                cancelled: AtomicBool::new(false),
+                notify: Notify::new(),
+            }),
+        };
+    }
+    /// Abort every call running under this token, and any started under it later
+    pub fn cancel(&self) {
+        self.state.cancelled.store(true, Ordering::SeqCst);
+        self.state.notify.notify_waiters();
+    }
+    pub fn is_cancelled(&self) -> bool {
+        return self.state.cancelled.load(Ordering::SeqCst);
+    }
+    /// Resolves once the token is cancelled
+    pub async fn cancelled(&self) {
+        loop {
+            // Register for the wake-up before checking, so a cancel() in between is not missed
+            let notified = self.state.notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+    /// Run `call`, giving up with [GatewayError::Interrupted] if the token is cancelled first
+    pub async fn run<T, F: Future<Output = Result<T, GatewayError>>>(
+        &self,
+        call: F,
+    ) -> Result<T, GatewayError> {
+        if self.is_cancelled() {
+            return Err(GatewayError::Interrupted);
+        }
+        tokio::select! {
+            rsp = call => rsp,
+            _ = self.cancelled() => Err(GatewayError::Interrupted),
+        }
+    }
+}
+impl Default for CancelToken {
+    fn default() -> CancelToken {
+        return CancelToken::new();
+    }
+}
+impl std::fmt::Debug for CancelToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CancelToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
@@ -1,216 +1,1014 @@
-use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::sync::{self as std_sync, Arc, Weak};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+use crate::core::aid::AgentID;
+use crate::core::error::GatewayError;
+use crate::core::filter::MessageFilter;
 use crate::core::message::Message;
+use crate::core::registry::{AnyMessage, MessageRegistry};
+use crate::core::typed::FjageMessage;
+use crate::remote::agent::{Agent, AgentContext};
+use crate::remote::behaviour;
+use crate::remote::queue::{Mailbox, MessageQueue, QueueLimits, QueueStats};
 //use crate::core::param::{ParameterManipulation, ParameterReq, ParameterRsp};
-use crate::protocol::connector::{SerialPortConnector, TcpConnector};
+#[cfg(unix)]
+use crate::protocol::connector::UnixSocketConnector;
+use crate::protocol::connector::{Link, SerialPortConnector, TcpConnector, WebSocketConnector};
 use crate::protocol::frame::Frame;
-use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
-use tokio::sync::{broadcast, Mutex};
+use futures_util::{stream, Stream};
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::sync::{broadcast, oneshot, watch, Notify};
 use uuid::Uuid;
 
 use crate::protocol::{connector::Connector, frame::*};
 
-#[derive(Clone)]
-pub enum GatewayReceiveInterrupt {
-    MESSAGE,
-    CANCEL,
+/// State of the link between a RemoteContainer and its master container
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LinkState {
+    CONNECTED,
+    RECONNECTING,
+    /// The link was lost and could not be re-established. The container will not reconnect.
+    DISCONNECTED,
+    /// The link was shut down deliberately. The container will not reconnect.
+    CLOSED,
+}
+impl LinkState {
+    /// Whether the link is gone for good
+    pub fn is_terminal(&self) -> bool {
+        return matches!(self, LinkState::DISCONNECTED | LinkState::CLOSED);
+    }
+}
+
+/// Why [RemoteContainer::run_link] stopped pumping frames
+enum LinkExit {
+    DROPPED,
+    SHUTDOWN,
+    /// [RemoteContainer::close] was called
+    CLOSE,
+}
+
+/// Frames waiting to be sent to the master, beyond which [RemoteContainer::send] fails with
+/// [GatewayError::WouldBlock] and the async calls wait for room
+pub const OUTBOX_CAPACITY: usize = 256;
+
+/// Controls how a RemoteContainer re-establishes a dropped link. The delay before each attempt
+/// starts at `initial_delay` and is multiplied by `multiplier` after every failed attempt, up to `max_delay`.
+#[derive(Clone, Debug)]
+pub struct ReconnectPolicy {
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub multiplier: u32,
+    /// Give up after this many failed attempts. `None` retries forever.
+    pub max_attempts: Option<u32>,
+}
+impl ReconnectPolicy {
+    /// A policy which never attempts to reconnect
+    pub fn never() -> ReconnectPolicy {
+        return ReconnectPolicy {
+            max_attempts: Some(0),
+            ..ReconnectPolicy::default()
+        };
+    }
+    fn delay(&self, attempt: u32) -> Duration {
+        let factor = self.multiplier.saturating_pow(attempt);
+        return self
+            .initial_delay
+            .saturating_mul(factor)
+            .min(self.max_delay);
+    }
+}
+impl Default for ReconnectPolicy {
+    fn default() -> ReconnectPolicy {
+        return ReconnectPolicy {
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            multiplier: 2,
+            max_attempts: None,
+        };
+    }
+}
+
+/// An agent registered with the container. Agents added by name only have no mailbox; their messages
+/// land in the shared queue read by [RemoteContainer::recv].
+struct LocalAgent {
+    aid: AgentID,
+    services: Vec<String>,
+    topics: Vec<AgentID>,
+    mailbox: Option<Arc<Mailbox>>,
+}
+
+/// An entry in [RemoteContainer]'s pending replies, removed again when the request stops waiting for any reason
+struct PendingReply {
+    pending: Arc<std_sync::Mutex<HashMap<String, oneshot::Sender<Message>>>>,
+    id: String,
+}
+impl PendingReply {
+    fn new(
+        pending: &Arc<std_sync::Mutex<HashMap<String, oneshot::Sender<Message>>>>,
+        id: &str,
+        waiter: oneshot::Sender<Message>,
+    ) -> PendingReply {
+        pending.lock().unwrap().insert(id.to_string(), waiter);
+        return PendingReply {
+            pending: pending.clone(),
+            id: id.to_string(),
+        };
+    }
+}
+impl Drop for PendingReply {
+    fn drop(&mut self) {
+        self.pending.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// A stream returned by [RemoteContainer::messages] or [RemoteContainer::topic_messages]
+struct MessageWatcher {
+    /// The topic the master has to forward for this stream, if any
+    topic: Option<AgentID>,
+    filter: Box<dyn MessageFilter>,
+    /// Held by the stream, so it is gone once the stream is dropped
+    mailbox: Weak<Mailbox>,
 }
 
 /// Object representing a container on a remote platform
 #[derive(Clone)]
 pub struct RemoteContainer {
-    sender: UnboundedSender<Frame>,
+    sender: mpsc::Sender<Frame>,
     rsp_frame_broadcast: broadcast::Sender<ResponseFrame>,
-    agents: Arc<Mutex<Vec<String>>>,
-    subscriptions: Arc<Mutex<Vec<String>>>,
-    agent_id: String,
-    msg_queue: Arc<Mutex<Vec<Message>>>,
-    msg_interrupt_listener: Arc<Mutex<mpsc::Receiver<GatewayReceiveInterrupt>>>,
-    msg_interrupt_sender: mpsc::Sender<GatewayReceiveInterrupt>,
+    link_state: watch::Receiver<LinkState>,
+    agents: Arc<std_sync::Mutex<Vec<LocalAgent>>>,
+    subscriptions: Arc<std_sync::Mutex<Vec<AgentID>>>,
+    /// Agents and topics the master has asked us to forward messages for, or `None` if it has not said
+    master_wants: Arc<std_sync::Mutex<Option<Vec<AgentID>>>>,
+    agent_id: AgentID,
+    msg_queue: Arc<std_sync::Mutex<MessageQueue>>,
+    /// Woken whenever a message is added to `msg_queue`
+    msg_arrived: Arc<Notify>,
+    /// Callers of [RemoteContainer::request] waiting for a reply, by the msgID of their request
+    pending_replies: Arc<std_sync::Mutex<HashMap<String, oneshot::Sender<Message>>>>,
+    watchers: Arc<std_sync::Mutex<Vec<MessageWatcher>>>,
+    /// Limits for the mailboxes of message streams and hosted agents
+    stream_limits: Arc<std_sync::Mutex<QueueLimits>>,
+    registry: Arc<std_sync::RwLock<MessageRegistry>>,
+    /// Bumped by [RemoteContainer::interrupt]. Receptions in progress watch it for a change.
+    interrupts: Arc<watch::Sender<u64>>,
+    /// Set by [RemoteContainer::close] to tell the link task to hang up
+    closing: Arc<watch::Sender<bool>>,
+    /// Tells the link task that [RemoteContainer::wanted_ids] has changed and the master should hear about it
+    wants_changed: Arc<Notify>,
 }
 impl RemoteContainer {
     /// Open a new TCP Remote Container using a hostname and port.
-    pub async fn new_tcp(hostname: &str, port: u16) -> RemoteContainer {
+    pub async fn new_tcp(hostname: &str, port: u16) -> Result<RemoteContainer, GatewayError> {
         let conn = TcpConnector::new(hostname, port);
-        return RemoteContainer::new(&conn).await;
+        return RemoteContainer::new(conn).await;
     }
-    pub async fn new_serial(dev: &str, baud: u32) -> RemoteContainer {
+    pub async fn new_serial(dev: &str, baud: u32) -> Result<RemoteContainer, GatewayError> {
         let conn = SerialPortConnector::new(dev, baud);
-        return RemoteContainer::new(&conn).await;
+        return RemoteContainer::new(conn).await;
+    }
+    /// Open a new Remote Container through the WebSocket connector of a fjåge master, at `ws://hostname:port/ws`.
+    pub async fn new_ws(hostname: &str, port: u16) -> Result<RemoteContainer, GatewayError> {
+        let conn = WebSocketConnector::new(hostname, port);
+        return RemoteContainer::new(conn).await;
     }
-    pub async fn new<T: Connector>(connector: &T) -> RemoteContainer {
-        let (tx, rx): (UnboundedSender<Frame>, UnboundedReceiver<Frame>) =
-            connector.connect().await;
+    /// Open a new Remote Container to a fjåge master listening on a Unix domain socket.
+    #[cfg(unix)]
+    pub async fn new_unix<P: AsRef<std::path::Path>>(
+        path: P,
+    ) -> Result<RemoteContainer, GatewayError> {
+        let conn = UnixSocketConnector::new(path);
+        return RemoteContainer::new(conn).await;
+    }
+    /// Open a new Remote Container over any connector, reconnecting with the default [ReconnectPolicy] if the link drops.
+    pub async fn new<T: Connector + Send + Sync + 'static>(
+        connector: T,
+    ) -> Result<RemoteContainer, GatewayError> {
+        return RemoteContainer::new_with_policy(connector, ReconnectPolicy::default()).await;
+    }
+    pub async fn new_with_policy<T: Connector + Send + Sync + 'static>(
+        connector: T,
+        policy: ReconnectPolicy,
+    ) -> Result<RemoteContainer, GatewayError> {
+        let link: Link = connector.connect().await?;
+        let (tx, rx) = mpsc::channel(OUTBOX_CAPACITY);
         let (rsp_frame_broadcast, _rsp_frame_listen): (
             broadcast::Sender<ResponseFrame>,
             broadcast::Receiver<ResponseFrame>,
         ) = broadcast::channel(64);
+        let (state_tx, state_rx) = watch::channel(LinkState::CONNECTED);
 
-        let (tx_interrupt, rx_interrupt) = mpsc::channel::<GatewayReceiveInterrupt>(64);
-
-        let mut agent_id = String::from("RustGW-");
-        agent_id.push_str(&Uuid::new_v4().to_string());
+        let agent_id = AgentID::agent(&format!("RustGW-{}", Uuid::new_v4()));
 
         let mut gateway = RemoteContainer {
             agent_id: agent_id.clone(),
-            sender: tx.clone(),
+            sender: tx,
             rsp_frame_broadcast: rsp_frame_broadcast.clone(),
-            agents: Arc::new(Mutex::new(Vec::new())),
-            subscriptions: Arc::new(Mutex::new(Vec::new())),
-            msg_interrupt_listener: Arc::new(Mutex::new(rx_interrupt)),
-            msg_queue: Arc::new(Mutex::new(Vec::new())),
-            msg_interrupt_sender: tx_interrupt,
+            link_state: state_rx,
+            agents: Arc::new(std_sync::Mutex::new(Vec::new())),
+            subscriptions: Arc::new(std_sync::Mutex::new(Vec::new())),
+            master_wants: Arc::new(std_sync::Mutex::new(None)),
+            msg_queue: Arc::new(std_sync::Mutex::new(MessageQueue::new(
+                QueueLimits::default(),
+            ))),
+            msg_arrived: Arc::new(Notify::new()),
+            pending_replies: Arc::new(std_sync::Mutex::new(HashMap::new())),
+            watchers: Arc::new(std_sync::Mutex::new(Vec::new())),
+            stream_limits: Arc::new(std_sync::Mutex::new(QueueLimits::default())),
+            registry: Arc::new(std_sync::RwLock::new(MessageRegistry::default())),
+            interrupts: Arc::new(watch::channel(0).0),
+            closing: Arc::new(watch::channel(false).0),
+            wants_changed: Arc::new(Notify::new()),
         };
         gateway.add_agent(&agent_id).await;
-        gateway.receive_task(rx);
-        gateway.subscribe(&agent_id).await;
+        // Subscribed before the link task announces us, so the master never sees us not wanting our own messages
+        gateway.subscriptions.lock().unwrap().push(agent_id.clone());
+        gateway.link_task(connector, policy, link, rx, state_tx);
 
-        return gateway;
+        return Ok(gateway);
     }
 
-    async fn update_watch(&mut self) {
-        let subs = self.subscriptions.lock().await;
-        self.sender
-            .send(Frame::Request(RequestFrame::wantsMessagesFor {
-                agentIDs: subs.clone(),
-            }))
-            .unwrap();
+    /// Have the link task tell the master what we now want. It goes out ahead of the outbox, so a full outbox cannot hold
+    /// it up, and several changes in a row are sent as one.
+    fn update_watch(&self) -> Result<(), GatewayError> {
+        if self.link_state().is_terminal() {
+            return Err(GatewayError::ConnectionLost);
+        }
+        self.wants_changed.notify_one();
+        return Ok(());
+    }
+    /// Everything we want the master to forward to us: our own subscriptions, the topics of open message
+    /// streams, plus the local agents with a mailbox and the topics they subscribe to.
+    fn wanted_ids(&self) -> Vec<AgentID> {
+        let mut ids = self.subscriptions.lock().unwrap().clone();
+        for watcher in self.watchers.lock().unwrap().iter() {
+            if let Some(topic) = watcher.topic.as_ref() {
+                if watcher.mailbox.strong_count() > 0 && !ids.contains(topic) {
+                    ids.push(topic.clone());
+                }
+            }
+        }
+        for agent in self.agents.lock().unwrap().iter() {
+            if agent.mailbox.is_none() {
+                continue;
+            }
+            for id in std::iter::once(&agent.aid).chain(agent.topics.iter()) {
+                if !ids.contains(id) {
+                    ids.push(id.clone());
+                }
+            }
+        }
+        return ids;
+    }
+    /// Queue a frame for the master. Frames queued while reconnecting are held until the link is back up.
+    /// Fails with [GatewayError::WouldBlock] if the outbox is full.
+    fn send_frame(&self, frame: Frame) -> Result<(), GatewayError> {
+        return self.sender.try_send(frame).map_err(|e| match e {
+            TrySendError::Full(_) => GatewayError::WouldBlock,
+            TrySendError::Closed(_) => GatewayError::ConnectionLost,
+        });
+    }
+    /// Like [RemoteContainer::send_frame], but waits for room in the outbox
+    async fn send_frame_wait(&self, frame: Frame) -> Result<(), GatewayError> {
+        return self
+            .sender
+            .send(frame)
+            .await
+            .map_err(|_| GatewayError::ConnectionLost);
     }
 
-    pub async fn is_subscribed(&self, aid: &str) -> bool {
-        return self.subscriptions.lock().await.contains(&aid.to_string());
+    pub async fn is_subscribed(&self, aid: &AgentID) -> bool {
+        return self.subscriptions.lock().unwrap().contains(aid);
     }
-    pub async fn subscribe(&mut self, aid: &str) {
-        self.subscriptions.lock().await.push(aid.to_string());
-        self.update_watch().await;
+    pub async fn subscribe(&mut self, aid: &AgentID) -> Result<(), GatewayError> {
+        self.subscriptions.lock().unwrap().push(aid.clone());
+        return self.update_watch();
     }
-    pub async fn unsubscribe(&mut self, aid: &str) {
-        self.subscriptions.lock().await.retain(|x| *x != aid);
-        self.update_watch().await;
+    pub async fn unsubscribe(&mut self, aid: &AgentID) -> Result<(), GatewayError> {
+        self.subscriptions.lock().unwrap().retain(|x| x != aid);
+        return self.update_watch();
     }
-    pub async fn add_agent(&mut self, aid: &str) {
-        self.agents.lock().await.push(aid.to_string());
+    /// Register an agent name with the container. Messages for it are read with [RemoteContainer::recv].
+    pub async fn add_agent(&mut self, aid: &AgentID) {
+        self.agents.lock().unwrap().push(LocalAgent {
+            aid: aid.clone(),
+            services: Vec::new(),
+            topics: Vec::new(),
+            mailbox: None,
+        });
     }
-    pub async fn subscribe_agent(&mut self, aid: &str) {
-        let mut topic_aid = String::from("#");
-        topic_aid.push_str(aid);
-        topic_aid.push_str("__ntf");
-        self.subscribe(&topic_aid).await;
+    /// Host an agent in this container. The agent's `init` runs before this returns, then every message
+    /// addressed to the agent, or to a topic it subscribes to, is passed to its `process_message`.
+    pub async fn add<A: Agent>(&mut self, name: &str, mut agent: A) -> Result<(), GatewayError> {
+        let aid = AgentID::agent(name);
+        let mailbox = Arc::new(Mailbox::new(self.stream_limits()));
+        let inbox = mailbox.clone();
+        {
+            let mut agents = self.agents.lock().unwrap();
+            if agents.iter().any(|a| a.aid == aid) {
+                return Err(GatewayError::DuplicateAgent(name.to_string()));
+            }
+            agents.push(LocalAgent {
+                aid: aid.clone(),
+                services: Vec::new(),
+                topics: Vec::new(),
+                mailbox: Some(mailbox),
+            });
+        }
+        let ctx = AgentContext::new(aid, self.clone());
+        agent.init(&ctx);
+        self.update_watch()?;
+
+        let link = self.link_state.clone();
+        tokio::spawn(async move {
+            let closed = RemoteContainer::link_closed(link);
+            tokio::pin!(closed);
+            loop {
+                tokio::select! {
+                    msg = inbox.recv() => match msg {
+                        Some(msg) => {
+                            if let Some(msg) = behaviour::dispatch(&ctx, msg).await {
+                                agent.process_message(&ctx, msg);
+                            }
+                        }
+                        // Removed from the container
+                        None => break,
+                    },
+                    _ = &mut closed => break,
+                }
+            }
+            ctx.behaviours().lock().unwrap().stop_all();
+            agent.shutdown(&ctx);
+        });
+        return Ok(());
     }
-    pub async fn unsubscribe_agent(&mut self, aid: &str) {
-        let mut topic_aid = String::from("#");
-        topic_aid.push_str(aid);
-        topic_aid.push_str("__ntf");
-        self.unsubscribe(&topic_aid).await;
+    /// Remove an agent from this container. Agents hosted with [RemoteContainer::add] get their `shutdown` called.
+    pub async fn remove_agent(&mut self, aid: &AgentID) -> Result<(), GatewayError> {
+        let removed = {
+            let mut agents = self.agents.lock().unwrap();
+            let pos = agents.iter().position(|a| a.aid == *aid);
+            pos.map(|pos| agents.remove(pos))
+        };
+        let Some(removed) = removed else {
+            return Err(GatewayError::AgentNotFound(aid.to_string()));
+        };
+        // Lets the agent's task finish, and its shutdown run
+        if let Some(mailbox) = removed.mailbox {
+            mailbox.close();
+        }
+        return self.update_watch();
+    }
+    /// The agents registered with this container
+    pub fn local_agents(&self) -> Vec<AgentID> {
+        return self
+            .agents
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|a| a.aid.clone())
+            .collect();
     }
-    fn receive_task(&mut self, mut receiver: UnboundedReceiver<Frame>) {
+    /// Services registered by the agents in this container
+    pub fn local_services(&self) -> Vec<String> {
+        let mut services: Vec<String> = Vec::new();
+        for agent in self.agents.lock().unwrap().iter() {
+            for service in agent.services.iter() {
+                if !services.contains(service) {
+                    services.push(service.clone());
+                }
+            }
+        }
+        return services;
+    }
+    /// Local agents which registered the given service
+    pub fn local_agents_for_service(&self, service: &str) -> Vec<AgentID> {
+        return self
+            .agents
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|a| a.services.iter().any(|s| s == service))
+            .map(|a| a.aid.clone())
+            .collect();
+    }
+    pub(crate) fn register_service(&self, aid: &AgentID, service: &str) {
+        let mut agents = self.agents.lock().unwrap();
+        if let Some(agent) = agents.iter_mut().find(|a| a.aid == *aid) {
+            if !agent.services.iter().any(|s| s == service) {
+                agent.services.push(service.to_string());
+            }
+        }
+    }
+    pub(crate) fn deregister_service(&self, aid: &AgentID, service: &str) {
+        let mut agents = self.agents.lock().unwrap();
+        if let Some(agent) = agents.iter_mut().find(|a| a.aid == *aid) {
+            agent.services.retain(|s| s != service);
+        }
+    }
+    pub(crate) fn subscribe_local(
+        &self,
+        aid: &AgentID,
+        topic: &AgentID,
+    ) -> Result<(), GatewayError> {
+        {
+            let mut agents = self.agents.lock().unwrap();
+            if let Some(agent) = agents.iter_mut().find(|a| a.aid == *aid) {
+                if !agent.topics.contains(topic) {
+                    agent.topics.push(topic.clone());
+                }
+            }
+        }
+        return self.update_watch();
+    }
+    pub(crate) fn unsubscribe_local(
+        &self,
+        aid: &AgentID,
+        topic: &AgentID,
+    ) -> Result<(), GatewayError> {
+        {
+            let mut agents = self.agents.lock().unwrap();
+            if let Some(agent) = agents.iter_mut().find(|a| a.aid == *aid) {
+                agent.topics.retain(|t| t != topic);
+            }
+        }
+        return self.update_watch();
+    }
+    /// Hand a message to the mailboxes of the local agents it is addressed to. Returns whether any agent took it.
+    fn deliver_local(&self, msg: &Message) -> bool {
+        let recipient = &msg.data.recipient;
+        let mut delivered = false;
+        for agent in self.agents.lock().unwrap().iter() {
+            let Some(mailbox) = agent.mailbox.as_ref() else {
+                continue;
+            };
+            if agent.aid == *recipient || agent.topics.contains(recipient) {
+                // A full mailbox drops a message and counts it, see [RemoteContainer::stream_stats]
+                mailbox.push(msg.clone());
+                delivered = true;
+            }
+        }
+        return delivered;
+    }
+    /// Deliver a message that has arrived for this container. Messages nobody here claims still go to the
+    /// queue read by [RemoteContainer::recv], so that they are not silently lost.
+    fn deliver(&self, msg: Message) {
+        let watched = self.deliver_watchers(&msg);
+        // Replies to a request in progress go straight to the caller waiting for them
+        let waiter = msg
+            .data
+            .inReplyTo
+            .as_ref()
+            .and_then(|id| self.pending_replies.lock().unwrap().remove(id));
+        let msg = match waiter {
+            Some(waiter) => match waiter.send(msg) {
+                Ok(()) => return,
+                // The caller gave up just now, so the reply is delivered as if nobody had asked for it
+                Err(msg) => msg,
+            },
+            None => msg,
+        };
+        if (self.deliver_local(&msg) || watched) && !self.wants_in_queue(&msg) {
+            return;
+        }
+        if self.msg_queue.lock().unwrap().push(msg) {
+            self.msg_arrived.notify_waiters();
+        }
+    }
+    /// Hand a copy of a message to every open stream whose filter accepts it. Returns whether any stream took it.
+    fn deliver_watchers(&self, msg: &Message) -> bool {
+        let mut delivered = false;
+        let mut dropped_topic = false;
+        self.watchers.lock().unwrap().retain(|watcher| {
+            let Some(mailbox) = watcher.mailbox.upgrade() else {
+                dropped_topic |= watcher.topic.is_some();
+                return false;
+            };
+            if watcher.filter.matches(msg) {
+                mailbox.push(msg.clone());
+                delivered = true;
+            }
+            return true;
+        });
+        if dropped_topic {
+            // Nobody is reading that topic any more, so the master can stop forwarding it
+            let _ = self.update_watch();
+        }
+        return delivered;
+    }
+    /// A stream of the messages arriving at this container that `filter` accepts, from now until the link is
+    /// closed. Every stream gets its own copy of each message, independently of [RemoteContainer::recv] and of
+    /// other streams.
+    pub fn messages<F: MessageFilter + 'static>(
+        &self,
+        filter: F,
+    ) -> impl Stream<Item = Message> + Send + 'static {
+        return self.watch(None, Box::new(filter));
+    }
+    /// A stream of the messages published on `topic`. The master is asked to forward the topic for as long as
+    /// the stream is alive; unlike [RemoteContainer::subscribe], the messages do not also pile up for
+    /// [RemoteContainer::recv].
+    pub fn topic_messages(
+        &self,
+        topic: &AgentID,
+    ) -> Result<impl Stream<Item = Message> + Send + 'static, GatewayError> {
+        let wanted = topic.clone();
+        let stream = self.watch(
+            Some(topic.clone()),
+            Box::new(move |msg: &Message| msg.data.recipient == wanted),
+        );
+        self.update_watch()?;
+        return Ok(stream);
+    }
+    fn watch(
+        &self,
+        topic: Option<AgentID>,
+        filter: Box<dyn MessageFilter>,
+    ) -> impl Stream<Item = Message> + Send + 'static {
+        let mailbox = Arc::new(Mailbox::new(self.stream_limits()));
+        self.watchers.lock().unwrap().push(MessageWatcher {
+            topic: topic,
+            filter: filter,
+            mailbox: Arc::downgrade(&mailbox),
+        });
+        let link = self.link_state.clone();
+        return stream::unfold((mailbox, link), |(mailbox, link)| async move {
+            let msg = tokio::select! {
+                msg = mailbox.recv() => msg,
+                _ = RemoteContainer::link_closed(link.clone()) => None,
+            };
+            return msg.map(|msg| (msg, (mailbox, link)));
+        });
+    }
+    /// Whether anything in this container, be it a hosted agent, a topic stream or a subscription, wants messages on `topic`
+    fn listens_to(&self, topic: &AgentID) -> bool {
+        return self.wanted_ids().contains(topic);
+    }
+    /// Whether the message should go to the queue read by [RemoteContainer::recv]
+    fn wants_in_queue(&self, msg: &Message) -> bool {
+        return self
+            .subscriptions
+            .lock()
+            .unwrap()
+            .contains(&msg.data.recipient);
+    }
+    /// Subscribe to the topic an agent publishes its notifications on
+    pub async fn subscribe_agent(&mut self, aid: &AgentID) -> Result<(), GatewayError> {
+        return self.subscribe(&AgentID::agent_topic(aid)).await;
+    }
+    pub async fn unsubscribe_agent(&mut self, aid: &AgentID) -> Result<(), GatewayError> {
+        return self.unsubscribe(&AgentID::agent_topic(aid)).await;
+    }
+    /// Close the connection to the master: drop our subscriptions, send the frames still queued, stop the hosted
+    /// agents, message streams and background tasks, and close the socket or serial port. Calls still waiting on
+    /// the master fail with [GatewayError::ConnectionLost]. Closing a container that is already closed does nothing.
+    pub async fn close(&self) {
+        if !self.link_state().is_terminal() {
+            self.subscriptions.lock().unwrap().clear();
+            // Tell the master to stop forwarding anything, in case it keeps the connection around.
+            // If the outbox is full we hang up without saying so, which ends the forwarding just as well.
+            let _ = self.send_frame(Frame::Request(RequestFrame::wantsMessagesFor {
+                agentIDs: Vec::new(),
+            }));
+            self.closing.send_replace(true);
+        }
+        RemoteContainer::link_closed(self.link_state.clone()).await;
+    }
+    /// Current state of the link to the master container
+    pub fn link_state(&self) -> LinkState {
+        return *self.link_state.borrow();
+    }
+    pub fn is_connected(&self) -> bool {
+        return self.link_state() == LinkState::CONNECTED;
+    }
+    /// Owns the connection to the master. Outgoing frames are forwarded from `outbox`, incoming frames are processed,
+    /// and when the link drops it is re-established according to `policy`.
+    fn link_task<T: Connector + Send + Sync + 'static>(
+        &mut self,
+        connector: T,
+        policy: ReconnectPolicy,
+        mut link: Link,
+        mut outbox: mpsc::Receiver<Frame>,
+        state: watch::Sender<LinkState>,
+    ) {
         let mut container = self.clone();
+        let mut closing = self.closing.subscribe();
         tokio::spawn(async move {
-            container.sender.send(Frame::Alive(true)).unwrap();
+            // Frames on their way to the connector, in the order they must go out
+            let mut pending = VecDeque::new();
             loop {
-                let frame = receiver.recv().await.unwrap();
-                let response = match frame {
-                    Frame::Alive(_) => None,
-                    Frame::Request(req) => container.process_request(req).await,
-                    Frame::Response(rsp) => {
-                        container.rsp_frame_broadcast.send(rsp).unwrap();
-                        None
+                // Whatever was meant for the last master is stale now
+                pending.clear();
+                container.announce(&mut pending);
+                match container
+                    .run_link(&mut link, &mut outbox, &mut pending, &mut closing)
+                    .await
+                {
+                    LinkExit::DROPPED => (),
+                    LinkExit::SHUTDOWN => {
+                        println!("RemoteContainer received shutdown from master, closing.");
+                        state.send_replace(LinkState::CLOSED);
+                        return;
+                    }
+                    LinkExit::CLOSE => {
+                        RemoteContainer::hang_up(link, outbox, pending).await;
+                        state.send_replace(LinkState::CLOSED);
+                        return;
+                    }
+                }
+
+                println!("Error: RemoteContainer lost connection to master, reconnecting...");
+                state.send_replace(LinkState::RECONNECTING);
+                let reconnected = tokio::select! {
+                    reconnected = RemoteContainer::reconnect(&connector, &policy) => reconnected,
+                    _ = RemoteContainer::close_requested(&mut closing) => {
+                        state.send_replace(LinkState::CLOSED);
+                        return;
                     }
                 };
-                if response.is_some() {
-                    container
-                        .sender
-                        .send(Frame::Response(response.unwrap()))
-                        .unwrap();
+                match reconnected {
+                    Some(new_link) => {
+                        link = new_link;
+                        state.send_replace(LinkState::CONNECTED);
+                    }
+                    None => {
+                        println!(
+                            "Error: RemoteContainer could not reconnect to master, giving up."
+                        );
+                        state.send_replace(LinkState::DISCONNECTED);
+                        return;
+                    }
                 }
             }
         });
     }
-    async fn query(&mut self, frame: Frame) -> ResponseFrame {
+    /// Introduce ourselves to a freshly connected master and restore our subscriptions.
+    fn announce(&self, pending: &mut VecDeque<Frame>) {
+        // A new master has not told us what it wants yet
+        *self.master_wants.lock().unwrap() = None;
+        pending.push_back(Frame::Alive(true));
+        pending.push_back(Frame::Request(RequestFrame::wantsMessagesFor {
+            agentIDs: self.wanted_ids(),
+        }));
+    }
+    /// Pump frames in both directions until the link drops, the master shuts it down or we are closed.
+    ///
+    /// Nothing here waits for the connector to take a frame: frames queue in `pending` and go out as it has room,
+    /// so incoming frames keep being read and a close is noticed however stalled the link is. Only the outbox
+    /// is held back while frames are pending, which makes a slow link hold back our senders rather than pile up.
+    async fn run_link(
+        &mut self,
+        link: &mut Link,
+        outbox: &mut mpsc::Receiver<Frame>,
+        pending: &mut VecDeque<Frame>,
+        closing: &mut watch::Receiver<bool>,
+    ) -> LinkExit {
+        loop {
+            tokio::select! {
+                // Subscription changes must overtake anything queued after them, so the master knows what to
+                // forward before it handles a request that was sent after subscribing
+                biased;
+                _ = RemoteContainer::close_requested(closing) => return LinkExit::CLOSE,
+                _ = self.wants_changed.notified() => {
+                    pending.push_back(Frame::Request(RequestFrame::wantsMessagesFor {
+                        agentIDs: self.wanted_ids(),
+                    }));
+                }
+                permit = link.0.reserve(), if !pending.is_empty() => {
+                    let Ok(permit) = permit else {
+                        return LinkExit::DROPPED;
+                    };
+                    permit.send(pending.pop_front().unwrap());
+                }
+                frame = link.1.recv() => {
+                    let Some(frame) = frame else {
+                        return LinkExit::DROPPED;
+                    };
+                    let response = match frame {
+                        Frame::Alive(_) => None,
+                        Frame::Request(RequestFrame::shutdown) => return LinkExit::SHUTDOWN,
+                        Frame::Request(req) => self.process_request(req).await,
+                        Frame::Response(rsp) => {
+                            // Nobody may be waiting on a response, which is fine
+                            let _ = self.rsp_frame_broadcast.send(rsp);
+                            None
+                        }
+                        Frame::Unknown(frame) => {
+                            println!("Error: RemoteContainer ignoring unknown frame: {}", frame);
+                            None
+                        }
+                    };
+                    if let Some(response) = response {
+                        pending.push_back(Frame::Response(response));
+                    }
+                }
+                frame = outbox.recv(), if pending.is_empty() => {
+                    let Some(frame) = frame else {
+                        return LinkExit::DROPPED;
+                    };
+                    pending.push_back(frame);
+                }
+            }
+        }
+    }
+    /// Resolves once [RemoteContainer::close] has been called
+    async fn close_requested(closing: &mut watch::Receiver<bool>) {
+        // The sender lives in the container, so it cannot be dropped while the link task runs
+        let _ = closing.wait_for(|closing| *closing).await;
+    }
+    /// Send the frames still queued for the master, then let go of the link and wait for the connector to close its stream
+    async fn hang_up(link: Link, mut outbox: mpsc::Receiver<Frame>, pending: VecDeque<Frame>) {
+        let (sender, mut receiver) = link;
+        outbox.close();
+        let flush = async move {
+            for frame in pending {
+                if sender.send(frame).await.is_err() {
+                    return;
+                }
+            }
+            while let Some(frame) = outbox.recv().await {
+                if sender.send(frame).await.is_err() {
+                    return;
+                }
+            }
+        };
+        // A connector that is stuck writing gets a second to finish, and is then abandoned
+        let _ = tokio::time::timeout(Duration::from_secs(1), async {
+            flush.await;
+            // The connector drops its end once it has written everything and closed the stream
+            while receiver.recv().await.is_some() {}
+        })
+        .await;
+    }
+    async fn reconnect<T: Connector>(connector: &T, policy: &ReconnectPolicy) -> Option<Link> {
+        let mut attempt = 0;
+        loop {
+            if policy.max_attempts.is_some_and(|max| attempt >= max) {
+                return None;
+            }
+            tokio::time::sleep(policy.delay(attempt)).await;
+            attempt += 1;
+            match connector.connect().await {
+                Ok(link) => return Some(link),
+                Err(e) => println!(
+                    "Error: RemoteContainer reconnect attempt {} failed: {:?}",
+                    attempt, e
+                ),
+            }
+        }
+    }
+    /// Snapshot of the link state for a caller about to wait on the master
+    fn watch_link(&self) -> watch::Receiver<LinkState> {
+        let mut state = self.link_state.clone();
+        state.borrow_and_update();
+        return state;
+    }
+    /// Resolves if the link drops (or has already been given up on) while a caller is waiting.
+    /// A caller who started waiting during a reconnect keeps waiting until the link is back up.
+    async fn link_lost(mut state: watch::Receiver<LinkState>) {
+        if state.borrow().is_terminal() {
+            return;
+        }
+        while state.changed().await.is_ok() {
+            if *state.borrow_and_update() != LinkState::CONNECTED {
+                return;
+            }
+        }
+    }
+    /// Resolves once the link is gone for good. Unlike [RemoteContainer::link_lost], a reconnect does not count.
+    async fn link_closed(mut state: watch::Receiver<LinkState>) {
+        while !state.borrow_and_update().is_terminal() {
+            if state.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+    async fn query(&mut self, frame: Frame) -> Result<ResponseFrame, GatewayError> {
         let mut listener = self.rsp_frame_broadcast.subscribe();
+        let lost = RemoteContainer::link_lost(self.watch_link());
         let id = frame.id().unwrap().clone();
-        self.sender.send(frame).unwrap();
-        loop {
-            let msg = listener.recv().await.unwrap();
-            if msg.id() == &id {
-                return msg;
+        self.send_frame_wait(frame).await?;
+        let response = async {
+            loop {
+                match listener.recv().await {
+                    Ok(msg) if msg.id() == &id => return Ok(msg),
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => {
+                        return Err(GatewayError::ConnectionLost)
+                    }
+                }
             }
+        };
+        tokio::select! {
+            rsp = response => rsp,
+            _ = lost => Err(GatewayError::ConnectionLost),
         }
     }
-    pub fn get_agent_id(&self) -> String {
+    pub fn get_agent_id(&self) -> AgentID {
         return self.agent_id.clone();
     }
     /// Get list of agents running in fjåge
-    pub async fn agents(&mut self) -> Vec<String> {
+    pub async fn agents(&mut self) -> Result<Vec<AgentID>, GatewayError> {
         let id = Uuid::new_v4().to_string();
         let rsp = self
             .query(Frame::Request(RequestFrame::agents { id: id.clone() }))
-            .await
-            .get_agent_ids();
-        return rsp.unwrap();
+            .await?;
+        return rsp
+            .get_agent_ids()
+            .ok_or_else(|| GatewayError::MalformedFrame(format!("{:?}", rsp)));
     }
     /// Get list of services running in fjåge
-    pub async fn services(&mut self) -> Vec<String> {
+    pub async fn services(&mut self) -> Result<Vec<String>, GatewayError> {
         let rsp = self
             .query(Frame::Request(RequestFrame::services {
                 id: Uuid::new_v4().to_string(),
             }))
-            .await;
-        return rsp.get_services().unwrap();
+            .await?;
+        return rsp
+            .get_services()
+            .ok_or_else(|| GatewayError::MalformedFrame(format!("{:?}", rsp)));
     }
     /// Ask if upstream container contains a specific agent
-    pub async fn contains_agent(&mut self, aid: &str) -> bool {
+    pub async fn contains_agent(&mut self, aid: &AgentID) -> Result<bool, GatewayError> {
         let rsp = self
             .query(Frame::Request(RequestFrame::containsAgent {
                 id: Uuid::new_v4().to_string(),
-                agentID: aid.to_string(),
+                agentID: aid.clone(),
             }))
-            .await;
-        return rsp.get_contains_agent().unwrap();
+            .await?;
+        return rsp
+            .get_contains_agent()
+            .ok_or_else(|| GatewayError::MalformedFrame(format!("{:?}", rsp)));
     }
-    /// Find an agent which advertises the requested service
-    pub async fn agent_for_service(&mut self, service: &str) -> Option<String> {
+    /// Find an agent which advertises the requested service. Fails with [GatewayError::AgentNotFound] if there is none.
+    pub async fn agent_for_service(&mut self, service: &str) -> Result<AgentID, GatewayError> {
         let rsp = self
             .query(Frame::Request(RequestFrame::agentForService {
                 id: Uuid::new_v4().to_string(),
                 service: service.to_string(),
             }))
-            .await;
-        return rsp.get_agent_id();
+            .await?;
+        return rsp
+            .get_agent_id()
+            .ok_or_else(|| GatewayError::AgentNotFound(service.to_string()));
     }
     /// Find all agents which advertise the requested service
-    pub async fn agents_for_service(&mut self, service: &str) -> Vec<String> {
+    pub async fn agents_for_service(
+        &mut self,
+        service: &str,
+    ) -> Result<Vec<AgentID>, GatewayError> {
         let rsp = self
             .query(Frame::Request(RequestFrame::agentsForService {
                 id: Uuid::new_v4().to_string(),
                 service: service.to_string(),
             }))
-            .await;
-        return rsp.get_agent_ids().unwrap();
+            .await?;
+        return rsp
+            .get_agent_ids()
+            .ok_or_else(|| GatewayError::MalformedFrame(format!("{:?}", rsp)));
     }
 
-    /// Send a message to the specified agent or topic. If "sender" is empty, it will be filled with the AgentID of the Gateway
-    pub fn send(&mut self, to: &str, mut msg: Message) {
+    /// Authenticate the connection with a master that requires credentials. Returns whether the master accepted them.
+    pub async fn authenticate(&mut self, creds: &str) -> Result<bool, GatewayError> {
+        let rsp = self
+            .query(Frame::Request(RequestFrame::auth {
+                id: Uuid::new_v4().to_string(),
+                creds: creds.to_string(),
+            }))
+            .await?;
+        return rsp
+            .get_auth()
+            .ok_or_else(|| GatewayError::MalformedFrame(format!("{:?}", rsp)));
+    }
+    /// Gives up on `call` with [GatewayError::Timeout] after `timeout`
+    async fn timed<T, F: Future<Output = Result<T, GatewayError>>>(
+        timeout: Duration,
+        call: F,
+    ) -> Result<T, GatewayError> {
+        return tokio::time::timeout(timeout, call)
+            .await
+            .unwrap_or(Err(GatewayError::Timeout));
+    }
+    /// Like [RemoteContainer::agents], but gives up with [GatewayError::Timeout] after `timeout`.
+    pub async fn agents_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<Vec<AgentID>, GatewayError> {
+        return RemoteContainer::timed(timeout, self.agents()).await;
+    }
+    pub async fn services_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<Vec<String>, GatewayError> {
+        return RemoteContainer::timed(timeout, self.services()).await;
+    }
+    pub async fn contains_agent_timeout(
+        &mut self,
+        aid: &AgentID,
+        timeout: Duration,
+    ) -> Result<bool, GatewayError> {
+        return RemoteContainer::timed(timeout, self.contains_agent(aid)).await;
+    }
+    pub async fn agent_for_service_timeout(
+        &mut self,
+        service: &str,
+        timeout: Duration,
+    ) -> Result<AgentID, GatewayError> {
+        return RemoteContainer::timed(timeout, self.agent_for_service(service)).await;
+    }
+    pub async fn agents_for_service_timeout(
+        &mut self,
+        service: &str,
+        timeout: Duration,
+    ) -> Result<Vec<AgentID>, GatewayError> {
+        return RemoteContainer::timed(timeout, self.agents_for_service(service)).await;
+    }
+    pub async fn authenticate_timeout(
+        &mut self,
+        creds: &str,
+        timeout: Duration,
+    ) -> Result<bool, GatewayError> {
+        return RemoteContainer::timed(timeout, self.authenticate(creds)).await;
+    }
+    /// Whether the master wants messages for the given agent or topic. Until the master declares its interest
+    /// with a wantsMessagesFor frame, it is assumed to want everything.
+    pub fn master_wants_messages_for(&self, aid: &AgentID) -> bool {
+        return match self.master_wants.lock().unwrap().as_ref() {
+            Some(wants) => wants.contains(aid),
+            None => true,
+        };
+    }
+
+    /// Send a message to the specified agent or topic. If "sender" is empty, it will be filled with the AgentID of the Gateway.
+    /// Fails with [GatewayError::WouldBlock] if too many frames are already waiting to go out, see [RemoteContainer::send_wait].
+    pub fn send(&mut self, to: &AgentID, msg: Message) -> Result<(), GatewayError> {
+        return self.send_relay(to, msg, true);
+    }
+    /// Send a message, choosing whether the master should relay it to other containers connected to it.
+    /// Without relaying, only agents hosted by the master itself can receive the message.
+    pub fn send_relay(
+        &mut self,
+        to: &AgentID,
+        msg: Message,
+        relay: bool,
+    ) -> Result<(), GatewayError> {
+        return match self.address(to, msg, relay) {
+            Some(frame) => self.send_frame(frame),
+            None => Ok(()),
+        };
+    }
+    /// Like [RemoteContainer::send], but waits for room if too many frames are waiting to go out
+    pub async fn send_wait(&mut self, to: &AgentID, msg: Message) -> Result<(), GatewayError> {
+        return self.send_relay_wait(to, msg, true).await;
+    }
+    /// Like [RemoteContainer::send_relay], but waits for room if too many frames are waiting to go out
+    pub async fn send_relay_wait(
+        &mut self,
+        to: &AgentID,
+        msg: Message,
+        relay: bool,
+    ) -> Result<(), GatewayError> {
+        return match self.address(to, msg, relay) {
+            Some(frame) => self.send_frame_wait(frame).await,
+            None => Ok(()),
+        };
+    }
+    /// Fill in the envelope of a message and hand it to the local agents and subscribers that want it.
+    /// Returns the frame that takes it on to the master, unless it was for a local agent only.
+    fn address(&self, to: &AgentID, mut msg: Message, relay: bool) -> Option<Frame> {
         if msg.data.sender.is_empty() {
             msg.data.sender = self.agent_id.clone();
         }
-        msg.data.recipient = to.to_string();
+        msg.data.recipient = to.clone();
         msg.data.sentAt = Some(
             SystemTime::now()
                 .duration_since(UNIX_EPOCH)
                 .unwrap()
                 .as_millis() as i64,
         );
-        self.sender
-            .send(Frame::Request(RequestFrame::send {
-                message: msg,
-                relay: true,
-            }))
-            .unwrap();
+        // Agents hosted here do not need the master to reach them
+        if !to.is_topic() && self.local_agents().contains(to) {
+            self.deliver(msg);
+            return None;
+        }
+        // Subscribers here get their copy of a topic message straight away, the rest of the world through the master
+        if to.is_topic() && self.listens_to(to) {
+            self.deliver(msg.clone());
+        }
+        msg.encode_java_classes();
+        return Some(Frame::Request(RequestFrame::send {
+            message: msg,
+            relay: relay,
+        }));
     }
     /// Send a message to the specified agent, then waits for a message with an inReplyTo marker matching the sent message's UUID.
-    pub async fn request(&mut self, to: &str, mut msg: Message) -> Option<Message> {
+    /// Replies with a REFUSE, FAILURE or NOT_UNDERSTOOD performative are returned as errors carrying the reply.
+    pub async fn request(
+        &mut self,
+        to: &AgentID,
+        mut msg: Message,
+    ) -> Result<Message, GatewayError> {
         let id = msg.data.msgID.clone();
         msg.data.sentAt = Some(
             SystemTime::now()
@@ -218,103 +1016,216 @@ impl RemoteContainer {
                 .unwrap()
                 .as_millis() as i64,
         );
-        self.clear_interrupt().await;
-        self.send(to, msg);
-        return self.recv(None, Some(id)).await;
+        let (tx, rx) = oneshot::channel();
+        // Registered before sending, so that even an immediate reply finds its way here
+        let _pending = PendingReply::new(&self.pending_replies, &id, tx);
+        let mut interrupted = self.watch_interrupts();
+        let lost = RemoteContainer::link_lost(self.watch_link());
+        tokio::pin!(lost);
+        // A stalled link may leave no room in the outbox, so waiting for it can be aborted like waiting for the reply
+        tokio::select! {
+            sent = self.send_wait(to, msg) => sent?,
+            _ = interrupted.changed() => return Err(GatewayError::Interrupted),
+            _ = &mut lost => return Err(GatewayError::ConnectionLost),
+        };
+        let rsp = tokio::select! {
+            // The sender is only dropped if the entry is removed, which happens when we stop waiting
+            rsp = rx => rsp.map_err(|_| GatewayError::ConnectionLost)?,
+            _ = interrupted.changed() => return Err(GatewayError::Interrupted),
+            _ = lost => return Err(GatewayError::ConnectionLost),
+        };
+        return GatewayError::check_reply(rsp);
     }
-    /// Clear any pending interrupts on the Gateway
-    pub async fn clear_interrupt(&mut self) {
-        while self.msg_interrupt_listener.lock().await.try_recv().is_ok() {}
+    /// Like [RemoteContainer::request], but gives up with [GatewayError::Timeout] after `timeout`.
+    pub async fn request_timeout(
+        &mut self,
+        to: &AgentID,
+        msg: Message,
+        timeout: Duration,
+    ) -> Result<Message, GatewayError> {
+        return RemoteContainer::timed(timeout, self.request(to, msg)).await;
+    }
+    /// Interrupts only abort receptions already in progress, so there is never one pending
+    #[deprecated(
+        note = "interrupts no longer carry over to later receptions, there is nothing to clear"
+    )]
+    pub async fn clear_interrupt(&mut self) {}
+    /// A receiver that sees a change when [RemoteContainer::interrupt] is next called
+    fn watch_interrupts(&self) -> watch::Receiver<u64> {
+        let mut interrupts = self.interrupts.subscribe();
+        interrupts.borrow_and_update();
+        return interrupts;
     }
     /// Receive a message. If clazzes, id, or both are specified, then only messages matching those parameters will be returned.
     pub async fn recv(
         &mut self,
         clazzes: Option<Vec<String>>,
         id: Option<String>,
-    ) -> Option<Message> {
-        self.clear_interrupt().await;
+    ) -> Result<Message, GatewayError> {
+        return self
+            .recv_filtered(move |msg: &Message| {
+                msg.check_clazz_is(&clazzes) && msg.check_in_reply_to_is(&id)
+            })
+            .await;
+    }
+    pub async fn recv_timeout(
+        &mut self,
+        clazzes: Option<Vec<String>>,
+        id: Option<String>,
+        timeout: Duration,
+    ) -> Result<Message, GatewayError> {
+        return RemoteContainer::timed(timeout, self.recv(clazzes, id)).await;
+    }
+    /// Receive the oldest queued message accepted by `filter`, waiting for one to arrive if there is none yet.
+    pub async fn recv_filtered<F: MessageFilter>(
+        &mut self,
+        filter: F,
+    ) -> Result<Message, GatewayError> {
+        let mut interrupted = self.watch_interrupts();
         loop {
-            let msg = {
-                let mut msg = None;
-                let mut msg_pos = None;
-                let mut queue = self.msg_queue.lock().await;
-                for (pos, msg) in queue.iter().enumerate() {
-                    if msg.check_clazz_is(&clazzes) && msg.check_in_reply_to_is(&id) {
-                        msg_pos = Some(pos);
-                    }
-                }
-                if msg_pos.is_some() {
-                    msg = Some(queue.remove(msg_pos.unwrap()));
-                }
-                msg
-            };
-            if msg.is_some() {
-                return Some(msg.unwrap());
+            // Listen before looking at the queue, so that a message arriving in between still wakes us
+            let arrived = self.msg_arrived.notified();
+            tokio::pin!(arrived);
+            arrived.as_mut().enable();
+            let msg = self.msg_queue.lock().unwrap().take(&filter);
+            if let Some(msg) = msg {
+                return Ok(msg);
             }
-            let int = self
-                .msg_interrupt_listener
-                .lock()
-                .await
-                .recv()
-                .await
-                .unwrap();
-
-            match int {
-                GatewayReceiveInterrupt::CANCEL => return None,
-                _ => (),
+            tokio::select! {
+                _ = arrived => (),
+                // The sender lives in self, so it cannot be dropped while we wait on it
+                _ = interrupted.changed() => return Err(GatewayError::Interrupted),
+                _ = RemoteContainer::link_closed(self.link_state.clone()) => {
+                    return Err(GatewayError::ConnectionLost)
+                }
             };
         }
     }
-    /// Interrupt an ongoing reception
+    /// Like [RemoteContainer::recv_filtered], but gives up with [GatewayError::Timeout] after `timeout`.
+    pub async fn recv_timeout_filtered<F: MessageFilter>(
+        &mut self,
+        filter: F,
+        timeout: Duration,
+    ) -> Result<Message, GatewayError> {
+        return RemoteContainer::timed(timeout, self.recv_filtered(filter)).await;
+    }
+    /// Change how many messages wait for [RemoteContainer::recv], and for how long. Messages that no longer fit are discarded.
+    pub fn set_queue_limits(&self, limits: QueueLimits) {
+        self.msg_queue.lock().unwrap().set_limits(limits);
+    }
+    pub fn queue_limits(&self) -> QueueLimits {
+        return self.msg_queue.lock().unwrap().limits();
+    }
+    /// How many messages are waiting for [RemoteContainer::recv], and how many were discarded
+    pub fn queue_stats(&self) -> QueueStats {
+        return self.msg_queue.lock().unwrap().stats();
+    }
+    /// Change how many messages wait in each message stream, message listener and hosted agent's mailbox, and for how
+    /// long. Applies to those already open as well as those opened later. Messages that no longer fit are discarded.
+    pub fn set_stream_limits(&self, limits: QueueLimits) {
+        *self.stream_limits.lock().unwrap() = limits.clone();
+        for mailbox in self.mailboxes() {
+            mailbox.set_limits(limits.clone());
+        }
+    }
+    pub fn stream_limits(&self) -> QueueLimits {
+        return self.stream_limits.lock().unwrap().clone();
+    }
+    /// How many messages are waiting in the message streams and hosted agents' mailboxes still open, and how many they discarded
+    pub fn stream_stats(&self) -> QueueStats {
+        let mut total = QueueStats::default();
+        for mailbox in self.mailboxes() {
+            let stats = mailbox.stats();
+            total.queued += stats.queued;
+            total.dropped_full += stats.dropped_full;
+            total.dropped_expired += stats.dropped_expired;
+        }
+        return total;
+    }
+    fn mailboxes(&self) -> Vec<Arc<Mailbox>> {
+        let mut mailboxes: Vec<Arc<Mailbox>> = self
+            .watchers
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|w| w.mailbox.upgrade())
+            .collect();
+        for agent in self.agents.lock().unwrap().iter() {
+            mailboxes.extend(agent.mailbox.clone());
+        }
+        return mailboxes;
+    }
+    /// Decode received messages of clazz `T::CLAZZ` as `T` in [RemoteContainer::recv_typed]
+    pub fn register<T: FjageMessage + Send + std::fmt::Debug + 'static>(&self) {
+        self.registry.write().unwrap().register::<T>();
+    }
+    /// Like [RemoteContainer::recv_filtered], but decodes the message into its registered type.
+    /// Messages of unregistered clazzes come back as [AnyMessage::Generic].
+    pub async fn recv_typed<F: MessageFilter>(
+        &mut self,
+        filter: F,
+    ) -> Result<AnyMessage, GatewayError> {
+        let msg = self.recv_filtered(filter).await?;
+        return Ok(self.registry.read().unwrap().decode(msg));
+    }
+    pub async fn recv_timeout_typed<F: MessageFilter>(
+        &mut self,
+        filter: F,
+        timeout: Duration,
+    ) -> Result<AnyMessage, GatewayError> {
+        let msg = self.recv_timeout_filtered(filter, timeout).await?;
+        return Ok(self.registry.read().unwrap().decode(msg));
+    }
+    /// Interrupt every reception and request in progress, in any task. To abort one particular call instead,
+    /// run it under a [CancelToken](crate::remote::cancel::CancelToken).
     pub fn interrupt(&mut self) {
-        self.msg_interrupt_sender
-            .blocking_send(GatewayReceiveInterrupt::CANCEL)
-            .unwrap();
+        self.interrupts.send_modify(|n| *n = n.wrapping_add(1));
     }
     async fn process_request(&mut self, req: RequestFrame) -> Option<ResponseFrame> {
         return match req {
             RequestFrame::agents { id } => Some(ResponseFrame::agents {
                 id: id,
-                agentIDs: self.agents.lock().await.clone(),
+                agentIDs: self.local_agents(),
+                agentTypes: None,
             }),
             RequestFrame::containsAgent { id, agentID } => Some(ResponseFrame::containsAgent {
                 id: id,
-                answer: self.agents.lock().await.contains(&agentID),
+                answer: self.local_agents().contains(&agentID),
             }),
             RequestFrame::services { id } => Some(ResponseFrame::services {
-                // TODO: investigate possibility of advertising services
                 id: id,
-                services: Vec::new(),
+                services: self.local_services(),
             }),
-            RequestFrame::agentForService { id, service: _ } => {
-                Some(ResponseFrame::agentForService {
-                    // This will always respond "None" because we have not implemented this functionality
-                    id: id,
-                    agentID: None,
-                })
-            }
-            RequestFrame::agentsForService { id, service: _ } => {
+            RequestFrame::agentForService { id, service } => Some(ResponseFrame::agentForService {
+                id: id,
+                agentID: self.local_agents_for_service(&service).first().cloned(),
+            }),
+            RequestFrame::agentsForService { id, service } => {
                 Some(ResponseFrame::agentsForService {
-                    // This will always respond with an empty vector because we have not implemented this functionality
                     id: id,
-                    agentIDs: Vec::new(),
+                    agentIDs: self.local_agents_for_service(&service),
                 })
             }
+            // We have no containers below us, so there is nobody to relay to
             RequestFrame::send {
                 mut message,
                 relay: _,
             } => {
                 message.decode_java_classes();
-                let mut queue = self.msg_queue.lock().await;
-                queue.push(message);
-                self.msg_interrupt_sender
-                    .send(GatewayReceiveInterrupt::MESSAGE)
-                    .await
-                    .unwrap();
-
+                self.deliver(message);
                 None
             }
-            RequestFrame::wantsMessagesFor { agentIDs: _ } => None,
+            RequestFrame::wantsMessagesFor { agentIDs } => {
+                *self.master_wants.lock().unwrap() = Some(agentIDs);
+                None
+            }
+            // We hold no credentials to check, so nobody can authenticate with us
+            RequestFrame::auth { id, creds: _ } => Some(ResponseFrame::auth {
+                id: id,
+                auth: false,
+            }),
+            // Handled by the link task, which owns the connection
+            RequestFrame::shutdown => None,
         };
     }
 }
@@ -1,19 +1,13 @@
-use serde::{Deserialize, Serialize};
-use serde_json::Value;
-use uuid::Uuid;
+use crate::core::{
+    message::{MessageHeader, Performative},
+    typed::FjageMessage,
+};
 
-use crate::core::message::{Message, Performative};
-use crate::protocol::base64::*;
-
-#[allow(non_camel_case_types, non_snake_case)]
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(FjageMessage, Debug, Clone)]
+#[fjage(clazz = "org.arl.fjage.shell.GetFileReq")]
 pub struct GetFileReq {
-    pub msgID: String,
-    pub perf: Performative,
-    pub recipient: String,
-    pub inReplyTo: Option<String>,
-    pub sender: String,
-    pub sentAt: i64,
+    #[fjage(header)]
+    pub header: MessageHeader,
     pub filename: String,
     pub ofs: u64,
     pub len: u64,
@@ -21,37 +15,19 @@ pub struct GetFileReq {
 impl GetFileReq {
     pub fn new(filename: &str) -> GetFileReq {
         return GetFileReq {
-            msgID: Uuid::new_v4().to_string(),
-            perf: Performative::REQUEST,
-            recipient: String::new(),
-            inReplyTo: None,
-            sender: String::new(),
-            sentAt: 0,
+            header: MessageHeader::new(Performative::REQUEST),
             filename: filename.to_string(),
             ofs: 0,
             len: 0,
         };
     }
-    pub fn from_msg(msg: Message) -> GetFileReq {
-        return serde_json::from_value(serde_json::to_value(msg.data).unwrap()).unwrap();
-    }
-    pub fn to_msg(&mut self) -> Message {
-        return Message {
-            clazz: "org.arl.fjage.shell.GetFileReq".to_string(),
-            data: serde_json::from_value(serde_json::to_value(self).unwrap()).unwrap(),
-        };
-    }
 }
 
-#[allow(non_camel_case_types, non_snake_case)]
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(FjageMessage, Debug, Clone)]
+#[fjage(clazz = "org.arl.fjage.shell.GetFileRsp")]
 pub struct GetFileRsp {
-    pub msgID: String,
-    pub perf: Performative,
-    pub recipient: String,
-    pub inReplyTo: Option<String>,
-    pub sender: String,
-    pub sentAt: i64,
+    #[fjage(header)]
+    pub header: MessageHeader,
     pub filename: String,
     pub dir: bool,
     pub contents: Vec<u8>,
@@ -60,38 +36,20 @@ pub struct GetFileRsp {
 impl GetFileRsp {
     pub fn new(filename: &str) -> GetFileRsp {
         return GetFileRsp {
-            msgID: Uuid::new_v4().to_string(),
-            perf: Performative::REQUEST,
-            recipient: String::new(),
-            inReplyTo: None,
-            sender: String::new(),
-            sentAt: 0,
+            header: MessageHeader::new(Performative::INFORM),
             filename: filename.to_string(),
             dir: false,
             contents: Vec::new(),
             ofs: 0,
         };
     }
-    pub fn from_msg(msg: Message) -> GetFileRsp {
-        return serde_json::from_value(serde_json::to_value(msg.data).unwrap()).unwrap();
-    }
-    pub fn to_msg(&mut self) -> Message {
-        return Message {
-            clazz: "org.arl.fjage.shell.GetFileRsp".to_string(),
-            data: serde_json::from_value(serde_json::to_value(self).unwrap()).unwrap(),
-        };
-    }
 }
 
-#[allow(non_camel_case_types, non_snake_case)]
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(FjageMessage, Debug, Clone)]
+#[fjage(clazz = "org.arl.fjage.shell.PutFileReq")]
 pub struct PutFileReq {
-    pub msgID: String,
-    pub perf: Performative,
-    pub recipient: String,
-    pub inReplyTo: Option<String>,
-    pub sender: String,
-    pub sentAt: i64,
+    #[fjage(header)]
+    pub header: MessageHeader,
     pub filename: String,
     pub contents: Option<Vec<u8>>,
     pub ofs: u64,
@@ -99,12 +57,7 @@ pub struct PutFileReq {
 impl PutFileReq {
     pub fn new(filename: &str) -> PutFileReq {
         return PutFileReq {
-            msgID: Uuid::new_v4().to_string(),
-            perf: Performative::REQUEST,
-            recipient: String::new(),
-            inReplyTo: None,
-            sender: String::new(),
-            sentAt: 0,
+            header: MessageHeader::new(Performative::REQUEST),
             filename: filename.to_string(),
             contents: None,
             ofs: 0,
@@ -120,20 +73,4 @@ impl PutFileReq {
         req.contents = Some(contents.to_vec());
         return req;
     }
-    pub fn from_msg(msg: Message) -> PutFileReq {
-        return serde_json::from_value(serde_json::to_value(msg.data).unwrap()).unwrap();
-    }
-    pub fn to_msg(&mut self) -> Message {
-        let mut b64_obj = Value::Null;
-        if self.contents.is_some() {
-            b64_obj = b64_obj_from_u8(self.contents.as_ref().unwrap());
-        }
-
-        let mut msg = Message {
-            clazz: "org.arl.fjage.shell.PutFileReq".to_string(),
-            data: serde_json::from_value(serde_json::to_value(self).unwrap()).unwrap(),
-        };
-        msg.data.fields.insert("contents".to_string(), b64_obj);
-        return msg;
-    }
 }
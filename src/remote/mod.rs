@@ -1,4 +1,8 @@
 //pub mod container;
+pub mod agent;
+pub mod behaviour;
+pub mod cancel;
 pub mod container;
 pub mod file;
+pub mod queue;
 pub mod shell;
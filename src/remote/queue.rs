@@ -0,0 +1,189 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tokio::sync::Notify;
+
+use crate::core::filter::{any, MessageFilter};
+use crate::core::message::Message;
+
+/// Which message a full queue gives up to make room
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Discard the oldest queued message, keeping the most recent ones
+    DROP_OLDEST,
+    /// Discard the message that just arrived, keeping what is already queued
+    DROP_NEWEST,
+}
+
+/// Limits on a queue of messages waiting to be read: the one behind [RemoteContainer::recv](crate::remote::container::RemoteContainer::recv),
+/// or those of each message stream, message listener and hosted agent
+#[derive(Clone, Debug)]
+pub struct QueueLimits {
+    /// Messages held at most
+    pub capacity: usize,
+    pub overflow: OverflowPolicy,
+    /// Discard messages that have waited longer than this since they arrived. `None` keeps them until read.
+    pub max_age: Option<Duration>,
+}
+impl QueueLimits {
+    /// No limits at all, as before queues were bounded
+    pub fn unbounded() -> QueueLimits {
+        return QueueLimits {
+            capacity: usize::MAX,
+            overflow: OverflowPolicy::DROP_OLDEST,
+            max_age: None,
+        };
+    }
+}
+impl Default for QueueLimits {
+    fn default() -> QueueLimits {
+        return QueueLimits {
+            capacity: 1024,
+            overflow: OverflowPolicy::DROP_OLDEST,
+            max_age: None,
+        };
+    }
+}
+
+/// Counters for one or more message queues
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct QueueStats {
+    /// Messages waiting to be read
+    pub queued: usize,
+    /// Messages discarded because the queue was full
+    pub dropped_full: u64,
+    /// Messages discarded because they were older than [QueueLimits::max_age]
+    pub dropped_expired: u64,
+}
+
+/// The messages waiting for `recv`, oldest first, with the time each arrived
+pub(crate) struct MessageQueue {
+    messages: VecDeque<(Instant, Message)>,
+    limits: QueueLimits,
+    stats: QueueStats,
+}
+impl MessageQueue {
+    pub fn new(limits: QueueLimits) -> MessageQueue {
+        return MessageQueue {
+            messages: VecDeque::new(),
+            limits: limits,
+            stats: QueueStats::default(),
+        };
+    }
+    /// Change the limits, discarding whatever no longer fits
+    pub fn set_limits(&mut self, limits: QueueLimits) {
+        self.limits = limits;
+        self.expire();
+        while self.messages.len() > self.limits.capacity {
+            match self.limits.overflow {
+                OverflowPolicy::DROP_OLDEST => self.messages.pop_front(),
+                OverflowPolicy::DROP_NEWEST => self.messages.pop_back(),
+            };
+            self.stats.dropped_full += 1;
+        }
+    }
+    pub fn limits(&self) -> QueueLimits {
+        return self.limits.clone();
+    }
+    /// Add a message that has just arrived. Returns whether it was kept.
+    pub fn push(&mut self, msg: Message) -> bool {
+        self.expire();
+        if self.messages.len() >= self.limits.capacity {
+            self.stats.dropped_full += 1;
+            match self.limits.overflow {
+                OverflowPolicy::DROP_NEWEST => return false,
+                OverflowPolicy::DROP_OLDEST => {
+                    self.messages.pop_front();
+                }
+            }
+            // A capacity of zero keeps nothing
+            if self.limits.capacity == 0 {
+                return false;
+            }
+        }
+        self.messages.push_back((Instant::now(), msg));
+        return true;
+    }
+    /// Remove and return the oldest message accepted by `filter`
+    pub fn take<F: MessageFilter + ?Sized>(&mut self, filter: &F) -> Option<Message> {
+        self.expire();
+        let pos = self
+            .messages
+            .iter()
+            .position(|(_, msg)| filter.matches(msg))?;
+        return self.messages.remove(pos).map(|(_, msg)| msg);
+    }
+    pub fn stats(&mut self) -> QueueStats {
+        self.expire();
+        return QueueStats {
+            queued: self.messages.len(),
+            ..self.stats
+        };
+    }
+    /// Discard the messages that have outlived [QueueLimits::max_age]. They are the oldest, so they are at the front.
+    fn expire(&mut self) {
+        let Some(max_age) = self.limits.max_age else {
+            return;
+        };
+        while self
+            .messages
+            .front()
+            .is_some_and(|(arrived, _)| arrived.elapsed() > max_age)
+        {
+            self.messages.pop_front();
+            self.stats.dropped_expired += 1;
+        }
+    }
+}
+
+/// A [MessageQueue] read by a single task, as behind a message stream or a hosted agent
+pub(crate) struct Mailbox {
+    queue: Mutex<MessageQueue>,
+    arrived: Notify,
+    closed: AtomicBool,
+}
+impl Mailbox {
+    pub fn new(limits: QueueLimits) -> Mailbox {
+        return Mailbox {
+            queue: Mutex::new(MessageQueue::new(limits)),
+            arrived: Notify::new(),
+            closed: AtomicBool::new(false),
+        };
+    }
+    /// Add a message, dropping one if the mailbox is full. Returns whether it was kept.
+    pub fn push(&self, msg: Message) -> bool {
+        let kept = self.queue.lock().unwrap().push(msg);
+        if kept {
+            self.arrived.notify_one();
+        }
+        return kept;
+    }
+    /// The oldest message, waiting for one if there is none. `None` once the mailbox is closed and empty.
+    pub async fn recv(&self) -> Option<Message> {
+        loop {
+            let msg = self.queue.lock().unwrap().take(&any());
+            if msg.is_some() {
+                return msg;
+            }
+            if self.closed.load(Ordering::SeqCst) {
+                return None;
+            }
+            // A push or close since we looked left a permit, so this cannot miss it
+            self.arrived.notified().await;
+        }
+    }
+    /// Wake the reader to tell it nothing more will arrive
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        self.arrived.notify_one();
+    }
+    pub fn set_limits(&self, limits: QueueLimits) {
+        self.queue.lock().unwrap().set_limits(limits);
+    }
+    pub fn stats(&self) -> QueueStats {
+        return self.queue.lock().unwrap().stats();
+    }
+}
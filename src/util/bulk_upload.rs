@@ -7,7 +7,11 @@ use std::{
 
 use anyhow::anyhow;
 use clap::Parser;
-use fjage_rs::{api::gateway::Gateway, core::message::Performative, remote::file::PutFileReq};
+use fjage_rs::{
+    api::gateway::Gateway,
+    core::{message::Performative, typed::FjageMessage},
+    remote::file::PutFileReq,
+};
 use indicatif::{ProgressBar, ProgressStyle};
 
 use std::fs::OpenOptions;
@@ -41,15 +45,13 @@ fn main() -> anyhow::Result<()> {
     //file.read_to_string(&mut contents).unwrap();
 
     // Connect to gateway
-    let mut gw = Gateway::new_tcp(&args.hostname, args.port);
+    let gw = Gateway::new_tcp(&args.hostname, args.port)?;
 
     // Find an agent advertising the SHELL service
-    let shell = gw
-        .agent_for_service("org.arl.fjage.shell.Services.SHELL")
-        .unwrap();
+    let shell = gw.agent_for_service("org.arl.fjage.shell.Services.SHELL")?;
 
     // Subscribe to the shell agent
-    gw.subscribe_agent(&shell);
+    gw.subscribe_agent(&shell)?;
 
     let chunk_len: u64 = args.chunk_len as u64;
 
@@ -94,11 +96,7 @@ fn main() -> anyhow::Result<()> {
             let mut msg = PutFileReq::new_contents(&remote_file_path, &buffer[0..bytes_read]);
             msg.ofs = offset;
             let rsp = gw.request(&shell, msg.to_msg());
-            let rsp = rsp.unwrap();
-            if match rsp.data.perf {
-                Performative::AGREE => true,
-                _ => false,
-            } {
+            if rsp.is_ok_and(|rsp| matches!(rsp.data.perf, Performative::AGREE)) {
                 //println!("FILE CHUNK UPLOADED SUCCESSFULLY");
             } else {
                 println!("FILE CHUNK FAILED TO UPLOAD");
@@ -7,6 +7,7 @@ use std::{
 
 use fjage_rs::{
     api::gateway::Gateway,
+    core::typed::FjageMessage,
     remote::file::{GetFileReq, GetFileRsp},
 };
 
@@ -32,7 +33,7 @@ fn main() {
     let port = port.unwrap();
 
     // Connect to gateway
-    let mut gw = Gateway::new_tcp(hostname, port);
+    let gw = Gateway::new_tcp(hostname, port).unwrap();
 
     // Find an agent advertising the SHELL service
     let shell = gw
@@ -40,12 +41,12 @@ fn main() {
         .unwrap();
 
     // Subscribe to the shell agent
-    gw.subscribe_agent(&shell);
+    gw.subscribe_agent(&shell).unwrap();
 
     // Construct and send a GetFileReq
-    let mut msg = GetFileReq::new(args.get(3).unwrap());
+    let msg = GetFileReq::new(args.get(3).unwrap());
     let rsp = gw.request(&shell, msg.to_msg());
-    let rsp = GetFileRsp::from_msg(rsp.unwrap());
+    let rsp = GetFileRsp::from_msg(rsp.unwrap()).unwrap();
 
     // If the user specified an output file, write response to it. If not, print output to the console
     if args.len() == 5 {
@@ -4,7 +4,11 @@ use std::{
     path::Path,
 };
 
-use fjage_rs::{api::gateway::Gateway, core::message::Performative, remote::file::PutFileReq};
+use fjage_rs::{
+    api::gateway::Gateway,
+    core::{message::Performative, typed::FjageMessage},
+    remote::file::PutFileReq,
+};
 
 use std::fs::OpenOptions;
 
@@ -43,7 +47,7 @@ fn main() {
     //file.read_to_string(&mut contents).unwrap();
 
     // Connect to gateway
-    let mut gw = Gateway::new_tcp(hostname, port);
+    let gw = Gateway::new_tcp(hostname, port).unwrap();
 
     // Find an agent advertising the SHELL service
     let shell = gw
@@ -51,7 +55,7 @@ fn main() {
         .unwrap();
 
     // Subscribe to the shell agent
-    gw.subscribe_agent(&shell);
+    gw.subscribe_agent(&shell).unwrap();
 
     let chunk_len: u64 = 1024 * 64;
 
@@ -67,11 +71,7 @@ fn main() {
         let mut msg = PutFileReq::new_contents(&remote_file_path, &buffer[0..bytes_read]);
         msg.ofs = offset;
         let rsp = gw.request(&shell, msg.to_msg());
-        let rsp = rsp.unwrap();
-        if match rsp.data.perf {
-            Performative::AGREE => true,
-            _ => false,
-        } {
+        if rsp.is_ok_and(|rsp| matches!(rsp.data.perf, Performative::AGREE)) {
             println!("FILE CHUNK UPLOADED SUCCESSFULLY");
         } else {
             println!("FILE CHUNK FAILED TO UPLOAD");
@@ -3,7 +3,11 @@ use std::{
     io::{self, BufRead, BufReader, Write},
 };
 
-use fjage_rs::{api::gateway::Gateway, core::message::Performative, remote::shell::ShellExecReq};
+use fjage_rs::{
+    api::gateway::Gateway,
+    core::{message::Performative, typed::FjageMessage},
+    remote::shell::ShellExecReq,
+};
 
 // Interactive remote shell
 
@@ -19,7 +23,7 @@ fn main() {
         return;
     }
 
-    let mut gw = if args.get(1).unwrap() == "--rs232" {
+    let gw = if args.get(1).unwrap() == "--rs232" {
         let dev: &str = args.get(2).unwrap();
         let baud: Result<u32, _> = args.get(3).unwrap().parse();
         if baud.is_err() {
@@ -29,7 +33,7 @@ fn main() {
         let baud = baud.unwrap();
 
         // Connect to gateway
-        Gateway::new_serial(dev, baud)
+        Gateway::new_serial(dev, baud).unwrap()
     } else {
         let hostname: &str = args.get(1).unwrap();
         let port: Result<u16, _> = args.get(2).unwrap().parse();
@@ -40,7 +44,7 @@ fn main() {
         let port = port.unwrap();
 
         // Connect to gateway
-        Gateway::new_tcp(hostname, port)
+        Gateway::new_tcp(hostname, port).unwrap()
     };
 
     // Find an agent advertising the SHELL service
@@ -50,7 +54,7 @@ fn main() {
 
     // Subscribe to the shell agent
 
-    gw.subscribe_agent(&shell);
+    gw.subscribe_agent(&shell).unwrap();
 
     let mut reader = BufReader::new(io::stdin());
 
@@ -63,21 +67,19 @@ fn main() {
 
         reader.read_line(&mut cmd).unwrap();
 
-        let mut msg = ShellExecReq::new(&cmd);
-        let rsp = gw.request(&shell, msg.to_msg()).unwrap();
-
-        if match rsp.data.perf {
-            Performative::AGREE => true,
-            _ => false,
-        } {
-            if rsp.data.fields.contains_key("ans") {
-                println!(
-                    "\n{}",
-                    rsp.data.fields.get("ans").unwrap().as_str().unwrap()
-                );
+        let msg = ShellExecReq::new(&cmd);
+        let rsp = gw.request(&shell, msg.to_msg());
+
+        match rsp {
+            Ok(rsp) if matches!(rsp.data.perf, Performative::AGREE) => {
+                if rsp.data.fields.contains_key("ans") {
+                    println!(
+                        "\n{}",
+                        rsp.data.fields.get("ans").unwrap().as_str().unwrap()
+                    );
+                }
             }
-        } else {
-            println!("COMMAND FAILED");
+            _ => println!("COMMAND FAILED"),
         }
     }
 }
@@ -1,6 +1,10 @@
 use std::env;
 
-use fjage_rs::{api::gateway::Gateway, core::message::Performative, remote::shell::ShellExecReq};
+use fjage_rs::{
+    api::gateway::Gateway,
+    core::{message::Performative, typed::FjageMessage},
+    remote::shell::ShellExecReq,
+};
 
 // Execute a shell command remotely
 
@@ -25,26 +29,22 @@ fn main() {
     let cmd: &str = args.get(3).unwrap();
 
     // Connect to gateway
-    let mut gw = Gateway::new_tcp(hostname, port);
+    let gw = Gateway::new_tcp(hostname, port).unwrap();
     // Find an agent advertising the SHELL service
     let shell = gw
         .agent_for_service("org.arl.fjage.shell.Services.SHELL")
         .unwrap();
 
     // Subscribe to the shell agent
-    gw.subscribe_agent(&shell);
-    let mut msg = ShellExecReq::new(cmd);
-    let rsp = gw.request(&shell, msg.to_msg()).unwrap();
+    gw.subscribe_agent(&shell).unwrap();
+    let msg = ShellExecReq::new(cmd);
+    let rsp = gw.request(&shell, msg.to_msg());
 
-    if match rsp.data.perf {
-        Performative::AGREE => true,
-        _ => false,
-    } {
-        println!(
+    match rsp {
+        Ok(rsp) if matches!(rsp.data.perf, Performative::AGREE) => println!(
             "SUCCESS:\n{}",
             rsp.data.fields.get("ans").unwrap().as_str().unwrap()
-        );
-    } else {
-        println!("COMMAND FAILED");
+        ),
+        _ => println!("COMMAND FAILED"),
     }
 }
@@ -0,0 +1,282 @@
+mod common;
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use common::MockMaster;
+use fjage_rs::{
+    api::gateway::Gateway,
+    core::{
+        aid::AgentID,
+        error::GatewayError,
+        message::{Message, Performative},
+    },
+    protocol::frame::RequestFrame,
+    remote::{
+        agent::{Agent, AgentContext},
+        behaviour::{
+            BehaviourHandle, FsmBehaviour, FsmStep, MessageBehaviour, OneShotBehaviour,
+            TickerBehaviour, WakerBehaviour,
+        },
+    },
+};
+use serde_json::json;
+
+/// Forwards everything it receives to `forward_to`, tagged with the clazz "Echo"
+struct EchoAgent {
+    forward_to: AgentID,
+    stopped: Arc<AtomicBool>,
+}
+impl Agent for EchoAgent {
+    fn init(&mut self, ctx: &AgentContext) {
+        ctx.register("org.arl.fjage.test.Services.ECHO");
+        ctx.subscribe(&AgentID::topic("news")).unwrap();
+    }
+    fn process_message(&mut self, ctx: &AgentContext, msg: Message) {
+        let mut echo = msg.clone();
+        echo.clazz = "Echo".to_string();
+        echo.data.sender = AgentID::default();
+        ctx.send(&self.forward_to, echo).unwrap();
+    }
+    fn shutdown(&mut self, _ctx: &AgentContext) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Poll `cond` for up to two seconds
+fn eventually<F: Fn() -> bool>(cond: F) -> bool {
+    for _ in 0..100 {
+        if cond() {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    return false;
+}
+
+fn ntf(to: &str) -> Message {
+    let mut msg = Message::new_generic("Ntf", Performative::INFORM, json!({}));
+    msg.data.recipient = AgentID::from(to);
+    return msg;
+}
+
+#[test]
+fn hosted_agents_are_visible_to_the_master() {
+    let master = MockMaster::start_default();
+    let gw = Gateway::new_tcp("127.0.0.1", master.port()).unwrap();
+    let stopped = Arc::new(AtomicBool::new(false));
+    gw.add(
+        "echo",
+        EchoAgent {
+            forward_to: gw.get_agent_id(),
+            stopped: stopped.clone(),
+        },
+    )
+    .unwrap();
+    assert!(matches!(
+        gw.add(
+            "echo",
+            EchoAgent {
+                forward_to: AgentID::default(),
+                stopped: stopped.clone(),
+            }
+        ),
+        Err(GatewayError::DuplicateAgent(_))
+    ));
+
+    let rsp = master
+        .query(RequestFrame::agentForService {
+            id: "1".to_string(),
+            service: "org.arl.fjage.test.Services.ECHO".to_string(),
+        })
+        .unwrap();
+    assert_eq!(rsp.get_agent_id(), Some(AgentID::agent("echo")));
+    let rsp = master
+        .query(RequestFrame::agents {
+            id: "2".to_string(),
+        })
+        .unwrap();
+    assert_eq!(
+        rsp.get_agent_ids(),
+        Some(vec![gw.get_agent_id(), AgentID::agent("echo")])
+    );
+    let rsp = master
+        .query(RequestFrame::services {
+            id: "3".to_string(),
+        })
+        .unwrap();
+    assert_eq!(
+        rsp.get_services(),
+        Some(vec!["org.arl.fjage.test.Services.ECHO".to_string()])
+    );
+
+    gw.remove_agent(&AgentID::agent("echo")).unwrap();
+    assert!(eventually(|| stopped.load(Ordering::SeqCst)));
+}
+
+#[test]
+fn messages_reach_the_agent_they_are_addressed_to() {
+    let master = MockMaster::start_default();
+    let gw = Gateway::new_tcp("127.0.0.1", master.port()).unwrap();
+    gw.add(
+        "echo",
+        EchoAgent {
+            forward_to: gw.get_agent_id(),
+            stopped: Arc::new(AtomicBool::new(false)),
+        },
+    )
+    .unwrap();
+
+    // A round trip guarantees the master has seen the agent's subscriptions
+    gw.agents().unwrap();
+    // Sent by the master, both directly and through a topic the agent subscribes to
+    master.send(ntf("echo"));
+    master.send(ntf("#news"));
+    for _ in 0..2 {
+        let echo = gw.recv_timeout(None, None, Duration::from_secs(1)).unwrap();
+        assert_eq!(echo.clazz, "Echo");
+        assert_eq!(echo.data.sender, "echo");
+    }
+
+    // Sent from the gateway, without a round trip through the master
+    gw.send(&AgentID::agent("echo"), ntf("echo")).unwrap();
+    let echo = gw.recv_timeout(None, None, Duration::from_secs(1)).unwrap();
+    assert_eq!(echo.clazz, "Echo");
+    assert!(master.inbox("echo").is_empty());
+}
+
+fn report(clazz: &str) -> Message {
+    return Message::new_generic(clazz, Performative::INFORM, json!({}));
+}
+
+/// Reports what its behaviours do to the gateway, as messages whose clazz names the behaviour
+struct BusyAgent {
+    report_to: AgentID,
+    ticks: Arc<AtomicUsize>,
+}
+impl Agent for BusyAgent {
+    fn init(&mut self, ctx: &AgentContext) {
+        let to = self.report_to.clone();
+        ctx.add(OneShotBehaviour::new(move |ctx| {
+            let to = to.clone();
+            async move { ctx.send(&to, report("OneShot")).unwrap() }
+        }));
+        let to = self.report_to.clone();
+        ctx.add(WakerBehaviour::new(Duration::from_millis(50), move |ctx| {
+            let to = to.clone();
+            async move { ctx.send(&to, report("Waker")).unwrap() }
+        }));
+        let ticks = self.ticks.clone();
+        let ticker = ctx.add(TickerBehaviour::new(Duration::from_millis(10), move |_| {
+            ticks.fetch_add(1, Ordering::SeqCst);
+            async {}
+        }));
+        let to = self.report_to.clone();
+        ctx.add(MessageBehaviour::for_clazz("StopTicker", move |ctx, _| {
+            ticker.stop();
+            // Reports again once a running ticker would have ticked several more times
+            let later = to.clone();
+            ctx.add(WakerBehaviour::new(Duration::from_millis(50), move |ctx| {
+                let to = later.clone();
+                async move { ctx.send(&to, report("Checked")).unwrap() }
+            }));
+            let to = to.clone();
+            async move { ctx.send(&to, report("Stopped")).unwrap() }
+        }));
+        let to = self.report_to.clone();
+        let mut countdown = 3;
+        ctx.add(
+            FsmBehaviour::new("counting")
+                .state("counting", move |_| {
+                    countdown -= 1;
+                    let step = if countdown == 0 {
+                        FsmStep::GOTO("done".to_string())
+                    } else {
+                        FsmStep::WAIT(Duration::from_millis(5))
+                    };
+                    async move { step }
+                })
+                .state("done", move |ctx| {
+                    let to = to.clone();
+                    async move {
+                        ctx.send(&to, report("Fsm")).unwrap();
+                        FsmStep::DONE
+                    }
+                }),
+        );
+    }
+}
+
+#[test]
+fn behaviours_run_on_the_container_runtime() {
+    let master = MockMaster::start_default();
+    let gw = Gateway::new_tcp("127.0.0.1", master.port()).unwrap();
+    let ticks = Arc::new(AtomicUsize::new(0));
+    gw.add(
+        "busy",
+        BusyAgent {
+            report_to: gw.get_agent_id(),
+            ticks: ticks.clone(),
+        },
+    )
+    .unwrap();
+
+    let mut reports = Vec::new();
+    for _ in 0..3 {
+        let msg = gw.recv_timeout(None, None, Duration::from_secs(1)).unwrap();
+        reports.push(msg.clazz.clone());
+    }
+    reports.sort();
+    assert_eq!(reports, vec!["Fsm", "OneShot", "Waker"]);
+
+    assert!(eventually(|| ticks.load(Ordering::SeqCst) > 0));
+    let mut stop = Message::new_generic("StopTicker", Performative::REQUEST, json!({}));
+    stop.data.recipient = AgentID::agent("busy");
+    gw.send(&AgentID::agent("busy"), stop).unwrap();
+    let msg = gw.recv_timeout(None, None, Duration::from_secs(1)).unwrap();
+    assert_eq!(msg.clazz, "Stopped");
+    let stopped_at = ticks.load(Ordering::SeqCst);
+    let msg = gw.recv_timeout(None, None, Duration::from_secs(1)).unwrap();
+    assert_eq!(msg.clazz, "Checked");
+    assert_eq!(ticks.load(Ordering::SeqCst), stopped_at);
+}
+
+/// Starts a one-shot and a ticker, and hands their handles out
+struct HandleAgent {
+    handles: Arc<Mutex<Vec<BehaviourHandle>>>,
+}
+impl Agent for HandleAgent {
+    fn init(&mut self, ctx: &AgentContext) {
+        let once = ctx.add(OneShotBehaviour::new(|_| async {}));
+        let ticker = ctx.add(TickerBehaviour::new(Duration::from_secs(60), |_| async {}));
+        self.handles.lock().unwrap().extend([once, ticker]);
+    }
+}
+
+#[test]
+fn finished_behaviours_are_forgotten() {
+    let master = MockMaster::start_default();
+    let gw = Gateway::new_tcp("127.0.0.1", master.port()).unwrap();
+    let handles = Arc::new(Mutex::new(Vec::new()));
+    gw.add(
+        "handles",
+        HandleAgent {
+            handles: handles.clone(),
+        },
+    )
+    .unwrap();
+
+    let handles = handles.lock().unwrap().clone();
+    let [once, ticker] = handles.as_slice() else {
+        panic!("expected two handles, got {}", handles.len());
+    };
+    assert!(eventually(|| !once.is_running()));
+    assert!(ticker.is_running());
+    ticker.stop();
+    assert!(!ticker.is_running());
+}
@@ -0,0 +1,24 @@
+use fjage_rs::core::{aid::AgentID, message::Message};
+
+#[test]
+fn topics_and_agents_keep_their_wire_form() {
+    let shell = AgentID::agent("shell");
+    assert!(!shell.is_topic());
+    assert_eq!(AgentID::agent_topic(&shell).to_string(), "#shell__ntf");
+    assert_eq!(
+        AgentID::agent_named_topic(&shell, "status").to_string(),
+        "#shell__status__ntf"
+    );
+    assert_eq!("#news".parse::<AgentID>().unwrap(), AgentID::topic("news"));
+    assert_ne!(AgentID::agent("news"), AgentID::topic("news"));
+
+    let mut msg = Message::new();
+    msg.data.recipient = AgentID::topic("news");
+    msg.data.sender = shell.clone();
+    let json = serde_json::to_value(&msg).unwrap();
+    assert_eq!(json["data"]["recipient"], "#news");
+    assert_eq!(json["data"]["sender"], "shell");
+    let msg: Message = serde_json::from_value(json).unwrap();
+    assert!(msg.data.recipient.is_topic());
+    assert_eq!(msg.data.sender, shell);
+}
@@ -0,0 +1,68 @@
+mod common;
+
+use std::{fs, process::Command};
+
+use common::{MockAgent, MockMaster};
+
+fn run(bin: &str, args: &[&str]) -> String {
+    let output = Command::new(bin).args(args).output().unwrap();
+    assert!(output.status.success(), "{} failed: {:?}", bin, output);
+    return String::from_utf8_lossy(&output.stdout).to_string();
+}
+
+#[test]
+fn remote_shell_exec_prints_the_answer() {
+    let master = MockMaster::start(vec![
+        MockAgent::shell("shell").exec(|cmd| Ok(format!("ran {}", cmd)))
+    ]);
+    let port = master.port().to_string();
+    let out = run(
+        env!("CARGO_BIN_EXE_remote_shell_exec"),
+        &["127.0.0.1", &port, "ps"],
+    );
+    assert!(out.contains("SUCCESS:\nran ps"), "{}", out);
+    assert_eq!(master.inbox("shell").len(), 1);
+}
+
+#[test]
+fn get_file_writes_the_remote_file() {
+    let master = MockMaster::start(vec![
+        MockAgent::shell("shell").file("logs/log-0.txt", b"hello\n")
+    ]);
+    let port = master.port().to_string();
+    let dir = std::env::temp_dir().join(format!("fjage-rs-get-file-{}", std::process::id()));
+    let local = dir.join("log.txt");
+    run(
+        env!("CARGO_BIN_EXE_get_file"),
+        &[
+            "127.0.0.1",
+            &port,
+            "logs/log-0.txt",
+            local.to_str().unwrap(),
+        ],
+    );
+    assert_eq!(fs::read(&local).unwrap(), b"hello\n");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn put_file_uploads_the_local_file() {
+    let master = MockMaster::start(vec![MockAgent::shell("shell")]);
+    let port = master.port().to_string();
+    let local = std::env::temp_dir().join(format!("fjage-rs-put-file-{}", std::process::id()));
+    fs::write(&local, b"uploaded contents").unwrap();
+    run(
+        env!("CARGO_BIN_EXE_put_file"),
+        &[
+            "127.0.0.1",
+            &port,
+            local.to_str().unwrap(),
+            "scripts/up.txt",
+        ],
+    );
+    assert_eq!(
+        master.file("shell", "scripts/up.txt"),
+        Some(b"uploaded contents".to_vec())
+    );
+    fs::remove_file(&local).unwrap();
+}
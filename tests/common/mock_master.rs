@@ -0,0 +1,742 @@
+use std::{
+    collections::HashMap,
+    net::TcpListener as StdTcpListener,
+    sync::{mpsc as std_mpsc, Arc, Mutex},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use fjage_rs::{
+    core::{
+        aid::AgentID,
+        message::{Message, Performative},
+        param::{ParameterReq, ParameterRsp},
+        typed::FjageMessage,
+    },
+    protocol::frame::{Frame, RequestFrame, ResponseFrame},
+    remote::{
+        file::{GetFileReq, GetFileRsp, PutFileReq},
+        shell::ShellExecReq,
+    },
+};
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    runtime::Handle,
+    sync::{mpsc, oneshot},
+};
+use tokio_tungstenite::tungstenite;
+use uuid::Uuid;
+
+pub const SHELL_SERVICE: &str = "org.arl.fjage.shell.Services.SHELL";
+pub const PARAMETER_TEST_SERVICE: &str = "org.arl.fjage.test.Services.PARAMETER_TEST";
+
+type Handler = Box<dyn Fn(&Message) -> Option<Message> + Send>;
+type ShellExec = Box<dyn Fn(&str) -> Result<String, String> + Send>;
+
+/// A fake agent hosted by [MockMaster]. Agents answer `ParameterReq` from their parameter table,
+/// and agents with a shell answer `ShellExecReq`, `GetFileReq` and `PutFileReq`.
+/// Anything else goes to the handlers added with [MockAgent::on_message], or is NOT_UNDERSTOOD.
+pub struct MockAgent {
+    name: String,
+    services: Vec<String>,
+    params: HashMap<(String, i64), Value>,
+    readonly: Vec<String>,
+    shell: Option<ShellExec>,
+    files: HashMap<String, Vec<u8>>,
+    handlers: Vec<Handler>,
+    inbox: Vec<Message>,
+}
+
+impl MockAgent {
+    pub fn new(name: &str) -> MockAgent {
+        MockAgent {
+            name: name.to_string(),
+            services: Vec::new(),
+            params: HashMap::new(),
+            readonly: Vec::new(),
+            shell: None,
+            files: HashMap::new(),
+            handlers: Vec::new(),
+            inbox: Vec::new(),
+        }
+    }
+    /// An agent with the parameters of `GatewayTestAgent` from `tests/docker/dummy.groovy`.
+    pub fn gateway_test_agent(name: &str) -> MockAgent {
+        return MockAgent::new(name)
+            .service(PARAMETER_TEST_SERVICE)
+            .readonly_param("roIntParam", json!(42))
+            .readonly_param("roLongParam", json!(50))
+            .readonly_param("roFloatParam", json!(20.99))
+            .readonly_param("roStringParam", json!("FINAL STRING"))
+            .param("rwBoolParam", json!(false))
+            .param("rwIntParam", json!(72))
+            .param("rwLongParam", json!(99))
+            .param("rwFloatParam", json!(277.76))
+            .param("rwDoubleParam", json!(79.99))
+            .param("rwStringParam", json!("MUTABLE STRING"))
+            .param("rwIntArrayParam", json!([27]))
+            .param("rwLongArrayParam", json!([640, 480]))
+            .param("rwFloatArrayParam", json!([27.0, -26.0, 25.0]))
+            .param("rwDoubleArrayParam", json!([0.0, 0.0, 15.0]))
+            .param("rwStringArrayParam", json!(["World", "Hello"]));
+    }
+    /// An agent advertising the shell service. Commands are echoed back unless [MockAgent::exec] says otherwise.
+    pub fn shell(name: &str) -> MockAgent {
+        let mut agent = MockAgent::new(name).service(SHELL_SERVICE);
+        agent.shell = Some(Box::new(|cmd| Ok(cmd.to_string())));
+        return agent;
+    }
+    pub fn service(mut self, service: &str) -> MockAgent {
+        self.services.push(service.to_string());
+        return self;
+    }
+    pub fn param(self, param: &str, value: Value) -> MockAgent {
+        return self.indexed_param(param, -1, value);
+    }
+    pub fn indexed_param(mut self, param: &str, index: i64, value: Value) -> MockAgent {
+        self.params.insert((param.to_string(), index), value);
+        return self;
+    }
+    pub fn readonly_param(mut self, param: &str, value: Value) -> MockAgent {
+        self.readonly.push(param.to_string());
+        return self.param(param, value);
+    }
+    /// Script the answers to `ShellExecReq`. `Err` is answered with FAILURE.
+    pub fn exec<F: Fn(&str) -> Result<String, String> + Send + 'static>(
+        mut self,
+        exec: F,
+    ) -> MockAgent {
+        self.shell = Some(Box::new(exec));
+        return self;
+    }
+    pub fn file(mut self, filename: &str, contents: &[u8]) -> MockAgent {
+        self.files.insert(filename.to_string(), contents.to_vec());
+        return self;
+    }
+    /// Add a handler that is offered every message before the built-in behaviour. Returning `None`
+    /// passes the message on. The reply is addressed back to the sender of the message.
+    pub fn on_message<F: Fn(&Message) -> Option<Message> + Send + 'static>(
+        mut self,
+        handler: F,
+    ) -> MockAgent {
+        self.handlers.push(Box::new(handler));
+        return self;
+    }
+
+    fn handle(&mut self, msg: Message) -> Option<Message> {
+        self.inbox.push(msg.clone());
+        for handler in self.handlers.iter() {
+            if let Some(rsp) = handler(&msg) {
+                return Some(reply_to(&msg, &self.name, rsp));
+            }
+        }
+        let rsp = match msg.clazz.as_str() {
+            "org.arl.fjage.param.ParameterReq" => self.handle_param(msg.clone()),
+            "org.arl.fjage.shell.ShellExecReq" if self.shell.is_some() => {
+                self.handle_exec(msg.clone())
+            }
+            "org.arl.fjage.shell.GetFileReq" if self.shell.is_some() => {
+                self.handle_get_file(msg.clone())
+            }
+            "org.arl.fjage.shell.PutFileReq" if self.shell.is_some() => {
+                self.handle_put_file(msg.clone())
+            }
+            _ => {
+                if !matches!(msg.data.perf, Performative::REQUEST) {
+                    return None;
+                }
+                Message::new_generic(
+                    "org.arl.fjage.Message",
+                    Performative::NOT_UNDERSTOOD,
+                    json!({}),
+                )
+            }
+        };
+        return Some(reply_to(&msg, &self.name, rsp));
+    }
+    fn handle_param(&mut self, mut msg: Message) -> Message {
+        msg.decode_java_classes();
+        let req = ParameterReq::from_msg(msg).unwrap();
+        let mut rsp = ParameterRsp::new();
+        rsp.index = req.index;
+        rsp.readonly = Some(self.readonly.clone());
+        if let Some(param) = req.param {
+            match self.access_param(&param, req.index, req.value) {
+                Some(value) => {
+                    rsp.param = Some(param);
+                    rsp.value = value;
+                }
+                None => {
+                    rsp.header.perf = Performative::FAILURE;
+                }
+            }
+        }
+        if let Some(requests) = req.requests {
+            let mut values = HashMap::new();
+            for (param, value) in requests {
+                if let Some(value) = self.access_param(&param, req.index, value) {
+                    values.insert(param, value);
+                }
+            }
+            rsp.values = Some(values);
+        }
+        return rsp.to_msg();
+    }
+    /// Get a parameter, or set it first if `value` is not null. Read-only parameters keep their value.
+    fn access_param(&mut self, param: &str, index: i64, value: Value) -> Option<Value> {
+        let key = (param.to_string(), index);
+        if !value.is_null() && !self.readonly.iter().any(|ro| ro == param) {
+            let current = self.params.get_mut(&key)?;
+            *current = value;
+        }
+        return self.params.get(&key).cloned();
+    }
+    fn handle_exec(&mut self, msg: Message) -> Message {
+        let req = ShellExecReq::from_msg(msg).unwrap();
+        return match (self.shell.as_ref().unwrap())(&req.cmd) {
+            Ok(ans) => Message::new_generic(
+                "org.arl.fjage.Message",
+                Performative::AGREE,
+                json!({ "ans": ans }),
+            ),
+            Err(ans) => Message::new_generic(
+                "org.arl.fjage.Message",
+                Performative::FAILURE,
+                json!({ "ans": ans }),
+            ),
+        };
+    }
+    fn handle_get_file(&mut self, msg: Message) -> Message {
+        let req = GetFileReq::from_msg(msg).unwrap();
+        let contents = match self.files.get(&req.filename) {
+            Some(contents) => contents,
+            None => {
+                return Message::new_generic(
+                    "org.arl.fjage.Message",
+                    Performative::FAILURE,
+                    json!({}),
+                )
+            }
+        };
+        let start = (req.ofs as usize).min(contents.len());
+        let end = if req.len == 0 {
+            contents.len()
+        } else {
+            (start + req.len as usize).min(contents.len())
+        };
+        let mut rsp = GetFileRsp::new(&req.filename);
+        rsp.ofs = req.ofs;
+        rsp.contents = contents[start..end].to_vec();
+        return rsp.to_msg();
+    }
+    fn handle_put_file(&mut self, mut msg: Message) -> Message {
+        msg.decode_java_classes();
+        let req = PutFileReq::from_msg(msg).unwrap();
+        match req.contents {
+            Some(contents) => {
+                let file = self.files.entry(req.filename).or_default();
+                let ofs = req.ofs as usize;
+                if file.len() < ofs + contents.len() {
+                    file.resize(ofs + contents.len(), 0);
+                }
+                file[ofs..ofs + contents.len()].copy_from_slice(&contents);
+            }
+            None => {
+                self.files.remove(&req.filename);
+            }
+        }
+        return Message::new_generic("org.arl.fjage.Message", Performative::AGREE, json!({}));
+    }
+}
+
+fn reply_to(req: &Message, sender: &str, mut rsp: Message) -> Message {
+    rsp.data.msgID = Uuid::new_v4().to_string();
+    rsp.data.inReplyTo = Some(req.data.msgID.clone());
+    rsp.data.recipient = req.data.sender.clone();
+    rsp.data.sender = AgentID::agent(sender);
+    rsp.data.sentAt = Some(now_millis());
+    return rsp;
+}
+
+fn to_line(mut frame: Frame) -> String {
+    return frame.to_json();
+}
+
+fn now_millis() -> i64 {
+    return SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as i64;
+}
+
+struct Client {
+    /// Lines to write to the client, without the trailing newline
+    sender: mpsc::UnboundedSender<String>,
+    /// What the client declared with wantsMessagesFor, or `None` if it has not said
+    wants: Option<Vec<AgentID>>,
+    /// Cuts the connection, see [MockMaster::drop_clients]
+    kill: Option<oneshot::Sender<()>>,
+}
+
+#[derive(Default)]
+struct State {
+    agents: Vec<MockAgent>,
+    clients: HashMap<usize, Client>,
+    next_client: usize,
+    /// Queries sent to clients with [MockMaster::query], by frame id
+    queries: HashMap<String, std_mpsc::Sender<ResponseFrame>>,
+    /// Leave queries from clients unanswered, like a master that has hung
+    ignore_queries: bool,
+    /// `{"alive": true}` frames received from clients
+    alive_frames: usize,
+    /// The credentials clients must present to authenticate, or `None` to accept any
+    creds: Option<String>,
+}
+
+impl State {
+    /// Deliver a message to every client that wants messages for its recipient, except `from`.
+    fn route(&mut self, msg: Message, from: Option<usize>) {
+        for (id, client) in self.clients.iter() {
+            if Some(*id) == from {
+                continue;
+            }
+            let wanted = match &client.wants {
+                Some(wants) => wants.contains(&msg.data.recipient),
+                None => true,
+            };
+            if wanted {
+                let _ = client
+                    .sender
+                    .send(to_line(Frame::Request(RequestFrame::send {
+                        message: msg.clone(),
+                        relay: false,
+                    })));
+            }
+        }
+    }
+    fn agent_ids(&self) -> Vec<AgentID> {
+        return self
+            .agents
+            .iter()
+            .map(|a| AgentID::agent(&a.name))
+            .collect();
+    }
+    fn agents_for_service(&self, service: &str) -> Vec<AgentID> {
+        return self
+            .agents
+            .iter()
+            .filter(|a| a.services.iter().any(|s| s == service))
+            .map(|a| AgentID::agent(&a.name))
+            .collect();
+    }
+}
+
+/// An in-process fjåge master container listening on a local TCP port, and on a second port for WebSocket
+/// connections at `/ws`. It speaks the JSON frame protocol to any number of gateways and hosts a set of
+/// scriptable [MockAgent]s.
+/// The master runs on its own thread, so it can serve both blocking and async clients.
+pub struct MockMaster {
+    port: u16,
+    ws_port: u16,
+    state: Arc<Mutex<State>>,
+    /// The runtime the master runs on, for serving connections that do not come through its listeners
+    handle: Handle,
+    stop: Option<oneshot::Sender<()>>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl MockMaster {
+    pub fn start(agents: Vec<MockAgent>) -> MockMaster {
+        let listener = StdTcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let ws_listener = StdTcpListener::bind("127.0.0.1:0").unwrap();
+        ws_listener.set_nonblocking(true).unwrap();
+        let ws_port = ws_listener.local_addr().unwrap().port();
+        let state = Arc::new(Mutex::new(State {
+            agents: agents,
+            ..Default::default()
+        }));
+        let (stop, stopped) = oneshot::channel();
+        let thread_state = state.clone();
+        let (handle_tx, handle_rx) = std_mpsc::channel();
+        let thread = thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_multi_thread()
+                .worker_threads(2)
+                .enable_all()
+                .build()
+                .unwrap();
+            let _ = handle_tx.send(runtime.handle().clone());
+            runtime.block_on(async move {
+                let listener = TcpListener::from_std(listener).unwrap();
+                let ws_listener = TcpListener::from_std(ws_listener).unwrap();
+                let accept = async {
+                    loop {
+                        tokio::select! {
+                            accepted = listener.accept() => {
+                                let (stream, _) = accepted.unwrap();
+                                tokio::spawn(MockMaster::serve(stream, thread_state.clone()));
+                            }
+                            accepted = ws_listener.accept() => {
+                                let (stream, _) = accepted.unwrap();
+                                tokio::spawn(MockMaster::serve_ws(stream, thread_state.clone()));
+                            }
+                        }
+                    }
+                };
+                tokio::select! {
+                    _ = accept => (),
+                    _ = stopped => (),
+                }
+            });
+            // Dropping the runtime closes every client socket
+        });
+        return MockMaster {
+            port: port,
+            ws_port: ws_port,
+            state: state,
+            handle: handle_rx.recv().unwrap(),
+            stop: Some(stop),
+            thread: Some(thread),
+        };
+    }
+    /// A master hosting `gwtestalpha` and `gwtestbeta` like the Docker test container, plus a `shell` agent.
+    pub fn start_default() -> MockMaster {
+        return MockMaster::start(vec![
+            MockAgent::gateway_test_agent("gwtestalpha"),
+            MockAgent::gateway_test_agent("gwtestbeta"),
+            MockAgent::shell("shell"),
+        ]);
+    }
+    pub fn port(&self) -> u16 {
+        return self.port;
+    }
+    /// Serve a gateway at the other end of `stream`, such as the master's end of a [DuplexConnector](fjage_rs::protocol::connector::DuplexConnector) pipe.
+    pub fn serve_stream<S: AsyncRead + AsyncWrite + Send + 'static>(&self, stream: S) {
+        self.handle
+            .spawn(MockMaster::serve(stream, self.state.clone()));
+    }
+    /// Also accept gateways on a Unix domain socket at `path`, which must not exist yet.
+    #[cfg(unix)]
+    pub fn listen_unix<P: AsRef<std::path::Path>>(&self, path: P) {
+        let listener = std::os::unix::net::UnixListener::bind(path).unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let state = self.state.clone();
+        self.handle.spawn(async move {
+            let listener = tokio::net::UnixListener::from_std(listener).unwrap();
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(MockMaster::serve(stream, state.clone()));
+            }
+        });
+    }
+    /// The port serving the WebSocket connector, at `ws://127.0.0.1:<ws_port>/ws`
+    pub fn ws_port(&self) -> u16 {
+        return self.ws_port;
+    }
+    /// Send a message to the connected gateways, as if it came from an agent in the master.
+    pub fn send(&self, mut msg: Message) {
+        if msg.data.sender.is_empty() {
+            msg.data.sender = AgentID::agent("mock");
+        }
+        if msg.data.sentAt.is_none() {
+            msg.data.sentAt = Some(now_millis());
+        }
+        self.wait_for_client();
+        self.state.lock().unwrap().route(msg, None);
+    }
+    /// Messages received so far by the named agent.
+    pub fn inbox(&self, agent: &str) -> Vec<Message> {
+        let state = self.state.lock().unwrap();
+        return state
+            .agents
+            .iter()
+            .find(|a| a.name == agent)
+            .map(|a| a.inbox.clone())
+            .unwrap_or_default();
+    }
+    /// Contents of a file held by the named shell agent.
+    pub fn file(&self, agent: &str, filename: &str) -> Option<Vec<u8>> {
+        let state = self.state.lock().unwrap();
+        let agent = state.agents.iter().find(|a| a.name == agent)?;
+        return agent.files.get(filename).cloned();
+    }
+    pub fn client_count(&self) -> usize {
+        return self.state.lock().unwrap().clients.len();
+    }
+    /// Wait up to a second for the number of connected gateways to reach `count`. Returns whether it did.
+    pub fn wait_for_client_count(&self, count: usize) -> bool {
+        for _ in 0..100 {
+            if self.client_count() == count {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        return false;
+    }
+    /// A gateway that has only just connected may not have been accepted yet. Give it up to a second.
+    fn wait_for_client(&self) {
+        for _ in 0..100 {
+            if self.client_count() > 0 {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+    /// Send a request frame to the first connected gateway and wait up to a second for its answer.
+    pub fn query(&self, req: RequestFrame) -> Option<ResponseFrame> {
+        self.wait_for_client();
+        let (tx, rx) = std_mpsc::channel();
+        {
+            let mut state = self.state.lock().unwrap();
+            let id = req.id()?.clone();
+            let client = state.clients.keys().min().copied()?;
+            state.queries.insert(id, tx);
+            let _ = state.clients[&client]
+                .sender
+                .send(to_line(Frame::Request(req)));
+        }
+        return rx.recv_timeout(Duration::from_secs(1)).ok();
+    }
+    /// Stop answering queries such as agents or agentForService. Messages are still delivered.
+    pub fn ignore_queries(&self) {
+        self.state.lock().unwrap().ignore_queries = true;
+    }
+    /// Accept only `creds` from gateways that authenticate
+    pub fn require_creds(&self, creds: &str) {
+        self.state.lock().unwrap().creds = Some(creds.to_string());
+    }
+    /// Cut the connection to every gateway without a word, as a network fault would. The gateways may reconnect.
+    pub fn drop_clients(&self) {
+        self.wait_for_client();
+        let mut state = self.state.lock().unwrap();
+        for client in state.clients.values_mut() {
+            if let Some(kill) = client.kill.take() {
+                let _ = kill.send(());
+            }
+        }
+    }
+    /// What each connected gateway declared with wantsMessagesFor, or `None` for those that have not said.
+    pub fn client_wants(&self) -> Vec<Option<Vec<AgentID>>> {
+        let state = self.state.lock().unwrap();
+        return state.clients.values().map(|c| c.wants.clone()).collect();
+    }
+    /// How many `{"alive": true}` frames the gateways have sent, one for every connection they open.
+    pub fn alive_frames(&self) -> usize {
+        return self.state.lock().unwrap().alive_frames;
+    }
+    /// Write `line` to every connected gateway as it is, whether or not it is a valid frame.
+    pub fn send_raw(&self, line: &str) {
+        self.wait_for_client();
+        let state = self.state.lock().unwrap();
+        for client in state.clients.values() {
+            let _ = client.sender.send(line.to_string());
+        }
+    }
+    /// Ask every connected gateway to shut down, as a master does when it stops.
+    pub fn shutdown_clients(&self) {
+        self.wait_for_client();
+        let state = self.state.lock().unwrap();
+        for client in state.clients.values() {
+            let _ = client
+                .sender
+                .send(to_line(Frame::Request(RequestFrame::shutdown)));
+        }
+    }
+
+    /// Complete the WebSocket handshake, then serve the frames carried in text messages as if they were lines on a TCP stream
+    async fn serve_ws(stream: TcpStream, state: Arc<Mutex<State>>) {
+        let Ok(ws) = tokio_tungstenite::accept_async(stream).await else {
+            return;
+        };
+        let (mut ws_sink, mut ws_stream) = ws.split();
+        let (lines, bridge) = tokio::io::duplex(64 * 1024);
+        let (bridge_in, mut bridge_out) = tokio::io::split(bridge);
+        let to_client = async {
+            let mut bridge_in = BufReader::new(bridge_in).lines();
+            while let Ok(Some(line)) = bridge_in.next_line().await {
+                let msg = tungstenite::Message::Text(line + "\n");
+                if ws_sink.send(msg).await.is_err() {
+                    return;
+                }
+            }
+        };
+        let from_client = async {
+            while let Some(Ok(msg)) = ws_stream.next().await {
+                let tungstenite::Message::Text(text) = msg else {
+                    continue;
+                };
+                if bridge_out.write_all(text.as_bytes()).await.is_err() {
+                    return;
+                }
+                if !text.ends_with('\n') && bridge_out.write_all(b"\n").await.is_err() {
+                    return;
+                }
+            }
+        };
+        tokio::select! {
+            _ = MockMaster::serve(lines, state) => (),
+            _ = to_client => (),
+            _ = from_client => (),
+        }
+    }
+
+    async fn serve<S: AsyncRead + AsyncWrite + Send + 'static>(
+        stream: S,
+        state: Arc<Mutex<State>>,
+    ) {
+        let (rstream, mut wstream) = tokio::io::split(stream);
+        let (sender, mut receiver) = mpsc::unbounded_channel::<String>();
+        let (kill, killed) = oneshot::channel::<()>();
+        let id = {
+            let mut state = state.lock().unwrap();
+            let id = state.next_client;
+            state.next_client += 1;
+            state.clients.insert(
+                id,
+                Client {
+                    sender: sender.clone(),
+                    wants: None,
+                    kill: Some(kill),
+                },
+            );
+            id
+        };
+        let writer = async {
+            while let Some(line) = receiver.recv().await {
+                let line = line + "\n";
+                if wstream.write_all(line.as_bytes()).await.is_err() {
+                    return;
+                }
+            }
+        };
+        let reader = async {
+            let mut reader = BufReader::new(rstream);
+            let mut line = String::new();
+            loop {
+                line.clear();
+                match reader.read_line(&mut line).await {
+                    Ok(0) | Err(_) => return,
+                    Ok(_) => (),
+                }
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let frame = match Frame::from_json(&line) {
+                    Ok(frame) => frame,
+                    Err(_) => continue,
+                };
+                let mut state = state.lock().unwrap();
+                if let Some(rsp) = MockMaster::process(&mut state, id, frame) {
+                    let _ = sender.send(to_line(Frame::Response(rsp)));
+                }
+            }
+        };
+        tokio::select! {
+            _ = writer => (),
+            _ = reader => (),
+            _ = killed => (),
+        }
+        state.lock().unwrap().clients.remove(&id);
+    }
+
+    fn process(state: &mut State, client: usize, frame: Frame) -> Option<ResponseFrame> {
+        let req = match frame {
+            Frame::Request(req) => req,
+            Frame::Response(rsp) => {
+                if let Some(query) = state.queries.remove(rsp.id()) {
+                    let _ = query.send(rsp);
+                }
+                return None;
+            }
+            Frame::Alive(true) => {
+                state.alive_frames += 1;
+                return None;
+            }
+            _ => return None,
+        };
+        if state.ignore_queries && req.id().is_some() {
+            return None;
+        }
+        return match req {
+            RequestFrame::agents { id } => Some(ResponseFrame::agents {
+                id: id,
+                agentIDs: state.agent_ids(),
+                agentTypes: None,
+            }),
+            RequestFrame::containsAgent { id, agentID } => Some(ResponseFrame::containsAgent {
+                id: id,
+                answer: state.agent_ids().contains(&agentID),
+            }),
+            RequestFrame::services { id } => {
+                let mut services: Vec<String> = Vec::new();
+                for agent in state.agents.iter() {
+                    for service in agent.services.iter() {
+                        if !services.contains(service) {
+                            services.push(service.clone());
+                        }
+                    }
+                }
+                Some(ResponseFrame::services {
+                    id: id,
+                    services: services,
+                })
+            }
+            RequestFrame::agentForService { id, service } => Some(ResponseFrame::agentForService {
+                id: id,
+                agentID: state.agents_for_service(&service).first().cloned(),
+            }),
+            RequestFrame::agentsForService { id, service } => {
+                Some(ResponseFrame::agentsForService {
+                    id: id,
+                    agentIDs: state.agents_for_service(&service),
+                })
+            }
+            RequestFrame::send { message, relay } => {
+                let recipient = message.data.recipient.clone();
+                let agent = state
+                    .agents
+                    .iter_mut()
+                    .find(|a| !recipient.is_topic() && a.name == recipient.name());
+                match agent {
+                    Some(agent) => {
+                        if let Some(rsp) = agent.handle(message) {
+                            state.route(rsp, None);
+                        }
+                    }
+                    // Only relayed messages go on to the other clients
+                    None if relay => state.route(message, Some(client)),
+                    None => (),
+                }
+                None
+            }
+            RequestFrame::wantsMessagesFor { agentIDs } => {
+                if let Some(client) = state.clients.get_mut(&client) {
+                    client.wants = Some(agentIDs);
+                }
+                None
+            }
+            RequestFrame::auth { id, creds } => Some(ResponseFrame::auth {
+                id: id,
+                auth: state
+                    .creds
+                    .as_ref()
+                    .is_none_or(|expected| *expected == creds),
+            }),
+            RequestFrame::shutdown => None,
+        };
+    }
+}
+
+impl Drop for MockMaster {
+    fn drop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
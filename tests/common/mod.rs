@@ -0,0 +1,6 @@
+// Each integration test crate uses a different part of the mock
+#![allow(dead_code, unused_imports)]
+
+pub mod mock_master;
+
+pub use mock_master::{MockAgent, MockMaster};
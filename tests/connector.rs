@@ -0,0 +1,35 @@
+use fjage_rs::protocol::connector::{DataBits, Parity, SerialPortConnector, StopBits};
+
+#[test]
+fn parses_rs232_settings() {
+    assert_eq!(
+        SerialPortConnector::parse_settings("N81").unwrap(),
+        (Parity::None, DataBits::Eight, StopBits::One)
+    );
+    assert_eq!(
+        SerialPortConnector::parse_settings("E72").unwrap(),
+        (Parity::Even, DataBits::Seven, StopBits::Two)
+    );
+    assert_eq!(
+        SerialPortConnector::parse_settings("O82").unwrap(),
+        (Parity::Odd, DataBits::Eight, StopBits::Two)
+    );
+    // Lower case and surrounding spaces are accepted, and nothing at all means the default
+    assert_eq!(
+        SerialPortConnector::parse_settings(" e51 ").unwrap(),
+        (Parity::Even, DataBits::Five, StopBits::One)
+    );
+    assert_eq!(
+        SerialPortConnector::parse_settings("").unwrap(),
+        (Parity::None, DataBits::Eight, StopBits::One)
+    );
+}
+
+#[test]
+fn rejects_bad_rs232_settings() {
+    for settings in ["X81", "N91", "N43", "N8", "N812", "81N", "garbage"] {
+        let err = SerialPortConnector::parse_settings(settings).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput, "{}", settings);
+    }
+    assert!(SerialPortConnector::with_settings("/dev/null", 9600, "N91").is_err());
+}
@@ -0,0 +1,622 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{mock_master::SHELL_SERVICE, MockAgent, MockMaster};
+use fjage_rs::{
+    core::{
+        aid::AgentID,
+        error::GatewayError,
+        filter::clazz,
+        message::{Message, Performative},
+    },
+    protocol::{
+        connector::{DuplexConnector, TcpConnector, WebSocketConnector, LINK_CAPACITY},
+        frame::{Frame, FrameError, RequestFrame},
+    },
+    remote::{
+        cancel::CancelToken,
+        container::{LinkState, ReconnectPolicy, RemoteContainer, OUTBOX_CAPACITY},
+        queue::{OverflowPolicy, QueueLimits, QueueStats},
+    },
+};
+use futures_util::{Stream, StreamExt};
+use serde_json::{json, Value};
+use tokio::io::AsyncWriteExt;
+
+async fn next<S: Stream<Item = Message> + Unpin>(stream: &mut S) -> Message {
+    return tokio::time::timeout(Duration::from_secs(1), stream.next())
+        .await
+        .unwrap()
+        .unwrap();
+}
+
+/// Poll `cond` for up to two seconds
+async fn eventually<F: Fn() -> bool>(cond: F) -> bool {
+    for _ in 0..100 {
+        if cond() {
+            return true;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    return false;
+}
+
+#[tokio::test]
+async fn each_stream_gets_its_own_copy() {
+    let master = MockMaster::start_default();
+    let mut container = RemoteContainer::new_tcp("127.0.0.1", master.port())
+        .await
+        .unwrap();
+    let mut datagrams = Box::pin(container.messages(clazz("org.arl.unet.DatagramNtf")));
+    let mut also_datagrams = Box::pin(container.messages(clazz("org.arl.unet.DatagramNtf")));
+    let mut frames = Box::pin(container.messages(clazz("org.arl.unet.phy.RxFrameNtf")));
+    let news = AgentID::topic("news");
+    let mut topic = Box::pin(container.topic_messages(&news).unwrap());
+    // Let the master see that we want the topic
+    assert!(
+        eventually(|| master
+            .client_wants()
+            .iter()
+            .any(|wants| wants.as_ref().is_some_and(|wants| wants.contains(&news))))
+        .await
+    );
+
+    for clazz in ["org.arl.unet.DatagramNtf", "org.arl.unet.phy.RxFrameNtf"] {
+        let mut ntf = Message::new_generic(clazz, Performative::INFORM, json!({}));
+        ntf.data.recipient = container.get_agent_id();
+        master.send(ntf);
+    }
+    let mut ntf = Message::new_generic("News", Performative::INFORM, json!({}));
+    ntf.data.recipient = news.clone();
+    master.send(ntf);
+
+    assert_eq!(next(&mut datagrams).await.clazz, "org.arl.unet.DatagramNtf");
+    assert_eq!(
+        next(&mut also_datagrams).await.clazz,
+        "org.arl.unet.DatagramNtf"
+    );
+    assert_eq!(next(&mut frames).await.clazz, "org.arl.unet.phy.RxFrameNtf");
+    assert_eq!(next(&mut topic).await.clazz, "News");
+    // The streams did not take the messages away from recv
+    let msg = container.recv(None, None).await.unwrap();
+    assert_eq!(msg.clazz, "org.arl.unet.DatagramNtf");
+}
+
+#[tokio::test]
+async fn calls_give_up_on_timeout_or_cancellation() {
+    let master = MockMaster::start(vec![MockAgent::new("silent")]);
+    let mut container = RemoteContainer::new_tcp("127.0.0.1", master.port())
+        .await
+        .unwrap();
+    let silent = AgentID::agent("silent");
+    // The mock answers only REQUESTs
+    let req = || Message::new_generic("org.arl.fjage.Message", Performative::QUERY_IF, json!({}));
+
+    let rsp = container
+        .request_timeout(&silent, req(), Duration::from_millis(100))
+        .await;
+    assert!(matches!(rsp, Err(GatewayError::Timeout)));
+
+    let token = CancelToken::new();
+    let mut requester = container.clone();
+    let waiting = token.clone();
+    let to = silent.clone();
+    let pending = tokio::spawn(async move { waiting.run(requester.request(&to, req())).await });
+    // Cancel once the request is waiting on the master
+    assert!(eventually(|| master.inbox("silent").len() == 2).await);
+    token.cancel();
+    assert!(matches!(
+        pending.await.unwrap(),
+        Err(GatewayError::Interrupted)
+    ));
+    // A cancelled token aborts later calls straight away, other calls are unaffected
+    assert!(matches!(
+        token.run(container.agents()).await,
+        Err(GatewayError::Interrupted)
+    ));
+    assert_eq!(container.agents().await.unwrap(), vec![silent.clone()]);
+
+    master.ignore_queries();
+    let rsp = container
+        .agent_for_service_timeout("org.arl.unet.Services.LINK", Duration::from_millis(100))
+        .await;
+    assert!(matches!(rsp, Err(GatewayError::Timeout)));
+}
+
+#[tokio::test]
+async fn concurrent_requests_each_get_their_own_reply() {
+    let master = MockMaster::start(vec![MockAgent::new("echo").on_message(|req| {
+        Some(Message::new_generic(
+            "org.arl.fjage.Message",
+            Performative::INFORM,
+            json!({ "n": req.data.fields["n"] }),
+        ))
+    })]);
+    let mut container = RemoteContainer::new_tcp("127.0.0.1", master.port())
+        .await
+        .unwrap();
+    // A reception waiting in another task neither blocks the requests nor takes their replies
+    let mut receiver = container.clone();
+    let ntf = tokio::spawn(async move {
+        receiver
+            .recv_timeout(Some(vec!["News".to_string()]), None, Duration::from_secs(2))
+            .await
+    });
+
+    let requests: Vec<_> = (0..20)
+        .map(|n| {
+            let mut container = container.clone();
+            tokio::spawn(async move {
+                let req = Message::new_generic(
+                    "org.arl.fjage.Message",
+                    Performative::REQUEST,
+                    json!({ "n": n }),
+                );
+                let rsp = container
+                    .request_timeout(&AgentID::agent("echo"), req, Duration::from_secs(2))
+                    .await
+                    .unwrap();
+                assert_eq!(rsp.get_i32("n", -1), n);
+            })
+        })
+        .collect();
+    for request in requests {
+        request.await.unwrap();
+    }
+
+    let mut news = Message::new_generic("News", Performative::INFORM, json!({}));
+    news.data.recipient = container.get_agent_id();
+    master.send(news);
+    assert_eq!(ntf.await.unwrap().unwrap().clazz, "News");
+    // Replies were handed over directly, none were left behind in the queue
+    assert!(matches!(
+        container
+            .recv_timeout(None, None, Duration::from_millis(100))
+            .await,
+        Err(GatewayError::Timeout)
+    ));
+
+    // An interrupt only aborts receptions already in progress
+    container.interrupt();
+    assert!(matches!(
+        container
+            .recv_timeout(None, None, Duration::from_millis(100))
+            .await,
+        Err(GatewayError::Timeout)
+    ));
+    let mut receiver = container.clone();
+    let waiting = tokio::spawn(async move { receiver.recv(None, None).await });
+    // The reception may not be waiting yet, so keep interrupting until it gives up
+    while !waiting.is_finished() {
+        container.interrupt();
+        tokio::task::yield_now().await;
+    }
+    assert!(matches!(
+        waiting.await.unwrap(),
+        Err(GatewayError::Interrupted)
+    ));
+}
+
+#[tokio::test]
+async fn full_queues_and_old_messages_are_dropped() {
+    let master = MockMaster::start_default();
+    let mut container = RemoteContainer::new_tcp("127.0.0.1", master.port())
+        .await
+        .unwrap();
+    let me = container.get_agent_id();
+    let send = |n: i32| {
+        let mut ntf = Message::new_generic("TestNtf", Performative::INFORM, json!({ "n": n }));
+        ntf.data.recipient = me.clone();
+        master.send(ntf);
+    };
+    let stats = |queued, dropped_full, dropped_expired| QueueStats {
+        queued: queued,
+        dropped_full: dropped_full,
+        dropped_expired: dropped_expired,
+    };
+
+    container.set_queue_limits(QueueLimits {
+        capacity: 2,
+        overflow: OverflowPolicy::DROP_OLDEST,
+        max_age: None,
+    });
+    (1..=3).for_each(send);
+    assert!(eventually(|| container.queue_stats() == stats(2, 1, 0)).await);
+    let first = container.recv(None, None).await.unwrap();
+    assert_eq!(first.get_i32("n", 0), 2);
+    container.recv(None, None).await.unwrap();
+
+    container.set_queue_limits(QueueLimits {
+        capacity: 1,
+        overflow: OverflowPolicy::DROP_NEWEST,
+        max_age: Some(Duration::from_millis(300)),
+    });
+    (4..=5).for_each(send);
+    // The survivor is the message that came first, until it grows too old
+    assert!(eventually(|| container.queue_stats() == stats(1, 2, 0)).await);
+    assert!(eventually(|| container.queue_stats() == stats(0, 2, 1)).await);
+    assert!(matches!(
+        container
+            .recv_timeout(None, None, Duration::from_millis(50))
+            .await,
+        Err(GatewayError::Timeout)
+    ));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn full_streams_drop_messages_too() {
+    let master = MockMaster::start_default();
+    let container = RemoteContainer::new_tcp("127.0.0.1", master.port())
+        .await
+        .unwrap();
+    container.set_stream_limits(QueueLimits {
+        capacity: 2,
+        overflow: OverflowPolicy::DROP_OLDEST,
+        max_age: None,
+    });
+    let mut stream = Box::pin(container.messages(clazz("TestNtf")));
+    for n in 1..=3 {
+        let mut ntf = Message::new_generic("TestNtf", Performative::INFORM, json!({ "n": n }));
+        ntf.data.recipient = container.get_agent_id();
+        master.send(ntf);
+    }
+    assert!(eventually(|| container.stream_stats().dropped_full == 1).await);
+    assert_eq!(container.stream_stats().queued, 2);
+    assert_eq!(next(&mut stream).await.get_i32("n", 0), 2);
+    assert_eq!(next(&mut stream).await.get_i32("n", 0), 3);
+    // The stream's limits do not touch the queue behind recv
+    assert!(eventually(|| container.queue_stats().queued == 3).await);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn a_stalled_link_holds_back_senders() {
+    // Nobody reads the master's end of the pipe, so everything we send piles up on our side
+    let (connector, mut theirs) = DuplexConnector::pair(64);
+    let mut container = RemoteContainer::new_with_policy(connector, ReconnectPolicy::never())
+        .await
+        .unwrap();
+    let to = AgentID::agent("nobody");
+    let ntf = || Message::new_generic("TestNtf", Performative::INFORM, json!({}));
+    let mut sent = 0;
+    while tokio::time::timeout(Duration::from_millis(200), container.send_wait(&to, ntf()))
+        .await
+        .is_ok()
+    {
+        sent += 1;
+        assert!(
+            sent <= OUTBOX_CAPACITY + LINK_CAPACITY + 64,
+            "nothing held back"
+        );
+    }
+    assert!(sent >= OUTBOX_CAPACITY);
+    assert!(matches!(
+        container.send(&to, ntf()),
+        Err(GatewayError::WouldBlock)
+    ));
+
+    // A request waiting for room can still be interrupted
+    let mut requester = container.clone();
+    let pending = tokio::spawn(async move { requester.request(&to, ntf()).await });
+    // The request may not be waiting yet, so keep interrupting until it gives up
+    let result = tokio::time::timeout(Duration::from_secs(1), async {
+        while !pending.is_finished() {
+            container.interrupt();
+            tokio::task::yield_now().await;
+        }
+        return pending.await.unwrap();
+    })
+    .await
+    .unwrap();
+    assert!(matches!(result, Err(GatewayError::Interrupted)));
+
+    // Frames from the master are still read, and its requests answered as soon as the link has room
+    let query = Frame::Request(RequestFrame::agents {
+        id: "1".to_string(),
+    });
+    let mut ntf = Message::new_generic("News", Performative::INFORM, json!({}));
+    ntf.data.recipient = container.get_agent_id();
+    let send = Frame::Request(RequestFrame::send {
+        message: ntf,
+        relay: false,
+    });
+    for mut frame in [query, send] {
+        theirs
+            .write_all(format!("{}\n", frame.to_json()).as_bytes())
+            .await
+            .unwrap();
+    }
+    let msg = container
+        .recv_timeout(None, None, Duration::from_secs(1))
+        .await
+        .unwrap();
+    assert_eq!(msg.clazz, "News");
+
+    // Closing gives up on what the link cannot take instead of waiting for it forever
+    tokio::time::timeout(Duration::from_secs(3), container.close())
+        .await
+        .unwrap();
+    assert_eq!(container.link_state(), LinkState::CLOSED);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn subscriptions_reach_the_master_before_later_sends() {
+    // Replies are addressed to the sender of the request, which here is a topic
+    let master = MockMaster::start(vec![MockAgent::new("echo").on_message(|_| {
+        Some(Message::new_generic(
+            "org.arl.fjage.Message",
+            Performative::INFORM,
+            json!({}),
+        ))
+    })]);
+    let mut container = RemoteContainer::new_tcp("127.0.0.1", master.port())
+        .await
+        .unwrap();
+    for n in 0..50 {
+        // The master only passes the reply on if it already knows we want the topic
+        let topic = AgentID::topic(&format!("news{}", n));
+        container.subscribe(&topic).await.unwrap();
+        let mut req =
+            Message::new_generic("org.arl.fjage.Message", Performative::REQUEST, json!({}));
+        req.data.sender = topic.clone();
+        container.send(&AgentID::agent("echo"), req).unwrap();
+        let msg = container
+            .recv_timeout(None, None, Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(msg.data.recipient, topic);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn reconnects_and_restores_subscriptions_after_the_link_drops() {
+    let master = MockMaster::start(vec![MockAgent::new("silent")]);
+    let policy = ReconnectPolicy {
+        initial_delay: Duration::from_millis(300),
+        max_attempts: Some(10),
+        ..ReconnectPolicy::default()
+    };
+    let mut container =
+        RemoteContainer::new_with_policy(TcpConnector::new("127.0.0.1", master.port()), policy)
+            .await
+            .unwrap();
+    let news = AgentID::topic("news");
+    container.subscribe(&news).await.unwrap();
+    assert!(
+        eventually(
+            || master.client_wants() == vec![Some(vec![container.get_agent_id(), news.clone()])]
+        )
+        .await
+    );
+    assert_eq!(master.alive_frames(), 1);
+
+    // The mock answers only REQUESTs, so this one waits until the link drops
+    let mut requester = container.clone();
+    let pending = tokio::spawn(async move {
+        let req = Message::new_generic("org.arl.fjage.Message", Performative::QUERY_IF, json!({}));
+        requester.request(&AgentID::agent("silent"), req).await
+    });
+    assert!(eventually(|| master.inbox("silent").len() == 1).await);
+    master.drop_clients();
+
+    assert!(matches!(
+        pending.await.unwrap(),
+        Err(GatewayError::ConnectionLost)
+    ));
+    assert!(eventually(|| container.link_state() == LinkState::RECONNECTING).await);
+    assert!(eventually(|| container.link_state() == LinkState::CONNECTED).await);
+    // The new connection is announced and asks for the same messages as before
+    assert!(
+        eventually(
+            || master.client_wants() == vec![Some(vec![container.get_agent_id(), news.clone()])]
+        )
+        .await
+    );
+    assert_eq!(master.alive_frames(), 2);
+    assert_eq!(container.agents().await.unwrap(), vec!["silent"]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn bad_and_unknown_frames_do_not_break_the_link() {
+    let garbage = "this is not json";
+    let unknown = r#"{"action":"fromTheFuture","id":"1234","payload":[1,2,3]}"#;
+    assert!(matches!(
+        Frame::from_json(garbage),
+        Err(FrameError::Json(_))
+    ));
+    match Frame::from_json(unknown) {
+        Ok(Frame::Unknown(raw)) => assert_eq!(raw, serde_json::from_str::<Value>(unknown).unwrap()),
+        other => panic!("expected an unknown frame, got {:?}", other),
+    }
+
+    let master = MockMaster::start_default();
+    let mut container = RemoteContainer::new_tcp("127.0.0.1", master.port())
+        .await
+        .unwrap();
+    let me = container.get_agent_id();
+    master.send_raw(garbage);
+    master.send_raw(unknown);
+    let mut ntf = Message::new_generic("News", Performative::INFORM, json!({}));
+    ntf.data.recipient = me.clone();
+    master.send(ntf);
+    let msg = container
+        .recv_timeout(None, None, Duration::from_secs(1))
+        .await
+        .unwrap();
+    assert_eq!(msg.clazz, "News");
+    assert_eq!(msg.data.recipient, me);
+    assert_eq!(container.link_state(), LinkState::CONNECTED);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn authenticates_with_the_master_but_not_the_other_way_round() {
+    let master = MockMaster::start_default();
+    master.require_creds("secret");
+    let mut container = RemoteContainer::new_tcp("127.0.0.1", master.port())
+        .await
+        .unwrap();
+    assert!(container.authenticate("secret").await.unwrap());
+    assert!(!container.authenticate("guess").await.unwrap());
+
+    let rsp = master
+        .query(RequestFrame::auth {
+            id: "auth-1".to_string(),
+            creds: "secret".to_string(),
+        })
+        .unwrap();
+    assert_eq!(rsp.id(), "auth-1");
+    assert_eq!(rsp.get_auth(), Some(false));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn unrelayed_messages_stay_with_the_master() {
+    let master = MockMaster::start(vec![]);
+    let mut sender = RemoteContainer::new_tcp("127.0.0.1", master.port())
+        .await
+        .unwrap();
+    let mut receiver = RemoteContainer::new_tcp("127.0.0.1", master.port())
+        .await
+        .unwrap();
+    let news = AgentID::topic("news");
+    receiver.subscribe(&news).await.unwrap();
+    assert!(
+        eventually(|| master
+            .client_wants()
+            .iter()
+            .any(|wants| wants.as_ref().is_some_and(|wants| wants.contains(&news))))
+        .await
+    );
+
+    let local = Message::new_generic("Local", Performative::INFORM, json!({}));
+    sender.send_relay(&news, local, false).unwrap();
+    let relayed = Message::new_generic("Relayed", Performative::INFORM, json!({}));
+    sender.send_relay(&news, relayed, true).unwrap();
+
+    let msg = receiver
+        .recv_timeout(None, None, Duration::from_secs(1))
+        .await
+        .unwrap();
+    assert_eq!(msg.clazz, "Relayed");
+    assert_eq!(receiver.queue_stats().queued, 0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn does_not_reconnect_after_the_master_shuts_down() {
+    let master = MockMaster::start_default();
+    let policy = ReconnectPolicy {
+        initial_delay: Duration::from_millis(50),
+        ..ReconnectPolicy::default()
+    };
+    let container =
+        RemoteContainer::new_with_policy(TcpConnector::new("127.0.0.1", master.port()), policy)
+            .await
+            .unwrap();
+    master.shutdown_clients();
+    // A link that is CLOSED stays that way, so there is no reconnect to wait for
+    assert!(eventually(|| container.link_state() == LinkState::CLOSED).await);
+    assert!(eventually(|| master.client_count() == 0).await);
+    assert_eq!(master.alive_frames(), 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn talks_to_the_master_over_websocket() {
+    let master = MockMaster::start_default();
+    let mut container =
+        RemoteContainer::new(WebSocketConnector::new("127.0.0.1", master.ws_port()))
+            .await
+            .unwrap();
+    assert_eq!(
+        container.agents().await.unwrap(),
+        vec!["gwtestalpha", "gwtestbeta", "shell"]
+    );
+
+    let mut ntf = Message::new_generic("News", Performative::INFORM, json!({}));
+    ntf.data.recipient = container.get_agent_id();
+    master.send(ntf);
+    let msg = container
+        .recv_timeout(None, None, Duration::from_secs(1))
+        .await
+        .unwrap();
+    assert_eq!(msg.clazz, "News");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn talks_to_the_master_over_an_in_memory_pipe() {
+    let master = MockMaster::start_default();
+    let (connector, theirs) = DuplexConnector::pair(64 * 1024);
+    master.serve_stream(theirs);
+    let mut container = RemoteContainer::new_with_policy(connector, ReconnectPolicy::never())
+        .await
+        .unwrap();
+    assert_eq!(
+        container.agent_for_service(SHELL_SERVICE).await.unwrap(),
+        AgentID::agent("shell")
+    );
+    let req = Message::new_generic("org.arl.fjage.Message", Performative::QUERY_IF, json!({}));
+    container.send(&AgentID::agent("gwtestalpha"), req).unwrap();
+    assert!(eventually(|| master.inbox("gwtestalpha").len() == 1).await);
+
+    // A pipe cannot be reopened, so losing it is final
+    drop(master);
+    assert!(eventually(|| container.link_state() == LinkState::DISCONNECTED).await);
+}
+
+#[cfg(unix)]
+#[tokio::test(flavor = "multi_thread")]
+async fn talks_to_the_master_over_a_unix_socket() {
+    let master = MockMaster::start_default();
+    let path = std::env::temp_dir().join(format!("fjage-rs-{}.sock", uuid::Uuid::new_v4()));
+    master.listen_unix(&path);
+    let mut container = RemoteContainer::new_unix(&path).await.unwrap();
+    assert_eq!(
+        container.agents().await.unwrap(),
+        vec!["gwtestalpha", "gwtestbeta", "shell"]
+    );
+    container.close().await;
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn topic_messages_reach_local_and_remote_subscribers() {
+    let master = MockMaster::start(vec![]);
+    let mut publisher = RemoteContainer::new_tcp("127.0.0.1", master.port())
+        .await
+        .unwrap();
+    let mut remote = RemoteContainer::new_tcp("127.0.0.1", master.port())
+        .await
+        .unwrap();
+    let news = AgentID::topic("news");
+    let alerts = AgentID::topic("alerts");
+    publisher.subscribe(&news).await.unwrap();
+    let mut local_alerts = Box::pin(publisher.topic_messages(&alerts).unwrap());
+    remote.subscribe(&news).await.unwrap();
+    assert!(
+        eventually(|| master
+            .client_wants()
+            .iter()
+            .filter(|wants| wants.as_ref().is_some_and(|wants| wants.contains(&news)))
+            .count()
+            == 2)
+        .await
+    );
+
+    let ntf = Message::new_generic("News", Performative::INFORM, json!({}));
+    publisher.send(&news, ntf).unwrap();
+    let ntf = Message::new_generic("Alert", Performative::INFORM, json!({}));
+    publisher.send(&alerts, ntf).unwrap();
+
+    let msg = publisher
+        .recv_timeout(None, None, Duration::from_secs(1))
+        .await
+        .unwrap();
+    assert_eq!((msg.clazz.as_str(), &msg.data.recipient), ("News", &news));
+    assert_eq!(next(&mut local_alerts).await.clazz, "Alert");
+    let msg = remote
+        .recv_timeout(None, None, Duration::from_secs(1))
+        .await
+        .unwrap();
+    assert_eq!(msg.clazz, "News");
+    // Each subscriber gets exactly one copy
+    assert_eq!(publisher.queue_stats().queued, 0);
+    assert_eq!(remote.queue_stats().queued, 0);
+}
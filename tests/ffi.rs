@@ -0,0 +1,173 @@
+mod common;
+
+use std::ffi::{c_char, CStr, CString};
+
+use common::{mock_master::SHELL_SERVICE, MockMaster};
+use fjage_rs::ffi::{
+    message::{
+        fjage_msg_add_bool, fjage_msg_add_string, fjage_msg_create, fjage_msg_destroy,
+        fjage_msg_get_performative, fjage_msg_get_string, fjage_msg_set_recipient,
+    },
+    param::{fjage_param_get_int, fjage_param_get_string, fjage_param_set_int},
+    util::{c_api_serial_connector, fjage_msg_t},
+    *,
+};
+
+fn cstr(s: &str) -> CString {
+    return CString::new(s).unwrap();
+}
+
+#[test]
+fn opens_finds_agents_and_closes() {
+    let master = MockMaster::start_default();
+    let host = cstr("127.0.0.1");
+    unsafe {
+        let gw = fjage_tcp_open(host.as_ptr(), master.port() as i32);
+        assert!(!gw.is_null());
+
+        let service = cstr(SHELL_SERVICE);
+        let aid = fjage_agent_for_service(gw, service.as_ptr());
+        assert!(!aid.is_null());
+        assert_eq!(CStr::from_ptr(aid).to_str().unwrap(), "shell");
+        fjage_aid_destroy(aid as *mut c_char);
+
+        let missing = cstr("org.arl.fjage.test.Services.NONE");
+        assert!(fjage_agent_for_service(gw, missing.as_ptr()).is_null());
+
+        assert_eq!(fjage_close(gw), 0);
+    }
+}
+
+#[test]
+fn opens_and_closes_repeatedly() {
+    let master = MockMaster::start_default();
+    let host = cstr("127.0.0.1");
+    for _ in 0..5 {
+        unsafe {
+            let gw = fjage_tcp_open(host.as_ptr(), master.port() as i32);
+            assert!(!gw.is_null());
+            assert_eq!(fjage_close(gw), 0);
+        }
+        assert!(master.wait_for_client_count(0));
+    }
+}
+
+#[test]
+fn opens_over_websocket() {
+    let master = MockMaster::start_default();
+    let host = cstr("127.0.0.1");
+    unsafe {
+        let gw = fjage_ws_open(host.as_ptr(), master.ws_port() as i32);
+        assert!(!gw.is_null());
+        let service = cstr(SHELL_SERVICE);
+        let aid = fjage_agent_for_service(gw, service.as_ptr());
+        assert_eq!(CStr::from_ptr(aid).to_str().unwrap(), "shell");
+        fjage_aid_destroy(aid as *mut c_char);
+        assert_eq!(fjage_close(gw), 0);
+    }
+}
+
+#[test]
+fn opening_an_invalid_port_returns_null() {
+    let host = cstr("127.0.0.1");
+    unsafe {
+        assert!(fjage_tcp_open(host.as_ptr(), -1).is_null());
+        assert!(fjage_tcp_open(host.as_ptr(), 65536).is_null());
+        assert!(fjage_ws_open(host.as_ptr(), -1).is_null());
+        assert!(fjage_ws_open(host.as_ptr(), 65536).is_null());
+    }
+}
+
+#[test]
+fn an_invalid_baud_rate_is_rejected() {
+    let dev = cstr("/dev/ttyUSB0");
+    unsafe {
+        // Building the connector does not touch the port, so only the baud rate can fail here
+        assert!(c_api_serial_connector(dev.as_ptr(), 115200, std::ptr::null()).is_ok());
+        assert!(c_api_serial_connector(dev.as_ptr(), -1, std::ptr::null()).is_err());
+        assert!(fjage_rs232_open(dev.as_ptr(), -1, std::ptr::null()).is_null());
+        assert_eq!(fjage_rs232_wakeup(dev.as_ptr(), -1, std::ptr::null()), -1);
+    }
+}
+
+#[test]
+fn opening_an_unreachable_master_returns_null() {
+    let port = MockMaster::start_default().port();
+    // The master has been dropped, so nothing listens on the port anymore
+    let host = cstr("127.0.0.1");
+    unsafe {
+        assert!(fjage_tcp_open(host.as_ptr(), port as i32).is_null());
+    }
+}
+
+#[test]
+fn gets_and_sets_parameters() {
+    let master = MockMaster::start_default();
+    let host = cstr("127.0.0.1");
+    let agent = cstr("gwtestalpha");
+    unsafe {
+        let gw = fjage_tcp_open(host.as_ptr(), master.port() as i32);
+        assert!(!gw.is_null());
+
+        let ro = cstr("roIntParam");
+        assert_eq!(
+            fjage_param_get_int(gw, agent.as_ptr(), ro.as_ptr(), -1, 0),
+            42
+        );
+        let rw = cstr("rwIntParam");
+        assert_eq!(
+            fjage_param_set_int(gw, agent.as_ptr(), rw.as_ptr(), 13, -1),
+            0
+        );
+        assert_eq!(
+            fjage_param_get_int(gw, agent.as_ptr(), rw.as_ptr(), -1, 0),
+            13
+        );
+        let missing = cstr("noSuchParam");
+        assert_eq!(
+            fjage_param_get_int(gw, agent.as_ptr(), missing.as_ptr(), -1, -7),
+            -7
+        );
+
+        let s = cstr("roStringParam");
+        let mut buf = [0 as c_char; 64];
+        let len = fjage_param_get_string(gw, agent.as_ptr(), s.as_ptr(), -1, buf.as_mut_ptr(), 64);
+        assert_eq!(len, "FINAL STRING".len() as i32);
+        assert_eq!(
+            CStr::from_ptr(buf.as_ptr()).to_str().unwrap(),
+            "FINAL STRING"
+        );
+
+        fjage_close(gw);
+    }
+}
+
+#[test]
+fn requests_shell_commands() {
+    let master = MockMaster::start_default();
+    let host = cstr("127.0.0.1");
+    unsafe {
+        let gw = fjage_tcp_open(host.as_ptr(), master.port() as i32);
+        assert!(!gw.is_null());
+
+        let clazz = cstr("org.arl.fjage.shell.ShellExecReq");
+        let msg = fjage_msg_create(clazz.as_ptr() as *mut c_char, 1);
+        let shell = cstr("shell");
+        fjage_msg_set_recipient(msg, shell.as_ptr());
+        let key = cstr("cmd");
+        let cmd = cstr("ps");
+        fjage_msg_add_string(msg, key.as_ptr(), cmd.as_ptr());
+        let key = cstr("ans");
+        fjage_msg_add_bool(msg, key.as_ptr(), true);
+
+        let rsp = fjage_request(gw, msg, 1000) as *mut fjage_msg_t;
+        assert!(!rsp.is_null());
+        // FJAGE_AGREE
+        assert_eq!(fjage_msg_get_performative(rsp), 2);
+        let ans = fjage_msg_get_string(rsp, key.as_ptr());
+        assert_eq!(CStr::from_ptr(ans).to_str().unwrap(), "ps");
+        fjage_msg_destroy(rsp);
+
+        fjage_close(gw);
+    }
+}
@@ -1,6 +1,9 @@
 #include "fjage.h"
 
 // New to fjage-rs
+// WebSocket connector
+fjage_gw_t fjage_ws_open(const char *hostname, int port);
+
 // double param extension
 int fjage_param_set_double(fjage_gw_t gw, fjage_aid_t aid, const char *param, double value, int ndx);
 double fjage_param_get_double(fjage_gw_t gw, fjage_aid_t aid, const char *param, int ndx, double defval);
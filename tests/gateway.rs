@@ -0,0 +1,387 @@
+mod common;
+
+use std::{sync::mpsc, thread, time::Duration};
+
+use common::{mock_master::PARAMETER_TEST_SERVICE, MockAgent, MockMaster};
+use fjage_rs::{
+    api::gateway::{Gateway, GatewayRuntime},
+    core::{
+        aid::AgentID,
+        error::GatewayError,
+        filter::{any, clazz, field, performative, sender, MessageFilter},
+        message::{Message, Performative},
+        param::ParameterManipulation,
+        typed::FjageMessage,
+    },
+    protocol::connector::TcpConnector,
+    remote::{
+        container::{LinkState, ReconnectPolicy},
+        file::GetFileRsp,
+        queue::{OverflowPolicy, QueueLimits},
+    },
+};
+use serde_json::json;
+
+#[test]
+fn lists_agents_and_services() {
+    let master = MockMaster::start_default();
+    let gw = Gateway::new_tcp("127.0.0.1", master.port()).unwrap();
+
+    assert_eq!(
+        gw.agents().unwrap(),
+        vec!["gwtestalpha", "gwtestbeta", "shell"]
+    );
+    assert!(gw.contains_agent(&AgentID::agent("gwtestbeta")).unwrap());
+    assert!(!gw.contains_agent(&AgentID::agent("nobody")).unwrap());
+    assert!(gw
+        .services()
+        .unwrap()
+        .contains(&PARAMETER_TEST_SERVICE.to_string()));
+    assert_eq!(
+        gw.agents_for_service(PARAMETER_TEST_SERVICE).unwrap(),
+        vec!["gwtestalpha", "gwtestbeta"]
+    );
+    assert!(matches!(
+        gw.agent_for_service("org.arl.fjage.test.Services.NONE"),
+        Err(GatewayError::AgentNotFound(_))
+    ));
+}
+
+#[test]
+fn opens_over_websocket() {
+    let master = MockMaster::start_default();
+    let gw = Gateway::new_ws("127.0.0.1", master.ws_port()).unwrap();
+    assert!(gw.is_connected());
+    assert_eq!(
+        gw.agent_for_service(PARAMETER_TEST_SERVICE).unwrap(),
+        AgentID::agent("gwtestalpha")
+    );
+    gw.close();
+}
+
+#[test]
+fn gets_and_sets_parameters() {
+    let master = MockMaster::start_default();
+    let mut gw = Gateway::new_tcp("127.0.0.1", master.port()).unwrap();
+
+    assert_eq!(
+        gw.get_int(&AgentID::agent("gwtestalpha"), "roIntParam", -1),
+        Some(42)
+    );
+    assert_eq!(
+        gw.get_string(&AgentID::agent("gwtestalpha"), "roStringParam", -1),
+        Some("FINAL STRING".to_string())
+    );
+    assert!(gw
+        .set_int(&AgentID::agent("gwtestalpha"), "roIntParam", 7, -1)
+        .is_err());
+
+    assert_eq!(
+        gw.set_long(&AgentID::agent("gwtestalpha"), "rwLongParam", 1234, -1),
+        Ok(1234)
+    );
+    assert_eq!(
+        gw.get_long(&AgentID::agent("gwtestalpha"), "rwLongParam", -1),
+        Some(1234)
+    );
+    // Each agent keeps its own parameters
+    assert_eq!(
+        gw.get_long(&AgentID::agent("gwtestbeta"), "rwLongParam", -1),
+        Some(99)
+    );
+
+    assert_eq!(
+        gw.get_long_array(&AgentID::agent("gwtestalpha"), "rwLongArrayParam", -1),
+        Some(vec![640, 480])
+    );
+    assert!(gw
+        .set_int_array(
+            &AgentID::agent("gwtestalpha"),
+            "rwIntArrayParam",
+            vec![1, 2, 3],
+            -1
+        )
+        .is_ok());
+    assert_eq!(
+        gw.get_int_array(&AgentID::agent("gwtestalpha"), "rwIntArrayParam", -1),
+        Some(vec![1, 2, 3])
+    );
+}
+
+#[test]
+fn request_errors_carry_the_reply() {
+    let master = MockMaster::start(vec![MockAgent::new("grumpy").on_message(|_| {
+        Some(Message::new_generic(
+            "org.arl.fjage.Message",
+            Performative::REFUSE,
+            json!({ "reason": "busy" }),
+        ))
+    })]);
+    let gw = Gateway::new_tcp("127.0.0.1", master.port()).unwrap();
+
+    let msg = Message::new_generic("org.arl.fjage.Message", Performative::REQUEST, json!({}));
+    match gw.request_timeout(&AgentID::agent("grumpy"), msg, Duration::from_secs(2)) {
+        Err(GatewayError::Refused(rsp)) => {
+            assert_eq!(rsp.data.fields.get("reason"), Some(&json!("busy")))
+        }
+        other => panic!("expected a refusal, got {:?}", other),
+    }
+    assert_eq!(master.inbox("grumpy").len(), 1);
+}
+
+#[test]
+fn receives_messages_from_the_master() {
+    let master = MockMaster::start_default();
+    let gw = Gateway::new_tcp("127.0.0.1", master.port()).unwrap();
+
+    let mut ntf = Message::new_generic(
+        "org.arl.fjage.test.TestNtf",
+        Performative::INFORM,
+        json!({ "n": 1 }),
+    );
+    ntf.data.recipient = gw.get_agent_id();
+    master.send(ntf);
+
+    let rsp = gw.recv_timeout(None, None, Duration::from_secs(1)).unwrap();
+    assert_eq!(rsp.clazz, "org.arl.fjage.test.TestNtf");
+    assert!(matches!(
+        gw.recv_timeout(None, None, Duration::from_millis(100)),
+        Err(GatewayError::Timeout)
+    ));
+}
+
+#[test]
+fn filters_return_the_oldest_matching_message() {
+    let master = MockMaster::start_default();
+    let gw = Gateway::new_tcp("127.0.0.1", master.port()).unwrap();
+
+    for (n, from, perf) in [
+        (1, "phy", Performative::INFORM),
+        (2, "mac", Performative::INFORM),
+        (3, "phy", Performative::FAILURE),
+    ] {
+        let mut ntf = Message::new_generic("org.arl.fjage.test.TestNtf", perf, json!({ "n": n }));
+        ntf.data.recipient = gw.get_agent_id();
+        ntf.data.sender = AgentID::agent(from);
+        master.send(ntf);
+    }
+    let n = |msg: Message| msg.data.fields["n"].clone();
+
+    // Messages arrive in order, so once the last one is here the others are queued
+    let last = gw
+        .recv_timeout_filtered(field("n", 3), Duration::from_secs(1))
+        .unwrap();
+    assert_eq!(n(last), 3);
+    let phy = gw
+        .recv_filtered(clazz("org.arl.fjage.test.TestNtf").and(sender(&AgentID::agent("phy"))))
+        .unwrap();
+    assert_eq!(n(phy), 1);
+    let mac = gw
+        .recv_filtered(
+            performative(Performative::FAILURE).or(|msg: &Message| msg.data.sender == "mac"),
+        )
+        .unwrap();
+    assert_eq!(n(mac), 2);
+    assert!(matches!(
+        gw.recv_timeout_filtered(|_: &Message| true, Duration::from_millis(100)),
+        Err(GatewayError::Timeout)
+    ));
+}
+
+#[test]
+fn listeners_are_called_until_removed() {
+    let master = MockMaster::start_default();
+    let gw = Gateway::new_tcp("127.0.0.1", master.port()).unwrap();
+    let (tx, rx) = mpsc::channel();
+    let listener = gw.add_message_listener(clazz("org.arl.fjage.test.TestNtf"), move |msg| {
+        tx.send(msg.data.fields["n"].clone()).unwrap();
+    });
+    let me = gw.get_agent_id();
+    let ntf = |n: i32| {
+        let mut ntf = Message::new_generic(
+            "org.arl.fjage.test.TestNtf",
+            Performative::INFORM,
+            json!({ "n": n }),
+        );
+        ntf.data.recipient = me.clone();
+        return ntf;
+    };
+
+    master.send(ntf(1));
+    assert_eq!(rx.recv_timeout(Duration::from_secs(1)).unwrap(), 1);
+    assert!(gw.remove_message_listener(listener));
+    assert!(!gw.remove_message_listener(listener));
+    master.send(ntf(2));
+    // Wait for the second message, which the listener no longer sees
+    gw.recv_timeout_filtered(field("n", 2), Duration::from_secs(1))
+        .unwrap();
+    assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+}
+
+#[test]
+fn a_slow_listener_drops_what_does_not_fit() {
+    let master = MockMaster::start_default();
+    let gw = Gateway::new_tcp("127.0.0.1", master.port()).unwrap();
+    gw.set_stream_limits(QueueLimits {
+        capacity: 1,
+        overflow: OverflowPolicy::DROP_NEWEST,
+        max_age: None,
+    });
+    let (tx, rx) = mpsc::channel();
+    let (started, starts) = mpsc::channel();
+    let (release, released) = mpsc::channel::<()>();
+    gw.add_message_listener(clazz("org.arl.fjage.test.TestNtf"), move |msg| {
+        // Hold on to each message until told to go on
+        let _ = started.send(());
+        let _ = released.recv();
+        tx.send(msg.data.fields["n"].clone()).unwrap();
+    });
+    let send = |n: i32| {
+        let mut ntf = Message::new_generic(
+            "org.arl.fjage.test.TestNtf",
+            Performative::INFORM,
+            json!({ "n": n }),
+        );
+        ntf.data.recipient = gw.get_agent_id();
+        master.send(ntf);
+    };
+    send(1);
+    starts.recv_timeout(Duration::from_secs(1)).unwrap();
+    send(2);
+    send(3);
+    // One message is in the callback, one waits for it, and the last has no room
+    let mut stats = gw.stream_stats();
+    for _ in 0..100 {
+        if stats.dropped_full > 0 {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+        stats = gw.stream_stats();
+    }
+    assert_eq!((stats.queued, stats.dropped_full), (1, 1));
+    drop(release);
+    assert_eq!(rx.recv_timeout(Duration::from_secs(1)).unwrap(), 1);
+    assert_eq!(rx.recv_timeout(Duration::from_secs(1)).unwrap(), 2);
+    gw.close();
+}
+
+#[test]
+fn registered_clazzes_are_received_typed() {
+    let master = MockMaster::start_default();
+    let gw = Gateway::new_tcp("127.0.0.1", master.port()).unwrap();
+    let mut rsp = GetFileRsp::new("notes.txt");
+    rsp.contents = b"hello".to_vec();
+    rsp.header.recipient = gw.get_agent_id();
+    master.send(rsp.to_msg());
+    let mut ntf = Message::new_generic(
+        "org.arl.fjage.test.TestNtf",
+        Performative::INFORM,
+        json!({}),
+    );
+    ntf.data.recipient = gw.get_agent_id();
+    master.send(ntf);
+
+    let msg = gw
+        .recv_timeout_typed(any(), Duration::from_secs(1))
+        .unwrap();
+    assert_eq!(msg.clazz(), GetFileRsp::CLAZZ);
+    let rsp = msg.downcast::<GetFileRsp>().unwrap();
+    assert_eq!(rsp.filename, "notes.txt");
+    assert_eq!(rsp.contents, b"hello");
+
+    let msg = gw
+        .recv_timeout_typed(any(), Duration::from_secs(1))
+        .unwrap();
+    assert!(!msg.is::<GetFileRsp>());
+    assert_eq!(msg.into_msg().clazz, "org.arl.fjage.test.TestNtf");
+}
+
+#[test]
+fn closes_when_the_master_shuts_down() {
+    let master = MockMaster::start_default();
+    let gw = Gateway::new_tcp("127.0.0.1", master.port()).unwrap();
+    assert!(gw.is_connected());
+
+    master.shutdown_clients();
+    assert!(matches!(
+        gw.recv_timeout(None, None, Duration::from_secs(1)),
+        Err(GatewayError::ConnectionLost)
+    ));
+    assert!(!gw.is_connected());
+}
+
+#[test]
+fn close_flushes_and_disconnects() {
+    let master = MockMaster::start_default();
+    // On the shared runtime, only close can end the connection
+    let gw = Gateway::new_in(
+        TcpConnector::new("127.0.0.1", master.port()),
+        ReconnectPolicy::default(),
+        GatewayRuntime::Shared,
+    )
+    .unwrap();
+    let clone = gw.clone();
+    let ntf = Message::new_generic(
+        "org.arl.fjage.test.TestNtf",
+        Performative::INFORM,
+        json!({}),
+    );
+    gw.send(&AgentID::agent("gwtestalpha"), ntf).unwrap();
+    gw.close();
+
+    // The master reads everything sent before the connection closed
+    assert!(master.wait_for_client_count(0));
+    assert_eq!(master.inbox("gwtestalpha").len(), 1);
+    assert_eq!(clone.link_state(), LinkState::CLOSED);
+    assert!(matches!(clone.agents(), Err(GatewayError::ConnectionLost)));
+    clone.close();
+}
+
+#[test]
+fn clones_share_the_connection_across_threads() {
+    fn shareable<T: Send + Sync + Clone>() {}
+    shareable::<Gateway>();
+
+    let master = MockMaster::start_default();
+    let gw = Gateway::new_tcp("127.0.0.1", master.port()).unwrap();
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            let gw = gw.clone();
+            thread::spawn(move || gw.agents().unwrap().len())
+        })
+        .collect();
+    for thread in threads {
+        assert_eq!(thread.join().unwrap(), 3);
+    }
+    assert_eq!(master.client_count(), 1);
+}
+
+#[tokio::test]
+async fn usable_inside_a_current_thread_runtime() {
+    let master = MockMaster::start_default();
+    let gw = Gateway::new_in(
+        TcpConnector::new("127.0.0.1", master.port()),
+        ReconnectPolicy::default(),
+        GatewayRuntime::Shared,
+    )
+    .unwrap();
+    assert!(gw.contains_agent(&AgentID::agent("shell")).unwrap());
+    // A dedicated runtime may be dropped here too
+    let own = Gateway::new_tcp("127.0.0.1", master.port()).unwrap();
+    assert!(own.contains_agent(&AgentID::agent("shell")).unwrap());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn attaches_to_the_calling_runtime() {
+    let master = MockMaster::start_default();
+    let gw = Gateway::new_in(
+        TcpConnector::new("127.0.0.1", master.port()),
+        ReconnectPolicy::default(),
+        GatewayRuntime::Handle(tokio::runtime::Handle::current()),
+    )
+    .unwrap();
+    assert_eq!(gw.agents().unwrap().len(), 3);
+    // As in an async main using the plain constructors
+    let own = Gateway::new_tcp("127.0.0.1", master.port()).unwrap();
+    assert_eq!(own.agents().unwrap().len(), 3);
+}
@@ -0,0 +1,147 @@
+use std::time::Duration;
+
+use fjage_rs::{
+    core::{
+        aid::AgentID,
+        message::{Message, Performative},
+        signal::{BasebandSignal, Complex32},
+    },
+    protocol::base64::JavaType,
+};
+use serde_json::json;
+
+#[test]
+fn java_arrays_are_decoded_at_any_depth_and_encoded_from_hints() {
+    let mut msg = Message::new_generic(
+        "org.arl.fjage.test.TestNtf",
+        Performative::INFORM,
+        json!({
+            "shorts": { "clazz": "[S", "data": "AQD//w==" },
+            "flags": { "clazz": "[Z", "data": "AQA=" },
+            "name": { "clazz": "[C", "data": "aABpAA==" },
+            "nested": {
+                "frames": [{ "clazz": "[B", "data": "AQID" }],
+                "names": { "clazz": "[Ljava.lang.String;", "data": ["a", "b"] },
+            },
+            "broken": { "clazz": "[I", "data": "not base64!" },
+        }),
+    );
+    msg.decode_java_classes();
+    assert_eq!(msg.data.fields["shorts"], json!([1, -1]));
+    assert_eq!(msg.data.fields["flags"], json!([true, false]));
+    assert_eq!(msg.data.fields["name"], "hi");
+    assert_eq!(msg.data.fields["nested"]["frames"], json!([[1, 2, 3]]));
+    assert_eq!(msg.data.fields["nested"]["names"], json!(["a", "b"]));
+    assert!(msg.data.fields["broken"].is_null());
+
+    msg.set_java_type("shorts", JavaType::SHORT_ARRAY);
+    msg.set_java_type("flags", JavaType::BOOLEAN_ARRAY);
+    msg.set_java_type("name", JavaType::CHAR_ARRAY);
+    msg.set_java_type("missing", JavaType::INT_ARRAY);
+    msg.encode_java_classes();
+    assert_eq!(
+        msg.data.fields["shorts"],
+        json!({ "clazz": "[S", "data": "AQD//w==" })
+    );
+    assert_eq!(msg.data.fields["flags"]["clazz"], "[Z");
+    assert_eq!(msg.data.fields["name"]["data"], "aABpAA==");
+    // Hints are local, they are not sent
+    let json = serde_json::to_value(&msg).unwrap();
+    assert!(json.get("java_types").is_none());
+}
+
+#[test]
+fn baseband_signals_keep_their_samples_and_metadata() {
+    let signal = BasebandSignal::new(
+        vec![Complex32::new(1.0, -1.0), Complex32::new(0.5, 0.25)],
+        Some(12000.0),
+        24000.0,
+    );
+    let mut req = Message::new_generic(
+        "org.arl.unet.bb.TxBasebandSignalReq",
+        Performative::REQUEST,
+        json!({}),
+    );
+    req.set_signal(&signal);
+    assert_eq!(req.data.fields["signal"]["clazz"], "[F");
+    assert_eq!(req.data.fields["signal__isComplex"], true);
+    assert_eq!(req.get_signal().unwrap(), signal);
+
+    // As received, after decode_java_classes
+    let ntf = Message::new_generic(
+        "org.arl.unet.bb.RxBasebandSignalNtf",
+        Performative::INFORM,
+        json!({ "signal": [1.0, 2.0, 3.0, 4.0], "fs": 4.0, "fc": 12000.0 }),
+    );
+    let signal = ntf.get_signal().unwrap();
+    assert_eq!(signal.samples[1], Complex32::new(3.0, 4.0));
+    assert_eq!(signal.duration(), Some(Duration::from_millis(500)));
+
+    let passband = BasebandSignal::passband(&[0.1, 0.2, 0.3], None);
+    req.set_signal(&passband);
+    assert_eq!(req.data.fields["signal__isComplex"], false);
+    assert_eq!(req.get_signal().unwrap(), passband);
+
+    // Complex samples stay complex even without a carrier
+    let at_dc = BasebandSignal::new(vec![Complex32::new(1.0, -1.0)], Some(1000.0), 0.0);
+    assert!(at_dc.is_complex());
+    req.set_signal(&at_dc);
+    assert_eq!(req.data.fields["signal__isComplex"], true);
+    let received = req.get_signal().unwrap();
+    assert_eq!(received.samples, vec![Complex32::new(1.0, -1.0)]);
+    assert_eq!(received, at_dc);
+}
+
+#[test]
+fn typed_accessors_read_back_what_the_builder_set() {
+    let msg = Message::builder("org.arl.unet.DatagramReq")
+        .perf(Performative::INFORM)
+        .recipient(&AgentID::agent("uwlink"))
+        .in_reply_to("1234")
+        .field("to", 2)
+        .field("ttl", 1.5)
+        .field("names", vec!["a", "b"])
+        .bytes("data", b"hi")
+        .build();
+    assert_eq!(msg.data.perf, Performative::INFORM);
+    assert_eq!(msg.data.recipient, "uwlink");
+    assert_eq!(msg.data.inReplyTo.as_deref(), Some("1234"));
+    assert_eq!(msg.get_i32("to", 0), 2);
+    assert_eq!(msg.get_f32("ttl", 0.0), 1.5);
+    assert_eq!(msg.get_i32("missing", 7), 7);
+    assert_eq!(msg.get_string("to", "none"), "none");
+    assert_eq!(msg.get_bytes("data").unwrap(), b"hi");
+    assert_eq!(msg.get_string_array("names").unwrap(), vec!["a", "b"]);
+    assert!(msg.get_bytes("names").is_none());
+
+    // Array getters see through the encoding applied on send
+    let mut sent = msg.clone();
+    sent.set_f32_array("signal", &[0.5, -2.0]);
+    sent.encode_java_classes();
+    assert_eq!(sent.data.fields["data"]["clazz"], "[B");
+    assert_eq!(sent.get_bytes("data").unwrap(), b"hi");
+    assert_eq!(sent.get_f32_array("signal").unwrap(), vec![0.5, -2.0]);
+}
+
+#[test]
+fn replies_go_back_to_the_sender() {
+    let mut req = Message::builder("org.arl.unet.DatagramReq").build();
+    req.data.sender = AgentID::agent("client");
+    req.data.recipient = AgentID::agent("uwlink");
+
+    let rsp = req.refuse("busy");
+    assert_eq!(rsp.data.perf, Performative::REFUSE);
+    assert_eq!(rsp.data.inReplyTo, Some(req.data.msgID.clone()));
+    assert_eq!(rsp.data.recipient, "client");
+    assert_eq!(rsp.data.sender, "uwlink");
+    assert_eq!(rsp.get_string("reason", ""), "busy");
+    assert!(req.agree().data.perf.is_success());
+    assert!(!req.not_understood().data.perf.is_success());
+
+    assert_eq!(Performative::QUERY_IF.to_string(), "QUERY_IF");
+    assert_eq!("not_understood".parse(), Ok(Performative::NOT_UNDERSTOOD));
+    assert!("MAYBE".parse::<Performative>().is_err());
+    assert_eq!(i32::from(Performative::CANCEL), 12);
+    assert_eq!(Performative::try_from(2), Ok(Performative::AGREE));
+    assert!(Performative::try_from(13).is_err());
+}
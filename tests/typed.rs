@@ -0,0 +1,87 @@
+use fjage_rs::core::{
+    aid::AgentID,
+    error::DecodeError,
+    message::{Message, MessageHeader, Performative},
+    registry::{AnyMessage, MessageRegistry},
+    typed::FjageMessage,
+};
+use serde_json::json;
+
+#[derive(FjageMessage, Debug, Clone)]
+#[fjage(clazz = "org.arl.unet.phy.TxFrameReq")]
+struct TxFrameReq {
+    #[fjage(header)]
+    header: MessageHeader,
+    to: i32,
+    data: Vec<u8>,
+    signal: Option<Vec<f32>>,
+    protocol: Option<i32>,
+}
+
+#[test]
+fn derived_messages_round_trip() {
+    let mut req = TxFrameReq {
+        header: MessageHeader::new(Performative::REQUEST),
+        to: 2,
+        data: vec![1, 2, 3],
+        signal: Some(vec![0.5, -1.0]),
+        protocol: None,
+    };
+    req.header.recipient = AgentID::agent("phy");
+
+    let msg = req.to_msg();
+    assert_eq!(msg.clazz, TxFrameReq::CLAZZ);
+    assert_eq!(msg.data.msgID, req.header.msgID);
+    assert_eq!(msg.data.recipient, "phy");
+    assert_eq!(
+        msg.data.fields["data"],
+        json!({ "clazz": "[B", "data": "AQID" })
+    );
+    assert_eq!(msg.data.fields["signal"]["clazz"], "[F");
+    assert!(!msg.data.fields.contains_key("protocol"));
+
+    let decoded = TxFrameReq::from_msg(msg.clone()).unwrap();
+    assert_eq!(decoded.to, 2);
+    assert_eq!(decoded.data, req.data);
+    assert_eq!(decoded.signal, req.signal);
+    assert_eq!(decoded.header.msgID, req.header.msgID);
+
+    // Arrays already decoded by decode_java_classes are accepted as well
+    let mut msg = msg;
+    msg.decode_java_classes();
+    assert_eq!(TxFrameReq::from_msg(msg).unwrap().data, vec![1, 2, 3]);
+
+    let msg = Message::new_generic(TxFrameReq::CLAZZ, Performative::REQUEST, json!({ "to": 2 }));
+    assert!(matches!(
+        TxFrameReq::from_msg(msg),
+        Err(DecodeError::MissingField(field)) if field == "data"
+    ));
+}
+
+#[test]
+fn registry_decodes_registered_clazzes_only() {
+    let req = TxFrameReq {
+        header: MessageHeader::new(Performative::REQUEST),
+        to: 3,
+        data: vec![],
+        signal: None,
+        protocol: Some(4),
+    };
+    let msg = req.to_msg();
+    assert_eq!(msg.decode::<TxFrameReq>().unwrap().protocol, Some(4));
+
+    let mut registry = MessageRegistry::default();
+    assert!(matches!(
+        registry.decode(msg.clone()),
+        AnyMessage::Generic(_)
+    ));
+    registry.register::<TxFrameReq>();
+    let typed = registry.decode(msg);
+    assert_eq!(typed.downcast_ref::<TxFrameReq>().unwrap().to, 3);
+
+    let generic = Message::new();
+    assert!(matches!(
+        generic.decode::<TxFrameReq>(),
+        Err(DecodeError::WrongClazz { .. })
+    ));
+}